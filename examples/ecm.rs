@@ -14,6 +14,7 @@ fn main() {
             b1: 100,
             b2: 10000,
             verbose: true,
+            ..Default::default()
         },
     );
     eprintln!("{:?}", ans);
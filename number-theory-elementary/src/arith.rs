@@ -0,0 +1,329 @@
+use crate::factor::factorize_u64;
+use crate::word::gcd;
+
+/// Computes Euler's totient function phi(n). n must be >= 1.
+pub fn euler_phi_u64(n: u64) -> u64 {
+    factorize_u64(n)
+        .into_iter()
+        .map(|(p, e)| (p - 1) * p.pow(e as u32 - 1))
+        .product()
+}
+
+/// Computes the Carmichael function lambda(n), the exponent of (Z/nZ)^*. n must be >= 1.
+pub fn carmichael_lambda_u64(n: u64) -> u64 {
+    factorize_u64(n)
+        .into_iter()
+        .map(|(p, e)| carmichael_lambda_prime_power(p, e))
+        .fold(1, |acc, l| acc / gcd(acc, l) * l)
+}
+
+/// Computes sigma_k(n), the sum of the k-th powers of the divisors of n,
+/// or None if it does not fit in u128. n must be >= 1.
+pub fn divisor_sigma_u64(n: u64, k: u32) -> Option<u128> {
+    let mut result: u128 = 1;
+    for (p, e) in factorize_u64(n) {
+        let pk = (p as u128).checked_pow(k)?;
+        // 1 + p^k + ... + p^{ke}
+        let mut sum: u128 = 1;
+        for _ in 0..e {
+            sum = sum.checked_mul(pk)?.checked_add(1)?;
+        }
+        result = result.checked_mul(sum)?;
+    }
+    Some(result)
+}
+
+/// Computes tau(n), the number of divisors of n. n must be >= 1.
+pub fn divisor_count_u64(n: u64) -> u64 {
+    factorize_u64(n).into_iter().map(|(_, e)| e + 1).product()
+}
+
+/// Computes the Möbius function mu(n). n must be >= 1.
+pub fn moebius_u64(n: u64) -> i32 {
+    let factors = factorize_u64(n);
+    if factors.iter().any(|&(_, e)| e >= 2) {
+        return 0;
+    }
+    if factors.len() % 2 == 0 {
+        1
+    } else {
+        -1
+    }
+}
+
+/// Computes omega(n), the number of distinct prime factors of n. n must be >= 1.
+pub fn omega_u64(n: u64) -> u64 {
+    factorize_u64(n).len() as u64
+}
+
+/// Computes Omega(n), the number of prime factors of n counted with multiplicity. n must be >= 1.
+pub fn big_omega_u64(n: u64) -> u64 {
+    factorize_u64(n).into_iter().map(|(_, e)| e).sum()
+}
+
+/// Returns the divisors of n in the increasing order. n must be >= 1.
+pub fn divisors_u64(n: u64) -> Vec<u64> {
+    let mut result = vec![1];
+    for (p, e) in factorize_u64(n) {
+        let len = result.len();
+        let mut pe = 1;
+        for _ in 0..e {
+            pe *= p;
+            for i in 0..len {
+                result.push(result[i] * pe);
+            }
+        }
+    }
+    result.sort_unstable();
+    result
+}
+
+fn carmichael_lambda_prime_power(p: u64, e: u64) -> u64 {
+    if p == 2 && e >= 3 {
+        1 << (e - 2)
+    } else {
+        (p - 1) * p.pow(e as u32 - 1)
+    }
+}
+
+/// A linear sieve of [0, n], from which tables of arithmetic functions up to n are computed in O(n) time.
+pub struct LinearSieve {
+    /// The primes <= n in the increasing order.
+    primes: Vec<u32>,
+    /// lpf[m] is the least prime factor of m. lpf[0] = lpf[1] = 0.
+    lpf: Vec<u32>,
+    /// rest[m] = m / p^e, where p = lpf[m] and p^e is the largest power of p dividing m.
+    rest: Vec<u32>,
+}
+
+impl LinearSieve {
+    /// Sieves [0, n]. n must be < 2^32.
+    pub fn new(n: usize) -> Self {
+        assert!(n < 1 << 32, "n = {} is too large", n);
+        let mut primes = vec![];
+        let mut lpf = vec![0; n + 1];
+        let mut rest = vec![0; n + 1];
+        if n >= 1 {
+            rest[1] = 1;
+        }
+        for i in 2..=n {
+            if lpf[i] == 0 {
+                lpf[i] = i as u32;
+                rest[i] = 1;
+                primes.push(i as u32);
+            }
+            // Every composite m is crossed off once, as lpf(m) (m / lpf(m)).
+            for &p in &primes {
+                let m = i * p as usize;
+                if p > lpf[i] || m > n {
+                    break;
+                }
+                lpf[m] = p;
+                rest[m] = if p == lpf[i] { rest[i] } else { i as u32 };
+            }
+        }
+        LinearSieve { primes, lpf, rest }
+    }
+
+    /// Returns the bound n of the sieve.
+    pub fn bound(&self) -> usize {
+        self.lpf.len() - 1
+    }
+
+    /// Returns the primes <= n in the increasing order.
+    pub fn primes(&self) -> &[u32] {
+        &self.primes
+    }
+
+    /// Returns the least prime factor of m, or None if m <= 1. m must be <= n.
+    pub fn least_prime_factor(&self, m: usize) -> Option<u64> {
+        match self.lpf[m] {
+            0 => None,
+            p => Some(p as u64),
+        }
+    }
+
+    /// Factorizes 1 <= m <= n in O(log m) time.
+    ///
+    /// Returns the prime factors with their multiplicity in the increasing order.
+    pub fn factorize(&self, mut m: usize) -> Vec<(u64, u64)> {
+        assert!(m >= 1);
+        let mut result = vec![];
+        while m > 1 {
+            let (p, e) = self.lpf_power(m);
+            result.push((p as u64, e));
+            m = self.rest[m] as usize;
+        }
+        result
+    }
+
+    /// Returns (p, e), where p = lpf(m) and p^e is the largest power of p dividing m >= 2.
+    fn lpf_power(&self, m: usize) -> (usize, u64) {
+        let p = self.lpf[m] as usize;
+        let mut q = m / self.rest[m] as usize;
+        let mut e = 0;
+        while q > 1 {
+            q /= p;
+            e += 1;
+        }
+        (p, e)
+    }
+
+    /// Returns the table of the multiplicative function f up to n, where f(p^e) = f_prime_power(p, e).
+    /// The entry for 0 is T::default().
+    ///
+    /// The entries are multiplied with `*` of T, which wraps around on overflow of primitive integers in release builds,
+    /// so T must hold every f(m) for m <= n.
+    pub fn multiplicative_table<T: Clone + Default + std::ops::Mul<Output = T>>(
+        &self,
+        one: T,
+        f_prime_power: impl Fn(u64, u64) -> T,
+    ) -> Vec<T> {
+        let n = self.bound();
+        let mut table = vec![T::default(); n + 1];
+        if n >= 1 {
+            table[1] = one;
+        }
+        for m in 2..=n {
+            let rest = self.rest[m] as usize;
+            table[m] = if rest == 1 {
+                let (p, e) = self.lpf_power(m);
+                f_prime_power(p as u64, e)
+            } else {
+                table[m / rest].clone() * table[rest].clone()
+            };
+        }
+        table
+    }
+
+    /// Returns the table of Euler's totient function phi up to n.
+    pub fn euler_phi_table(&self) -> Vec<u64> {
+        self.multiplicative_table(1, |p, e| (p - 1) * p.pow(e as u32 - 1))
+    }
+
+    /// Returns the table of the Carmichael function lambda up to n. The entry for 0 is 0.
+    pub fn carmichael_lambda_table(&self) -> Vec<u64> {
+        let n = self.bound();
+        let mut table = vec![0; n + 1];
+        if n >= 1 {
+            table[1] = 1;
+        }
+        for m in 2..=n {
+            let rest = self.rest[m] as usize;
+            let (p, e) = self.lpf_power(m);
+            let l = carmichael_lambda_prime_power(p as u64, e);
+            table[m] = l / gcd(l, table[rest]) * table[rest];
+        }
+        table
+    }
+
+    /// Returns the table of sigma_k up to n, or None if an entry does not fit in u128.
+    pub fn divisor_sigma_table(&self, k: u32) -> Option<Vec<u128>> {
+        let n = self.bound();
+        let mut table = vec![0; n + 1];
+        if n >= 1 {
+            table[1] = 1;
+        }
+        for m in 2..=n {
+            let rest = self.rest[m] as usize;
+            let (p, e) = self.lpf_power(m);
+            let pk = (p as u128).checked_pow(k)?;
+            // 1 + p^k + ... + p^{ke}
+            let mut sum: u128 = 1;
+            for _ in 0..e {
+                sum = sum.checked_mul(pk)?.checked_add(1)?;
+            }
+            table[m] = sum.checked_mul(table[rest])?;
+        }
+        Some(table)
+    }
+
+    /// Returns the table of tau, the number of divisors, up to n.
+    pub fn divisor_count_table(&self) -> Vec<u64> {
+        self.multiplicative_table(1, |_, e| e + 1)
+    }
+
+    /// Returns the table of the Möbius function mu up to n.
+    pub fn moebius_table(&self) -> Vec<i8> {
+        self.multiplicative_table(1, |_, e| if e == 1 { -1 } else { 0 })
+    }
+
+    /// Returns the table of omega, the number of distinct prime factors, up to n.
+    pub fn omega_table(&self) -> Vec<u8> {
+        let n = self.bound();
+        let mut table = vec![0; n + 1];
+        for m in 2..=n {
+            table[m] = table[self.rest[m] as usize] + 1;
+        }
+        table
+    }
+
+    /// Returns the table of Omega, the number of prime factors counted with multiplicity, up to n.
+    pub fn big_omega_table(&self) -> Vec<u8> {
+        let n = self.bound();
+        let mut table = vec![0; n + 1];
+        for m in 2..=n {
+            table[m] = table[m / self.lpf[m] as usize] + 1;
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic_functions_work() {
+        assert_eq!(euler_phi_u64(1), 1);
+        assert_eq!(euler_phi_u64(36), 12);
+        assert_eq!(euler_phi_u64(1_000_000_007), 1_000_000_006);
+        assert_eq!(carmichael_lambda_u64(8), 2);
+        assert_eq!(carmichael_lambda_u64(5040), 12);
+        assert_eq!(carmichael_lambda_u64(561), 80);
+        assert_eq!(divisor_sigma_u64(12, 0), Some(6));
+        assert_eq!(divisor_sigma_u64(12, 1), Some(28));
+        assert_eq!(divisor_sigma_u64(12, 2), Some(210));
+        assert_eq!(divisor_sigma_u64(u64::MAX, 3), None);
+        assert_eq!(divisor_count_u64(720720), 240);
+        assert_eq!(moebius_u64(1), 1);
+        assert_eq!(moebius_u64(30), -1);
+        assert_eq!(moebius_u64(18), 0);
+        assert_eq!(omega_u64(720720), 6);
+        assert_eq!(big_omega_u64(720720), 10);
+        assert_eq!(divisors_u64(1), vec![1]);
+        assert_eq!(divisors_u64(12), vec![1, 2, 3, 4, 6, 12]);
+    }
+
+    #[test]
+    fn linear_sieve_agrees_with_factorization() {
+        let n = 3000;
+        let sieve = LinearSieve::new(n);
+        assert_eq!(sieve.bound(), n);
+        assert_eq!(sieve.primes().len(), 430);
+        assert_eq!(sieve.least_prime_factor(1), None);
+        assert_eq!(sieve.least_prime_factor(91), Some(7));
+        let phi = sieve.euler_phi_table();
+        let lambda = sieve.carmichael_lambda_table();
+        let sigma2 = sieve.divisor_sigma_table(2).unwrap();
+        let tau = sieve.divisor_count_table();
+        let mu = sieve.moebius_table();
+        let omega = sieve.omega_table();
+        let big_omega = sieve.big_omega_table();
+        for m in 1..=n {
+            let m64 = m as u64;
+            assert_eq!(sieve.factorize(m), factorize_u64(m64), "m = {}", m);
+            assert_eq!(phi[m], euler_phi_u64(m64), "m = {}", m);
+            assert_eq!(lambda[m], carmichael_lambda_u64(m64), "m = {}", m);
+            assert_eq!(Some(sigma2[m]), divisor_sigma_u64(m64, 2), "m = {}", m);
+            assert_eq!(tau[m], divisors_u64(m64).len() as u64, "m = {}", m);
+            assert_eq!(mu[m] as i32, moebius_u64(m64), "m = {}", m);
+            assert_eq!(omega[m] as u64, omega_u64(m64), "m = {}", m);
+            assert_eq!(big_omega[m] as u64, big_omega_u64(m64), "m = {}", m);
+        }
+        // sigma_13(m) < 2^128 for m < 921, and sigma_13(921) > 2^128.
+        let sigma13 = LinearSieve::new(920).divisor_sigma_table(13).unwrap();
+        assert_eq!(Some(sigma13[920]), divisor_sigma_u64(920, 13));
+        assert_eq!(LinearSieve::new(921).divisor_sigma_table(13), None);
+    }
+}
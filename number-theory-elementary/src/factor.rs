@@ -0,0 +1,609 @@
+use crate::montgomery::Montgomery;
+use crate::primality::{is_prime_u128, is_prime_u64};
+use crate::primes::primes;
+use crate::word::{exact_sqrt, gcd, isqrt, Word};
+
+/// The number of iterations after which gcds are taken in Brent's variant.
+const BATCH: u64 = 128;
+
+/// Hart's one line factoring is tried on n below this bound.
+const HART_BOUND: u64 = 1 << 42;
+
+/// The number of iterations of Hart's one line factoring.
+const HART_ITERATIONS: u64 = 1 << 16;
+
+/// SQUFOF is tried on n below this bound.
+const SQUFOF_BOUND: u64 = 1 << 62;
+
+/// The number of iterations of Pollard's rho method tried on u128 before ECM.
+const RHO_ITERATIONS_U128: u64 = 1 << 16;
+
+/// (B1, the number of curves) for ECM on u128, suited to factors of 15 and 20 digits.
+/// After them, curves with the last B1 are tried until a factor is found. B2 is 100 B1.
+const ECM_SCHEDULE_U128: [(u64, u64); 2] = [(2000, 25), (11000, 90)];
+
+/// Finds a nontrivial factor of n with Pollard's rho method on machine words.
+/// See `rho::pollard_brent` in the main crate for the algorithm.
+/// It gives up and returns None after max_iter evaluations of x -> x^2 + c in total.
+/// n should be an odd composite number.
+pub fn pollard_brent_u64(n: u64, max_iter: u64) -> Option<u64> {
+    pollard_brent_word(n, max_iter)
+}
+
+/// The u128 version of `pollard_brent_u64`.
+pub fn pollard_brent_u128(n: u128, max_iter: u64) -> Option<u128> {
+    pollard_brent_word(n, max_iter)
+}
+
+fn pollard_brent_word<T: Word>(n: T, max_iter: u64) -> Option<T> {
+    if n & T::ONE == T::ZERO {
+        return Some(T::from_u64(2));
+    }
+    let mont = Montgomery::new(n);
+    let mut remaining = max_iter;
+    let mut c = mont.one();
+    while remaining > 0 {
+        if let Some(fac) = pollard_brent_oneshot(&mont, c, &mut remaining) {
+            return Some(fac);
+        }
+        c = mont.add(c, mont.one());
+    }
+    None
+}
+
+fn pollard_brent_oneshot<T: Word>(mont: &Montgomery<T>, c: T, remaining: &mut u64) -> Option<T> {
+    let n = mont.modulus();
+    let f = |x: T| mont.add(mont.mul(x, x), c);
+    let mut y = mont.to_mont(T::from_u64(2));
+    let mut ys = y;
+    let mut q = mont.one();
+    let mut g = T::ONE;
+    let mut r = 1u64;
+    let x = loop {
+        let x = y;
+        for _ in 0..r {
+            y = f(y);
+        }
+        let mut k = 0;
+        while k < r && g == T::ONE {
+            ys = y;
+            let steps = BATCH.min(r - k);
+            for _ in 0..steps {
+                y = f(y);
+                q = mont.mul(q, mont.sub(x, y));
+            }
+            g = gcd(q, n);
+            k += steps;
+        }
+        let used = 2 * r;
+        if g != T::ONE {
+            *remaining = remaining.saturating_sub(used);
+            break x;
+        }
+        if *remaining <= used {
+            *remaining = 0;
+            return None;
+        }
+        *remaining -= used;
+        r *= 2;
+    };
+    if g == n {
+        // The batched product hit 0 mod n. Retry one step at a time.
+        loop {
+            ys = f(ys);
+            g = gcd(mont.sub(x, ys), n);
+            if g != T::ONE {
+                break;
+            }
+        }
+    }
+    if g == n {
+        None
+    } else {
+        Some(g)
+    }
+}
+
+/// Finds a nontrivial factor of n with Hart's one line factoring algorithm,
+/// trying the multipliers 1, ..., max_iter.
+///
+/// It finds a factor quickly if n has no factors below n^{1/3}.
+///
+/// Hart, William B. "A one line factoring algorithm." Journal of the Australian Mathematical Society 92.1 (2012): 61-69.
+pub fn hart_olf(n: u64, max_iter: u64) -> Option<u64> {
+    let n = n as u128;
+    if let Some(r) = exact_sqrt(n) {
+        return (r > 1).then_some(r as u64);
+    }
+    for i in 1..=max_iter as u128 {
+        let ni = n * i;
+        let mut s = isqrt(ni);
+        if s * s != ni {
+            s += 1;
+        }
+        let m = s * s % n;
+        if let Some(t) = exact_sqrt(m) {
+            let g = gcd(s - t, n);
+            if g != 1 && g != n {
+                return Some(g as u64);
+            }
+        }
+    }
+    None
+}
+
+/// Multipliers for SQUFOF.
+const SQUFOF_MULTIPLIERS: [u64; 16] = [
+    1,
+    3,
+    5,
+    7,
+    11,
+    3 * 5,
+    3 * 7,
+    3 * 11,
+    5 * 7,
+    5 * 11,
+    7 * 11,
+    3 * 5 * 7,
+    3 * 5 * 11,
+    3 * 7 * 11,
+    5 * 7 * 11,
+    3 * 5 * 7 * 11,
+];
+
+/// Finds a nontrivial factor of n with Shanks' square forms factorization.
+///
+/// This is Algorithm 8.7.2 in \[Cohen\], with the multipliers in `SQUFOF_MULTIPLIERS`.
+/// n should be an odd composite number below 2^62.
+///
+/// \[Cohen\]: Cohen, Henri. A course in computational algebraic number theory. Vol. 138. Springer Science & Business Media, 2013.
+pub fn squfof(n: u64) -> Option<u64> {
+    if n & 1 == 0 {
+        return Some(2);
+    }
+    if let Some(r) = exact_sqrt(n) {
+        return (r > 1).then_some(r);
+    }
+    SQUFOF_MULTIPLIERS
+        .iter()
+        .find_map(|&k| squfof_multiplier(n, k))
+}
+
+fn squfof_multiplier(n: u64, k: u64) -> Option<u64> {
+    let kn = n as i128 * k as i128;
+    let p0 = isqrt(kn as u128) as i128;
+    let mut q_prev = 1;
+    let mut p = p0;
+    let mut q = kn - p0 * p0;
+    if q == 0 {
+        return None;
+    }
+    let bound = 6 * isqrt(2 * p0 as u128) as i128;
+    // Forward cycle until Q is a square at an even step.
+    let mut r = None;
+    for i in 2..bound {
+        let b = (p0 + p) / q;
+        let p_next = b * q - p;
+        let q_next = q_prev + b * (p - p_next);
+        q_prev = q;
+        q = q_next;
+        p = p_next;
+        if i % 2 == 0 {
+            if let Some(s) = exact_sqrt(q as u128) {
+                r = Some(s as i128);
+                break;
+            }
+        }
+    }
+    let r = r?;
+    // Reverse cycle from the square root of the form until P repeats.
+    let b = (p0 - p) / r;
+    p += b * r;
+    q_prev = r;
+    q = (kn - p * p) / q_prev;
+    for _ in 0..bound {
+        let b = (p0 + p) / q;
+        let p_next = b * q - p;
+        let q_next = q_prev + b * (p - p_next);
+        q_prev = q;
+        q = q_next;
+        if p_next == p {
+            break;
+        }
+        p = p_next;
+    }
+    let g = gcd(n, p as u64);
+    (g != 1 && g != n).then_some(g)
+}
+
+/// Finds a nontrivial factor of an odd composite n that is not a perfect square.
+fn find_factor_u64(n: u64) -> u64 {
+    if n < HART_BOUND {
+        if let Some(fac) = hart_olf(n, HART_ITERATIONS) {
+            return fac;
+        }
+    }
+    if n < SQUFOF_BOUND {
+        if let Some(fac) = squfof(n) {
+            return fac;
+        }
+    }
+    pollard_brent_u64(n, u64::MAX).unwrap()
+}
+
+/// Finds a nontrivial factor of n with ECM on machine words, with the curve of Suyama's parametrization sigma.
+/// See `ecm::ecm_curve` in the main crate for the algorithm, which this follows with the same stage 2.
+/// n should be an odd composite number. Returns None if the curve fails to find a factor.
+pub fn ecm_u128(n: u128, sigma: u64, b1: u64, b2: u64) -> Option<u128> {
+    let mont = Montgomery::new(n);
+    let (curve, mut pt) = match WordCurve::suyama(&mont, sigma) {
+        Ok(result) => result,
+        Err(g) => return (g != n).then_some(g),
+    };
+    let ps = primes(b2.max(b1) as usize + 1);
+    for &p in &ps {
+        let p = p as u64;
+        if p > b1 {
+            break;
+        }
+        let mut q = p;
+        while q <= b1 / p {
+            q *= p;
+        }
+        pt = curve.mul(pt, q);
+    }
+    let g = gcd(mont.from_mont(pt.1), n);
+    if g != 1 {
+        return (g != n).then_some(g);
+    }
+    let g = gcd(mont.from_mont(curve.stage2(pt, &ps, b1)), n);
+    (g != 1 && g != n).then_some(g)
+}
+
+/// A point on a Montgomery curve in XZ coordinates, in Montgomery form.
+type WordPoint = (u128, u128);
+
+/// A Montgomery curve whose (A + 2) / 4 is kept as a fraction, so that no inversion modulo n is needed.
+struct WordCurve<'a> {
+    mont: &'a Montgomery<u128>,
+    a24_num: u128,
+    a24_den: u128,
+}
+
+impl<'a> WordCurve<'a> {
+    /// u = sigma^2 - 5, v = 4 sigma, x = u^3 / v^3, (A + 2) / 4 = (v - u)^3 (3u + v) / (16 u^3 v).
+    /// Returns Err(g) if g = gcd(16 u^3 v, n) > 1.
+    fn suyama(mont: &'a Montgomery<u128>, sigma: u64) -> Result<(Self, WordPoint), u128> {
+        let m = mont;
+        let n = m.modulus();
+        let int = |x: u64| m.to_mont(x as u128 % n);
+        let sigma = int(sigma);
+        let u = m.sub(m.mul(sigma, sigma), int(5));
+        let v = m.mul(int(4), sigma);
+        let u3 = m.mul(m.mul(u, u), u);
+        let vmu = m.sub(v, u);
+        let num = m.mul(m.mul(m.mul(vmu, vmu), vmu), m.add(m.mul(int(3), u), v));
+        let den = m.mul(m.mul(u3, v), int(16));
+        let g = gcd(m.from_mont(den), n);
+        if g != 1 {
+            return Err(g);
+        }
+        let curve = WordCurve {
+            mont,
+            a24_num: num,
+            a24_den: den,
+        };
+        Ok((curve, (u3, m.mul(m.mul(v, v), v))))
+    }
+
+    /// Computes 2P.
+    fn dbl(&self, (x, z): WordPoint) -> WordPoint {
+        let m = self.mont;
+        let s = m.add(x, z);
+        let d = m.sub(x, z);
+        let s2 = m.mul(s, s);
+        let d2 = m.mul(d, d);
+        let t = m.sub(s2, d2);
+        // Both coordinates are multiplied by a24_den.
+        let x = m.mul(m.mul(s2, d2), self.a24_den);
+        let z = m.mul(t, m.add(m.mul(d2, self.a24_den), m.mul(self.a24_num, t)));
+        (x, z)
+    }
+
+    /// Computes P + Q from P, Q and P - Q.
+    fn add(&self, p: WordPoint, q: WordPoint, diff: WordPoint) -> WordPoint {
+        let m = self.mont;
+        let u = m.mul(m.sub(p.0, p.1), m.add(q.0, q.1));
+        let v = m.mul(m.add(p.0, p.1), m.sub(q.0, q.1));
+        let sum = m.add(u, v);
+        let dif = m.sub(u, v);
+        (
+            m.mul(diff.1, m.mul(sum, sum)),
+            m.mul(diff.0, m.mul(dif, dif)),
+        )
+    }
+
+    /// Computes kP with the Montgomery ladder.
+    fn mul(&self, p: WordPoint, k: u64) -> WordPoint {
+        if k == 0 {
+            return (self.mont.one(), 0);
+        }
+        let mut r0 = p;
+        let mut r1 = self.dbl(p);
+        for bit in (0..63 - k.leading_zeros()).rev() {
+            if (k >> bit) & 1 == 1 {
+                r0 = self.add(r1, r0, p);
+                r1 = self.dbl(r1);
+            } else {
+                r1 = self.add(r1, r0, p);
+                r0 = self.dbl(r0);
+            }
+        }
+        r0
+    }
+
+    /// Performs the baby-step giant-step stage 2 on P for the primes in ps above b1,
+    /// and returns the product of the differences.
+    fn stage2(&self, p: WordPoint, ps: &[usize], b1: u64) -> u128 {
+        let m = self.mont;
+        let b2 = ps.last().map_or(0, |&p| p as u64);
+        let d = [2310, 210]
+            .into_iter()
+            .find(|&d| d * d <= 2 * b2)
+            .unwrap_or(30);
+        // Baby steps: baby[i] = (2i + 1) P for 2i + 1 <= d / 2
+        let p2 = self.dbl(p);
+        let mut baby = vec![p, self.add(p2, p, p)];
+        while 2 * (baby.len() as u64) < d / 2 {
+            let len = baby.len();
+            baby.push(self.add(baby[len - 1], p2, baby[len - 2]));
+        }
+        // Giant steps: cur = kD P, prev = (k-1)D P
+        let q = self.mul(p, d);
+        let mut k = 1;
+        let mut prev = self.mul(p, 0);
+        let mut cur = q;
+        let mut acc = m.one();
+        for &prime in ps {
+            let prime = prime as u64;
+            if prime <= b1 {
+                continue;
+            }
+            let target = (prime + d / 2) / d;
+            let j = prime.abs_diff(target * d);
+            let baby_j = baby[(j / 2) as usize];
+            if target == 0 {
+                acc = m.mul(acc, baby_j.1);
+                continue;
+            }
+            while k < target {
+                let next = if k == 1 {
+                    self.dbl(cur)
+                } else {
+                    self.add(cur, q, prev)
+                };
+                prev = std::mem::replace(&mut cur, next);
+                k += 1;
+            }
+            acc = m.mul(acc, m.sub(m.mul(cur.0, baby_j.1), m.mul(baby_j.0, cur.1)));
+        }
+        acc
+    }
+}
+
+/// Finds a nontrivial factor of an odd composite n < 2^128 that is not a perfect square.
+fn find_factor_u128(n: u128) -> u128 {
+    if let Some(fac) = pollard_brent_u128(n, RHO_ITERATIONS_U128) {
+        return fac;
+    }
+    let mut sigma = 6;
+    for (b1, curves) in ECM_SCHEDULE_U128 {
+        for _ in 0..curves {
+            sigma += 1;
+            if let Some(fac) = ecm_u128(n, sigma, b1, 100 * b1) {
+                return fac;
+            }
+        }
+    }
+    let (b1, _) = ECM_SCHEDULE_U128[ECM_SCHEDULE_U128.len() - 1];
+    loop {
+        sigma += 1;
+        if let Some(fac) = ecm_u128(n, sigma, b1, 100 * b1) {
+            return fac;
+        }
+    }
+}
+
+/// Factorizes n on machine words.
+///
+/// Returns the prime factors with their multiplicity in the increasing order.
+/// Factors below 2^64 are found by `factorize_u64`, and the others are split with Pollard's rho method and ECM.
+pub fn factorize_u128(n: u128) -> Vec<(u128, u64)> {
+    assert!(n >= 1);
+    let mut factors = vec![];
+    let mut stack = vec![(n, 1)];
+    while let Some((m, e)) = stack.pop() {
+        if let Ok(small) = u64::try_from(m) {
+            for (p, f) in factorize_u64(small) {
+                factors.push((p as u128, f * e));
+            }
+            continue;
+        }
+        if m & 1 == 0 {
+            let zeros = m.trailing_zeros();
+            factors.push((2, zeros as u64 * e));
+            stack.push((m >> zeros, e));
+            continue;
+        }
+        if is_prime_u128(m) {
+            factors.push((m, e));
+            continue;
+        }
+        if let Some(r) = exact_sqrt(m) {
+            stack.push((r, 2 * e));
+            continue;
+        }
+        let fac = find_factor_u128(m);
+        stack.push((fac, e));
+        stack.push((m / fac, e));
+    }
+    factors.sort_unstable();
+    let mut merged: Vec<(u128, u64)> = vec![];
+    for (p, e) in factors {
+        match merged.last_mut() {
+            Some(last) if last.0 == p => last.1 += e,
+            _ => merged.push((p, e)),
+        }
+    }
+    merged
+}
+
+/// Factorizes n on machine words.
+///
+/// Returns the prime factors with their multiplicity in the increasing order.
+/// Composites are split with Hart's one line factoring, SQUFOF, or Pollard's rho method, depending on their size.
+pub fn factorize_u64(mut n: u64) -> Vec<(u64, u64)> {
+    assert!(n >= 1);
+    let mut factors = vec![];
+    for p in [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47] {
+        let mut e = 0;
+        while n % p == 0 {
+            n /= p;
+            e += 1;
+        }
+        if e > 0 {
+            factors.push((p, e));
+        }
+    }
+    let mut stack = vec![(n, 1)];
+    while let Some((m, e)) = stack.pop() {
+        if m == 1 {
+            continue;
+        }
+        if is_prime_u64(m) {
+            factors.push((m, e));
+            continue;
+        }
+        if let Some(r) = exact_sqrt(m) {
+            stack.push((r, 2 * e));
+            continue;
+        }
+        let fac = find_factor_u64(m);
+        stack.push((fac, e));
+        stack.push((m / fac, e));
+    }
+    factors.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = vec![];
+    for (p, e) in factors {
+        match merged.last_mut() {
+            Some(last) if last.0 == p => last.1 += e,
+            _ => merged.push((p, e)),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pollard_brent_u64_works() {
+        let n = 1_000_003u64 * 998_244_353;
+        let fac = pollard_brent_u64(n, 1 << 20).unwrap();
+        assert!(fac == 1_000_003 || fac == 998_244_353);
+        assert_eq!(pollard_brent_u64(1_000_000_007 * 1_000_000_009, 100), None);
+    }
+
+    #[test]
+    fn pollard_brent_u128_works() {
+        let n = 1_000_003u128 * 18_446_744_073_709_551_557;
+        let fac = pollard_brent_u128(n, 1 << 20).unwrap();
+        assert!(fac == 1_000_003 || fac == 18_446_744_073_709_551_557);
+    }
+
+    #[test]
+    fn ecm_u128_works() {
+        // Too large for Pollard's rho method with RHO_ITERATIONS_U128 iterations
+        let p = 1_000_000_000_039u128;
+        let q = 1_000_000_000_000_000_000_000_007u128;
+        let found = (7..200).find_map(|sigma| ecm_u128(p * q, sigma, 2000, 200_000));
+        assert_eq!(found, Some(p));
+    }
+
+    #[test]
+    fn factorize_u128_works() {
+        assert_eq!(factorize_u128(1), vec![]);
+        assert_eq!(factorize_u128(1 << 100), vec![(2, 100)]);
+        let p = 1_000_000_000_000_037u128;
+        let q = 200_000_000_000_000_000_089u128;
+        assert_eq!(factorize_u128(p * q), vec![(p, 1), (q, 1)]);
+        assert_eq!(
+            factorize_u128(p * q * 12),
+            vec![(2, 2), (3, 1), (p, 1), (q, 1)]
+        );
+        let r = 18_446_744_073_709_551_557u128; // The largest prime below 2^64
+        assert_eq!(factorize_u128(r * r), vec![(r, 2)]);
+        assert_eq!(factorize_u128(r * 65_537), vec![(65_537, 1), (r, 1)]);
+    }
+
+    #[test]
+    fn hart_olf_works() {
+        let n = 1_000_003u64 * 1_000_033;
+        let fac = hart_olf(n, 1 << 16).unwrap();
+        assert!(fac == 1_000_003 || fac == 1_000_033);
+    }
+
+    #[test]
+    fn squfof_works() {
+        for (p, q) in [
+            (11u64, 13u64),
+            (1_000_003, 998_244_353),
+            (1_000_000_007, 1_000_000_009),
+            (2_147_483_647, 998_244_353),
+        ] {
+            let fac = squfof(p * q).unwrap();
+            assert!(fac == p || fac == q, "p = {}, q = {}, fac = {}", p, q, fac);
+        }
+    }
+
+    #[test]
+    fn factorize_u64_works() {
+        assert_eq!(factorize_u64(1), vec![]);
+        assert_eq!(
+            factorize_u64(8293702863045600),
+            vec![
+                (2, 5),
+                (3, 3),
+                (5, 2),
+                (7, 1),
+                (11, 1),
+                (13, 1),
+                (17, 1),
+                (19, 1),
+                (1187567, 1),
+            ]
+        );
+        assert_eq!(
+            factorize_u64(4_294_967_291 * 4_294_967_279),
+            vec![(4_294_967_279, 1), (4_294_967_291, 1)]
+        );
+        assert_eq!(
+            factorize_u64(18_446_744_073_709_551_557),
+            vec![(18_446_744_073_709_551_557, 1)]
+        );
+        let p = 2_642_239u64; // p^3 < 2^64
+        assert_eq!(factorize_u64(p * p * p), vec![(p, 3)]);
+        let expected = vec![
+            (3, 1),
+            (5, 1),
+            (17, 1),
+            (257, 1),
+            (641, 1),
+            (65537, 1),
+            (6700417, 1),
+        ];
+        assert_eq!(factorize_u64(u64::MAX), expected);
+    }
+}
@@ -24,9 +24,9 @@ pub fn kronecker_symbol_i64(mut a: i64, mut b: i64) -> i32 {
         }
         if b < 0 {
             b = -b;
-        }
-        if a < 0 {
-            k = -k;
+            if a < 0 {
+                k = -k;
+            }
         }
     }
     // 3. and 4.
@@ -74,4 +74,16 @@ mod tests {
             [0, 1, 1, -1, 1, -1, -1, -1, 1, 1, -1, -1, -1, 1, -1, 1, 1, 0],
         );
     }
+
+    #[test]
+    fn kronecker_symbol_i64_works_negative() {
+        // (a/-1) = -1 iff a < 0
+        assert_eq!(kronecker_symbol_i64(-100, 1), 1);
+        assert_eq!(kronecker_symbol_i64(-3, 13), 1);
+        assert_eq!(kronecker_symbol_i64(-3, -13), -1);
+        assert_eq!(kronecker_symbol_i64(3, -13), 1);
+        // (-1/7) = -1 and (-1/2) = 1
+        assert_eq!(kronecker_symbol_i64(-1, 7), -1);
+        assert_eq!(kronecker_symbol_i64(-1, 14), -1);
+    }
 }
@@ -1,5 +1,23 @@
+mod arith;
+mod factor;
 mod kronecker;
+mod montgomery;
+mod primality;
+mod prime_count;
 mod primes;
+mod word;
 
+pub use arith::{
+    big_omega_u64, carmichael_lambda_u64, divisor_count_u64, divisor_sigma_u64, divisors_u64,
+    euler_phi_u64, moebius_u64, omega_u64, LinearSieve,
+};
+pub use factor::{
+    ecm_u128, factorize_u128, factorize_u64, hart_olf, pollard_brent_u128, pollard_brent_u64,
+    squfof,
+};
 pub use kronecker::kronecker_symbol_i64;
-pub use primes::{primes, Primes};
+pub use montgomery::Montgomery;
+pub use primality::{deterministic_mr_bases, is_prime_u128, is_prime_u64, DETERMINISTIC_MR_BOUND};
+pub use prime_count::{nth_prime, prime_count};
+pub use primes::{primes, primes_in, Primes};
+pub use word::Word;
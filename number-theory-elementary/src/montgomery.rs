@@ -0,0 +1,164 @@
+use crate::word::Word;
+
+/// Arithmetic modulo an odd n in Montgomery form.
+///
+/// An element x mod n is represented by xR mod n, where R = 2^T::BITS.
+/// Multiplication then needs no division by n.
+/// Values passed to and returned by the methods other than `to_mont` and `from_mont` are in this form.
+#[derive(Clone, Copy, Debug)]
+pub struct Montgomery<T> {
+    n: T,
+    /// n^{-1} mod R
+    n_inv: T,
+    /// R mod n
+    r1: T,
+    /// R^2 mod n
+    r2: T,
+}
+
+impl<T: Word> Montgomery<T> {
+    /// n must be odd and > 1.
+    pub fn new(n: T) -> Self {
+        assert!(n > T::ONE && n & T::ONE == T::ONE, "n = {:?}", n);
+        // Newton's iteration doubles the number of correct low bits, starting from 3.
+        let mut n_inv = n;
+        for _ in 0..7 {
+            n_inv = n_inv.wrapping_mul(T::from_u64(2).wrapping_sub(n.wrapping_mul(n_inv)));
+        }
+        let r1 = (T::MAX % n + T::ONE) % n;
+        let mut mont = Montgomery {
+            n,
+            n_inv,
+            r1,
+            r2: r1,
+        };
+        let mut r2 = r1;
+        for _ in 0..T::BITS {
+            r2 = mont.add(r2, r2);
+        }
+        mont.r2 = r2;
+        mont
+    }
+
+    pub fn modulus(&self) -> T {
+        self.n
+    }
+
+    /// Computes (hi R + lo) / R mod n. hi must be < n.
+    fn reduce(&self, lo: T, hi: T) -> T {
+        let m = lo.wrapping_mul(self.n_inv);
+        // The low word of m n equals lo, so it cancels.
+        let (_, mn_hi) = m.mul_wide(self.n);
+        if hi >= mn_hi {
+            hi - mn_hi
+        } else {
+            hi.wrapping_sub(mn_hi).wrapping_add(self.n)
+        }
+    }
+
+    /// Converts x to Montgomery form.
+    pub fn to_mont(&self, x: T) -> T {
+        self.mul(x % self.n, self.r2)
+    }
+
+    /// Converts x from Montgomery form.
+    pub fn from_mont(&self, x: T) -> T {
+        self.reduce(x, T::ZERO)
+    }
+
+    /// 1 in Montgomery form.
+    pub fn one(&self) -> T {
+        self.r1
+    }
+
+    pub fn add(&self, a: T, b: T) -> T {
+        let (sum, overflow) = a.overflowing_add(b);
+        if overflow || sum >= self.n {
+            sum.wrapping_sub(self.n)
+        } else {
+            sum
+        }
+    }
+
+    pub fn sub(&self, a: T, b: T) -> T {
+        if a >= b {
+            a - b
+        } else {
+            a.wrapping_sub(b).wrapping_add(self.n)
+        }
+    }
+
+    pub fn neg(&self, a: T) -> T {
+        self.sub(T::ZERO, a)
+    }
+
+    pub fn mul(&self, a: T, b: T) -> T {
+        let (lo, hi) = a.mul_wide(b);
+        self.reduce(lo, hi)
+    }
+
+    /// Computes a / 2.
+    pub fn half(&self, a: T) -> T {
+        if a & T::ONE == T::ZERO {
+            a >> 1
+        } else {
+            // (a + n) / 2 without overflow, as both a and n are odd
+            (a >> 1) + (self.n >> 1) + T::ONE
+        }
+    }
+
+    /// Computes a^e.
+    pub fn pow(&self, a: T, e: T) -> T {
+        let mut result = self.r1;
+        let bits = T::BITS - e.leading_zeros();
+        for i in (0..bits).rev() {
+            result = self.mul(result, result);
+            if (e >> i) & T::ONE == T::ONE {
+                result = self.mul(result, a);
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn montgomery_u64_works() {
+        let n = 1_000_000_007u64;
+        let mont = Montgomery::new(n);
+        let a = mont.to_mont(123_456_789);
+        let b = mont.to_mont(987_654_321);
+        assert_eq!(
+            mont.from_mont(mont.mul(a, b)),
+            (123_456_789u128 * 987_654_321 % n as u128) as u64
+        );
+        assert_eq!(mont.from_mont(mont.add(a, b)), 111_111_103);
+        assert_eq!(mont.from_mont(mont.sub(a, b)), 135_802_475);
+        assert_eq!(mont.from_mont(mont.pow(a, n - 1)), 1);
+        assert_eq!(
+            mont.from_mont(mont.mul(mont.half(a), mont.to_mont(2))),
+            123_456_789
+        );
+    }
+
+    #[test]
+    fn montgomery_u128_works() {
+        // 2^127 - 1 is a prime.
+        let n = (1u128 << 127) - 1;
+        let mont = Montgomery::new(n);
+        let a = mont.to_mont(3);
+        assert_eq!(mont.from_mont(mont.pow(a, n - 1)), 1);
+        let two = mont.to_mont(2);
+        assert_eq!(mont.from_mont(mont.pow(two, 127)), 1);
+        // A modulus close to 2^128
+        let n = u128::MAX - 158;
+        let mont = Montgomery::new(n);
+        let x = mont.to_mont(n - 1);
+        assert_eq!(mont.from_mont(mont.mul(x, x)), 1);
+        assert_eq!(mont.from_mont(mont.add(x, x)), n - 2);
+        assert_eq!(mont.from_mont(mont.neg(x)), 1);
+    }
+}
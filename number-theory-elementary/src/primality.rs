@@ -0,0 +1,298 @@
+use crate::montgomery::Montgomery;
+use crate::word::{exact_sqrt, Word};
+
+const SMALL_PRIMES: [u64; 13] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41];
+
+/// Miller-Rabin bases that are deterministic for all n < 2^64, found by Jim Sinclair.
+const BASES_U64: [u64; 7] = [2, 325, 9375, 28178, 450775, 9780504, 1795265022];
+
+/// Miller-Rabin with the first 13 primes as bases is deterministic below this bound.
+///
+/// Sorenson, Jonathan, and Jonathan Webster. "Strong pseudoprimes to twelve prime bases." Mathematics of Computation 86.304 (2017): 985-1003.
+pub const DETERMINISTIC_MR_BOUND: u128 = 3_317_044_064_679_887_385_961_981;
+
+/// (psi_k, k): the smallest strong pseudoprime to all of the first k primes is psi_k.
+/// Only the k with psi_k > psi_{k-1} are listed.
+const PSI: [(u128, usize); 10] = [
+    (2_047, 1),
+    (1_373_653, 2),
+    (25_326_001, 3),
+    (3_215_031_751, 4),
+    (2_152_302_898_747, 5),
+    (3_474_749_660_383, 6),
+    (341_550_071_728_321, 7),
+    (3_825_123_056_546_413_051, 9),
+    (318_665_857_834_031_151_167_461, 12),
+    (DETERMINISTIC_MR_BOUND, 13),
+];
+
+/// Returns the fewest first primes that make Miller-Rabin deterministic for n,
+/// or None if n >= `DETERMINISTIC_MR_BOUND`.
+pub fn deterministic_mr_bases(n: u128) -> Option<&'static [u64]> {
+    PSI.iter()
+        .find(|&&(psi, _)| n < psi)
+        .map(|&(_, k)| &SMALL_PRIMES[..k])
+}
+
+/// Tests if n is a strong probable prime to the given base. n must be odd and > 1.
+fn is_strong_probable_prime<T: Word>(mont: &Montgomery<T>, base: T) -> bool {
+    let n = mont.modulus();
+    let s = (n - T::ONE).trailing_zeros();
+    let d = (n - T::ONE) >> s;
+    let a = mont.to_mont(base);
+    if a == T::ZERO {
+        return true;
+    }
+    let one = mont.one();
+    let minus_one = mont.neg(one);
+    let mut x = mont.pow(a, d);
+    if x == one || x == minus_one {
+        return true;
+    }
+    for _ in 1..s {
+        x = mont.mul(x, x);
+        if x == minus_one {
+            return true;
+        }
+    }
+    false
+}
+
+/// Trial division by `SMALL_PRIMES`. Returns Some if it decides the primality of n.
+fn small_prime_test<T: Word>(n: T) -> Option<bool> {
+    if n < T::from_u64(2) {
+        return Some(false);
+    }
+    for p in SMALL_PRIMES {
+        let p = T::from_u64(p);
+        if n == p {
+            return Some(true);
+        }
+        if n % p == T::ZERO {
+            return Some(false);
+        }
+    }
+    // n has no prime factors <= 41.
+    if n < T::from_u64(43 * 43) {
+        return Some(true);
+    }
+    None
+}
+
+/// Tests if n is a prime. The result is always correct.
+pub fn is_prime_u64(n: u64) -> bool {
+    if let Some(result) = small_prime_test(n) {
+        return result;
+    }
+    let mont = Montgomery::new(n);
+    BASES_U64
+        .iter()
+        .all(|&base| is_strong_probable_prime(&mont, base))
+}
+
+/// Tests if n is a prime.
+///
+/// For n < `DETERMINISTIC_MR_BOUND`, the result is always correct.
+/// For larger n, this is the Baillie-PSW test, a strong probable prime test to base 2 followed by
+/// a strong Lucas test with Selfridge's parameters. No composite is known to pass it.
+pub fn is_prime_u128(n: u128) -> bool {
+    if let Ok(n) = u64::try_from(n) {
+        return is_prime_u64(n);
+    }
+    if let Some(result) = small_prime_test(n) {
+        return result;
+    }
+    let mont = Montgomery::new(n);
+    if let Some(bases) = deterministic_mr_bases(n) {
+        return bases
+            .iter()
+            .all(|&base| is_strong_probable_prime(&mont, base as u128));
+    }
+    is_strong_probable_prime(&mont, 2) && is_strong_lucas_probable_prime(&mont)
+}
+
+/// Computes the Jacobi symbol (a/n) for an odd n.
+fn jacobi_u128(mut a: u128, mut n: u128) -> i32 {
+    a %= n;
+    let mut result = 1;
+    while a != 0 {
+        let twos = a.trailing_zeros();
+        a >>= twos;
+        if twos % 2 == 1 && (n % 8 == 3 || n % 8 == 5) {
+            result = -result;
+        }
+        if a % 4 == 3 && n % 4 == 3 {
+            result = -result;
+        }
+        std::mem::swap(&mut a, &mut n);
+        a %= n;
+    }
+    if n == 1 {
+        result
+    } else {
+        0
+    }
+}
+
+/// The strong Lucas probable prime test with Selfridge's parameters:
+/// D is the first of 5, -7, 9, -11, ... with (D/n) = -1, P = 1 and Q = (1 - D) / 4.
+/// n must be odd, > 1 and have no small prime factors.
+fn is_strong_lucas_probable_prime(mont: &Montgomery<u128>) -> bool {
+    let n = mont.modulus();
+    // (D/n) = -1 never happens for a square n.
+    if exact_sqrt(n).is_some() {
+        return false;
+    }
+    let mut d = 5i64;
+    loop {
+        let d_mod_n = if d > 0 {
+            d as u128
+        } else {
+            n - d.unsigned_abs() as u128
+        };
+        match jacobi_u128(d_mod_n, n) {
+            -1 => break,
+            0 if d.unsigned_abs() as u128 != n => return false,
+            _ => {}
+        }
+        d = if d > 0 { -d - 2 } else { -d + 2 };
+    }
+    let to_mont = |x: i64| {
+        let x_abs = mont.to_mont(x.unsigned_abs() as u128);
+        if x < 0 {
+            mont.neg(x_abs)
+        } else {
+            x_abs
+        }
+    };
+    let dm = to_mont(d);
+    let q = to_mont((1 - d) / 4);
+
+    // n + 1 = k 2^s with k odd. n + 1 does not overflow, since 3 divides 2^128 - 1.
+    let s = (n + 1).trailing_zeros();
+    let k = (n + 1) >> s;
+    // (U_j, V_j, Q^j) for j = 1, with P = 1
+    let mut u = mont.one();
+    let mut v = mont.one();
+    let mut qk = q;
+    let bits = 128 - k.leading_zeros();
+    for i in (0..bits - 1).rev() {
+        // j -> 2j
+        u = mont.mul(u, v);
+        v = mont.sub(mont.mul(v, v), mont.add(qk, qk));
+        qk = mont.mul(qk, qk);
+        if (k >> i) & 1 == 1 {
+            // j -> j + 1
+            let new_u = mont.half(mont.add(u, v));
+            v = mont.half(mont.add(mont.mul(dm, u), v));
+            u = new_u;
+            qk = mont.mul(qk, q);
+        }
+    }
+    if u == 0 || v == 0 {
+        return true;
+    }
+    for _ in 1..s {
+        v = mont.sub(mont.mul(v, v), mont.add(qk, qk));
+        qk = mont.mul(qk, qk);
+        if v == 0 {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_prime_naive(n: u64) -> bool {
+        n >= 2 && (2..).take_while(|d| d * d <= n).all(|d| n % d != 0)
+    }
+
+    #[test]
+    fn is_prime_u64_works() {
+        for n in 0..10_000 {
+            assert_eq!(is_prime_u64(n), is_prime_naive(n), "n = {}", n);
+        }
+        assert!(is_prime_u64(1_000_000_007));
+        // 2^64 - 59 is the largest prime < 2^64.
+        assert!(is_prime_u64(18_446_744_073_709_551_557));
+        // Strong pseudoprime to bases 2, 3, 5, 7, 11, 13, 17, 19, 23
+        assert!(!is_prime_u64(3_825_123_056_546_413_051));
+        assert!(!is_prime_u64(4_294_967_297)); // 641 * 6700417
+    }
+
+    #[test]
+    fn is_prime_u128_works() {
+        assert!(is_prime_u128((1 << 127) - 1));
+        assert!(is_prime_u128(1_000_000_000_000_000_000_000_000_103));
+        assert!(!is_prime_u128(
+            1_000_000_000_000_000_000_000_000_103 * 3_000_000_019
+        ));
+        // 2^128 - 159 is the largest prime < 2^128.
+        assert!(is_prime_u128(u128::MAX - 158));
+        assert!(!is_prime_u128(u128::MAX));
+        // Strong pseudoprime to the first 12 prime bases, below DETERMINISTIC_MR_BOUND
+        assert!(!is_prime_u128(318_665_857_834_031_151_167_461));
+        // Powers of primes
+        let p = 4_294_967_311u128;
+        assert!(!is_prime_u128(p * p * p));
+        let q = 18_446_744_073_709_551_557u128;
+        assert!(!is_prime_u128(q * q));
+    }
+
+    #[test]
+    fn deterministic_mr_bases_works() {
+        assert_eq!(deterministic_mr_bases(2_046), Some(&[2][..]));
+        assert_eq!(deterministic_mr_bases(2_047), Some(&[2, 3][..]));
+        assert_eq!(
+            deterministic_mr_bases(3_825_123_056_546_413_051),
+            Some(&[2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37][..])
+        );
+        assert_eq!(
+            deterministic_mr_bases(DETERMINISTIC_MR_BOUND - 1)
+                .unwrap()
+                .len(),
+            13
+        );
+        assert_eq!(deterministic_mr_bases(DETERMINISTIC_MR_BOUND), None);
+        // The listed bounds are strong pseudoprimes to one base fewer.
+        for (psi, k) in PSI {
+            if let Ok(psi) = u64::try_from(psi) {
+                let mont = Montgomery::new(psi);
+                assert!(SMALL_PRIMES[..k - 1]
+                    .iter()
+                    .all(|&base| is_strong_probable_prime(&mont, base)));
+            }
+        }
+    }
+
+    #[test]
+    fn strong_lucas_works() {
+        // Strong Lucas pseudoprimes: 5459, 5777, 10877
+        for n in [5459u128, 5777, 10877] {
+            assert!(is_strong_lucas_probable_prime(&Montgomery::new(n)));
+        }
+        for n in [1_000_000_007u128, 998_244_353, (1 << 127) - 1] {
+            assert!(is_strong_lucas_probable_prime(&Montgomery::new(n)));
+        }
+        let n = 1_000_000_007u128 * 998_244_353;
+        assert!(!is_strong_lucas_probable_prime(&Montgomery::new(n)));
+    }
+
+    #[test]
+    fn jacobi_u128_works() {
+        for n in (1..200i64).step_by(2) {
+            for a in 0..200i64 {
+                assert_eq!(
+                    jacobi_u128(a as u128, n as u128),
+                    crate::kronecker_symbol_i64(a, n),
+                    "a = {}, n = {}",
+                    a,
+                    n
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,432 @@
+use crate::primes::{primes_in, Primes, Sieve, BIT, SEGMENT_BYTES};
+use crate::word::isqrt;
+
+/// Below this bound, `prime_count` simply sieves [0, x].
+const SIEVE_BOUND: u64 = 1 << 20;
+
+/// phi(x, c) for the first c primes is computed from a table of the integers coprime to their product.
+const TINY_PRIMES: [u64; 6] = [2, 3, 5, 7, 11, 13];
+const TINY_PRIMORIAL: u64 = 30030;
+const TINY_TOTIENT: u64 = 5760;
+
+/// MASK_LE[r] has the bits for the residues <= r modulo 30.
+const MASK_LE: [u8; 30] = {
+    let mut mask = [0; 30];
+    let mut acc = 0;
+    let mut r = 0;
+    while r < 30 {
+        acc |= BIT[r];
+        mask[r] = acc;
+        r += 1;
+    }
+    mask
+};
+
+/// The primes, the Möbius function and the least prime factors up to y, computed by a linear sieve.
+struct SmallSieve {
+    /// primes[b] is the b-th prime (1-indexed). primes[0] = 0 is unused.
+    primes: Vec<u64>,
+    mu: Vec<i8>,
+    /// lpf[1] = u64::MAX, since 1 has no prime factors.
+    lpf: Vec<u64>,
+}
+
+impl SmallSieve {
+    fn new(y: usize) -> Self {
+        let mut primes = vec![0];
+        let mut mu = vec![0; y + 1];
+        let mut lpf = vec![0; y + 1];
+        mu[1] = 1;
+        lpf[1] = u64::MAX;
+        for n in 2..=y {
+            if lpf[n] == 0 {
+                lpf[n] = n as u64;
+                mu[n] = -1;
+                primes.push(n as u64);
+            }
+            for &p in &primes[1..] {
+                let m = n * p as usize;
+                if p > lpf[n] || m > y {
+                    break;
+                }
+                lpf[m] = p;
+                mu[m] = if p == lpf[n] { 0 } else { -mu[n] };
+            }
+        }
+        SmallSieve { primes, mu, lpf }
+    }
+}
+
+/// The number of bits covered by a counter in `LeafSieve`.
+const COUNTER_BITS: usize = 512;
+
+/// A segment [low, low + len) of the sieve in `special_leaves`, one bit per integer,
+/// with the number of set bits in each block of `COUNTER_BITS` bits.
+struct LeafSieve {
+    words: Vec<u64>,
+    counters: Vec<u32>,
+    /// The number of set bits
+    total: u64,
+}
+
+impl LeafSieve {
+    fn new(len: usize) -> Self {
+        let mut words = vec![u64::MAX; len.div_ceil(64)];
+        if len % 64 != 0 {
+            *words.last_mut().unwrap() = (1 << (len % 64)) - 1;
+        }
+        LeafSieve {
+            words,
+            counters: vec![],
+            total: 0,
+        }
+    }
+    fn clear(&mut self, i: usize) {
+        self.words[i / 64] &= !(1 << (i % 64));
+    }
+    fn count_blocks(&mut self) {
+        self.counters = self
+            .words
+            .chunks(COUNTER_BITS / 64)
+            .map(|chunk| chunk.iter().map(|w| w.count_ones()).sum())
+            .collect();
+        self.total = self.counters.iter().map(|&c| c as u64).sum();
+    }
+    /// Clears bit i and updates the counter. `count_blocks` must have been called.
+    fn remove(&mut self, i: usize) {
+        let mask = 1 << (i % 64);
+        if self.words[i / 64] & mask != 0 {
+            self.words[i / 64] &= !mask;
+            self.counters[i / COUNTER_BITS] -= 1;
+            self.total -= 1;
+        }
+    }
+}
+
+/// Counts the set bits in [0, i] of a `LeafSieve` for increasing i, summing the counters as it goes.
+struct LeafCursor {
+    block: usize,
+    sum: u64,
+}
+
+impl LeafCursor {
+    fn count(&mut self, sieve: &LeafSieve, i: usize) -> u64 {
+        let block = i / COUNTER_BITS;
+        while self.block < block {
+            self.sum += sieve.counters[self.block] as u64;
+            self.block += 1;
+        }
+        let first_word = block * (COUNTER_BITS / 64);
+        let full: u64 = sieve.words[first_word..i / 64]
+            .iter()
+            .map(|w| w.count_ones() as u64)
+            .sum();
+        let partial = sieve.words[i / 64] & (u64::MAX >> (63 - i % 64));
+        self.sum + full + partial.count_ones() as u64
+    }
+}
+
+/// Counts the primes <= x with the algorithm of Lagarias, Miller and Odlyzko.
+///
+/// With a = pi(y) for y = alpha x^{1/3}, pi(x) = phi(x, a) + a - 1 - P2(x, a),
+/// where phi(x, a) counts the integers <= x with no prime factors <= p_a,
+/// and P2(x, a) counts those with exactly two prime factors > p_a.
+/// phi(x, a) is the sum of the ordinary leaves phi(x / n, c) for small c and n <= y,
+/// and of the special leaves, which are counted by sieving [1, x / y] in segments,
+/// with a counter of the integers left in each block of `COUNTER_BITS` bits.
+/// This takes O(x^{2/3} / log x) time and O(x^{1/3} log x) memory.
+/// pi(10^15) takes about 30 seconds, and each factor of 10 in x about 4 times longer,
+/// so x up to about 10^17 is practical; pi(10^18) takes about half an hour.
+///
+/// Lagarias, J. C., V. S. Miller, and A. M. Odlyzko. "Computing π(x): The Meissel-Lehmer method." Mathematics of Computation 44.170 (1985): 537-560.
+pub fn prime_count(x: u64) -> u64 {
+    if x < SIEVE_BOUND {
+        return primes_in(0..x + 1).len() as u64;
+    }
+    let log_x = (x as f64).ln();
+    // alpha = 4 is about optimal for 10^12 <= x <= 10^15.
+    let alpha = (log_x / 8.0).max(1.0);
+    let y = ((alpha * (x as f64).cbrt()) as u64).min(isqrt(x));
+    let small = SmallSieve::new(y as usize);
+    let pi_y = (small.primes.len() - 1) as i128;
+    let phi = ordinary_leaves(x, y, &small) + special_leaves(x, y, &small);
+    (phi + pi_y - 1 - p2(x, y, &small)) as u64
+}
+
+/// Computes phi(v, c) for the c = 6 primes in `TINY_PRIMES`.
+struct TinyPhi {
+    table: Vec<u64>,
+}
+
+impl TinyPhi {
+    fn new() -> Self {
+        let mut table = Vec::with_capacity(TINY_PRIMORIAL as usize);
+        let mut count = 0;
+        for n in 0..TINY_PRIMORIAL {
+            if n > 0 && TINY_PRIMES.iter().all(|&p| n % p != 0) {
+                count += 1;
+            }
+            table.push(count);
+        }
+        TinyPhi { table }
+    }
+    fn phi(&self, v: u64) -> u64 {
+        v / TINY_PRIMORIAL * TINY_TOTIENT + self.table[(v % TINY_PRIMORIAL) as usize]
+    }
+}
+
+/// The sum of mu(n) phi(x / n, c) over the squarefree n <= y with lpf(n) > p_c.
+fn ordinary_leaves(x: u64, y: u64, small: &SmallSieve) -> i128 {
+    let tiny = TinyPhi::new();
+    let p_c = TINY_PRIMES[TINY_PRIMES.len() - 1];
+    (1..=y as usize)
+        .filter(|&n| small.mu[n] != 0 && small.lpf[n] > p_c)
+        .map(|n| small.mu[n] as i128 * tiny.phi(x / n as u64) as i128)
+        .sum()
+}
+
+/// The sum of -mu(m) phi(x / (p_b m), b - 1) over c < b < a and y / p_b < m <= y with lpf(m) > p_b.
+///
+/// The values of phi are read off a sieve of [1, x / y] in segments.
+/// Before the multiples of p_b are crossed off, the sieve holds the integers with no prime factors < p_b,
+/// and phi[b] counts them in the previous segments.
+fn special_leaves(x: u64, y: u64, small: &SmallSieve) -> i128 {
+    let primes = &small.primes;
+    let pi_y = primes.len() - 1;
+    let c = TINY_PRIMES.len();
+    let limit = x / y + 1;
+    let segment_size = (isqrt(limit) as usize).next_power_of_two();
+    let mut phi = vec![0u64; pi_y + 1];
+    let mut s2 = 0i128;
+    let mut low = 1;
+    while low < limit {
+        let high = (low + segment_size as u64).min(limit);
+        let len = (high - low) as usize;
+        let mut sieve = LeafSieve::new(len);
+        for &p in &primes[1..=c] {
+            let mut k = p.max(low.div_ceil(p) * p);
+            while k < high {
+                sieve.clear((k - low) as usize);
+                k += p;
+            }
+        }
+        sieve.count_blocks();
+        for b in c + 1..pi_y {
+            let p = primes[b];
+            let xp = x / p;
+            let min_m = (xp / high).max(y / p);
+            let max_m = (xp / low).min(y);
+            if p >= max_m {
+                // Larger b have no special leaves in this or later segments.
+                break;
+            }
+            // x / (p m) increases as m decreases.
+            let mut cursor = LeafCursor { block: 0, sum: 0 };
+            if p * p > y {
+                // lpf(m) > p and m <= y imply that m is a prime, with mu(m) = -1.
+                let start = primes.partition_point(|&q| q <= min_m.max(p));
+                let end = primes.partition_point(|&q| q <= max_m);
+                for &q in primes[start..end].iter().rev() {
+                    let xn = xp / q;
+                    s2 += (phi[b] + cursor.count(&sieve, (xn - low) as usize)) as i128;
+                }
+            } else {
+                for m in (min_m + 1..=max_m).rev() {
+                    let m_index = m as usize;
+                    if small.mu[m_index] != 0 && small.lpf[m_index] > p {
+                        let xn = xp / m;
+                        let count = cursor.count(&sieve, (xn - low) as usize);
+                        s2 -= small.mu[m_index] as i128 * (phi[b] + count) as i128;
+                    }
+                }
+            }
+            phi[b] += sieve.total;
+            let mut k = p.max(low.div_ceil(p) * p);
+            while k < high {
+                sieve.remove((k - low) as usize);
+                k += p;
+            }
+        }
+        low = high;
+    }
+    s2
+}
+
+/// Computes pi(n) for increasing n > y by sieving segments in order.
+struct PrimeCounter<'a> {
+    /// The sieving primes >= 7.
+    sieving: &'a [u64],
+    sieve: Sieve,
+    /// pi(sieve.base - 1)
+    before: u64,
+    /// The number of set bits in sieve.bits[..pos]
+    pos: usize,
+    counted: u64,
+}
+
+impl<'a> PrimeCounter<'a> {
+    fn new(base: u64, before: u64, sieving: &'a [u64]) -> Self {
+        let mut counter = PrimeCounter {
+            sieving,
+            sieve: Sieve::new(base, 0),
+            before,
+            pos: 0,
+            counted: 0,
+        };
+        counter.sieve_segment(base);
+        counter
+    }
+    fn sieve_segment(&mut self, base: u64) {
+        self.sieve = Sieve::new(base, SEGMENT_BYTES as usize);
+        let limit = isqrt(self.sieve.end() - 1) as u64;
+        for &p in self.sieving.iter().take_while(|&&p| p <= limit) {
+            self.sieve.cross_off(p);
+        }
+        self.pos = 0;
+        self.counted = 0;
+    }
+    fn pi(&mut self, n: u64) -> u64 {
+        while n as u128 >= self.sieve.end() {
+            let rest: u64 = self.sieve.bits[self.pos..]
+                .iter()
+                .map(|b| b.count_ones() as u64)
+                .sum();
+            self.before += self.counted + rest;
+            self.sieve_segment(self.sieve.end() as u64);
+        }
+        let offset = n - self.sieve.base;
+        let k = (offset / 30) as usize;
+        while self.pos < k {
+            self.counted += self.sieve.bits[self.pos].count_ones() as u64;
+            self.pos += 1;
+        }
+        let partial = self.sieve.bits[k] & MASK_LE[(offset % 30) as usize];
+        self.before + self.counted + partial.count_ones() as u64
+    }
+}
+
+/// The width of the blocks in which primes are enumerated downwards in `p2` and `nth_prime`.
+const P2_BLOCK: u64 = 1 << 20;
+
+/// P2(x, a) = sum of pi(x / p) - pi(p) + 1 over the primes y < p <= sqrt(x).
+///
+/// The primes p are enumerated in decreasing order in blocks, so that x / p increases.
+fn p2(x: u64, y: u64, small: &SmallSieve) -> i128 {
+    let sqrt_x = isqrt(x);
+    if sqrt_x <= y {
+        return 0;
+    }
+    let primes = &small.primes[1..];
+    let base = (y + 1) / 30 * 30;
+    let before = primes.partition_point(|&p| p < base) as u64;
+    let mut counter = PrimeCounter::new(base, before, &primes[3..]);
+    let pi_y = primes.len() as i128;
+    let mut sum = 0i128;
+    let mut k = 0i128;
+    let mut hi = sqrt_x + 1;
+    while hi > y + 1 {
+        let lo = hi.saturating_sub(P2_BLOCK).max(y + 1);
+        for p in primes_in(lo..hi).into_iter().rev() {
+            sum += counter.pi(x / p) as i128;
+            k += 1;
+        }
+        hi = lo;
+    }
+    // pi(p) - 1 runs over pi_y, ..., pi_y + k - 1.
+    sum - k * pi_y - k * (k - 1) / 2
+}
+
+/// Returns the n-th prime (1-indexed): nth_prime(1) = 2.
+///
+/// An approximation x of the n-th prime is found by inverting the logarithmic integral,
+/// pi(x) is computed by `prime_count`, and the primes between are enumerated with a segmented sieve.
+pub fn nth_prime(n: u64) -> u64 {
+    assert!(n >= 1, "n must be positive");
+    if n < SIEVE_BOUND / 16 {
+        return Primes::new().nth(n as usize - 1).unwrap() as u64;
+    }
+    let guess = inverse_li(n as f64) as u64;
+    let mut count = prime_count(guess);
+    if count < n {
+        return Primes::starting_at(guess as usize + 1)
+            .nth((n - count - 1) as usize)
+            .unwrap() as u64;
+    }
+    // count = pi(hi - 1) >= n
+    let mut hi = guess + 1;
+    loop {
+        let lo = hi.saturating_sub(P2_BLOCK);
+        let block = primes_in(lo..hi);
+        let len = block.len() as u64;
+        if count - len < n {
+            return block[(n - (count - len) - 1) as usize];
+        }
+        count -= len;
+        hi = lo;
+    }
+}
+
+/// li(x) = gamma + ln ln x + sum (ln x)^k / (k k!)
+fn li(x: f64) -> f64 {
+    const EULER_GAMMA: f64 = 0.577_215_664_901_532_9;
+    let log_x = x.ln();
+    let mut term = 1.0;
+    let mut sum = 0.0;
+    for k in 1..1000 {
+        term *= log_x / k as f64;
+        let add = term / k as f64;
+        sum += add;
+        if add < sum * 1e-17 {
+            break;
+        }
+    }
+    EULER_GAMMA + log_x.ln() + sum
+}
+
+/// Solves li(x) = n by Newton's method, since li'(x) = 1 / ln x.
+fn inverse_li(n: f64) -> f64 {
+    let mut x = n * n.ln();
+    for _ in 0..100 {
+        let next = x - (li(x) - n) * x.ln();
+        if (next - x).abs() < 1.0 {
+            return next;
+        }
+        x = next;
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prime_count_works() {
+        assert_eq!(prime_count(0), 0);
+        assert_eq!(prime_count(2), 1);
+        assert_eq!(prime_count(1_000_000), 78498);
+        assert_eq!(prime_count(10_000_000), 664579);
+        assert_eq!(prime_count(1_000_000_000), 50847534);
+        assert_eq!(prime_count(10_000_000_000), 455052511);
+        assert_eq!(prime_count(1 << 32), 203280221);
+        // Around the boundary of the sieve
+        for x in [
+            SIEVE_BOUND - 1,
+            SIEVE_BOUND,
+            SIEVE_BOUND + 12345,
+            3_000_000,
+            12_345_678,
+        ] {
+            assert_eq!(prime_count(x), primes_in(0..x + 1).len() as u64);
+        }
+    }
+
+    #[test]
+    fn nth_prime_works() {
+        assert_eq!(nth_prime(1), 2);
+        assert_eq!(nth_prime(25), 97);
+        assert_eq!(nth_prime(1_000_000), 15485863);
+        assert_eq!(nth_prime(50847534), 999999937);
+        assert_eq!(nth_prime(50847535), 1000000007);
+    }
+}
@@ -1,44 +1,247 @@
-pub fn primes(bound: usize) -> Vec<usize> {
-    let mut is_prime = vec![true; bound + 1];
-    is_prime[0] = false;
-    if bound >= 1 {
-        is_prime[1] = false;
-    }
-    for i in 2..=bound {
-        if !is_prime[i] {
-            continue;
+use std::ops::Range;
+
+use crate::primality::is_prime_u64;
+use crate::word::isqrt;
+
+/// The residues modulo 30 coprime to 30. Bit i of a byte in a sieve stands for 30k + WHEEL[i].
+const WHEEL: [u64; 8] = [1, 7, 11, 13, 17, 19, 23, 29];
+
+/// GAP[i] is the distance from WHEEL[i] to the next residue coprime to 30.
+const GAP: [u64; 8] = [6, 4, 2, 4, 2, 4, 6, 2];
+
+/// BIT[r] is the bit for the residue r modulo 30, or 0 if gcd(r, 30) > 1.
+pub(crate) const BIT: [u8; 30] = {
+    let mut bit = [0; 30];
+    let mut i = 0;
+    while i < 8 {
+        bit[WHEEL[i] as usize] = 1 << i;
+        i += 1;
+    }
+    bit
+};
+
+/// The maximum number of bytes `Primes` sieves at once. A byte covers 30 integers.
+pub(crate) const SEGMENT_BYTES: u64 = 1 << 15;
+
+/// The number of bytes in the first segment of `Primes`. Segments double in size up to `SEGMENT_BYTES`,
+/// so that taking a few small primes is cheap.
+const FIRST_SEGMENT_BYTES: u64 = 64;
+
+/// `primes_in` sieves short windows only with the primes up to this bound, and tests the survivors with `is_prime_u64`.
+const PRESIEVE_BOUND: u64 = 1 << 16;
+
+/// A bit-packed sieve of the integers in [base, base + 30 len) coprime to 30, where 30 | base.
+/// Set bits are the integers not crossed off yet.
+pub(crate) struct Sieve {
+    pub(crate) base: u64,
+    pub(crate) bits: Vec<u8>,
+}
+
+impl Sieve {
+    pub(crate) fn new(base: u64, len: usize) -> Self {
+        let mut bits = vec![0xff; len];
+        if base == 0 && len > 0 {
+            // 1 is not a prime.
+            bits[0] &= !BIT[1];
+        }
+        Sieve { base, bits }
+    }
+
+    /// Sieves [0, 30 len) with the primes found in the sieve itself.
+    fn from_zero(len: usize) -> Self {
+        let mut sieve = Sieve::new(0, len);
+        let end = sieve.end();
+        for k in 0..len {
+            for (i, &r) in WHEEL.iter().enumerate() {
+                let p = 30 * k as u64 + r;
+                if (p as u128) * (p as u128) >= end {
+                    return sieve;
+                }
+                if sieve.bits[k] >> i & 1 == 1 {
+                    sieve.cross_off(p);
+                }
+            }
+        }
+        sieve
+    }
+
+    pub(crate) fn end(&self) -> u128 {
+        self.base as u128 + 30 * self.bits.len() as u128
+    }
+
+    /// Crosses off p j for j >= p coprime to 30. p must be coprime to 30.
+    pub(crate) fn cross_off(&mut self, p: u64) {
+        let end = self.end();
+        let mut j = p.max(self.base.div_ceil(p));
+        while BIT[(j % 30) as usize] == 0 {
+            j += 1;
         }
-        for j in 2..=bound / i {
-            is_prime[i * j] = false;
+        let mut i = BIT[(j % 30) as usize].trailing_zeros() as usize;
+        while let Some(m) = p.checked_mul(j) {
+            if m as u128 >= end {
+                break;
+            }
+            let offset = m - self.base;
+            self.bits[(offset / 30) as usize] &= !BIT[(offset % 30) as usize];
+            j += GAP[i];
+            i = (i + 1) % 8;
         }
     }
-    (2..=bound).filter(|&i| is_prime[i]).collect()
+
+    /// The integers not crossed off, in the increasing order.
+    fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        self.bits.iter().enumerate().flat_map(move |(k, &byte)| {
+            let base = self.base as u128 + 30 * k as u128;
+            (0..8)
+                .filter(move |&i| byte >> i & 1 == 1)
+                .filter_map(move |i| u64::try_from(base + WHEEL[i] as u128).ok())
+        })
+    }
+}
+
+/// Returns the primes <= bound in the increasing order.
+pub fn primes(bound: usize) -> Vec<usize> {
+    Primes::new().take_while(|&p| p <= bound).collect()
 }
 
-fn is_prime(a: usize) -> bool {
-    if a <= 1 {
-        return false;
+/// Returns the primes in the range in the increasing order.
+///
+/// The whole range is sieved at once with a wheel modulo 30, which takes (end - start) / 30 bytes.
+/// If the range is shorter than sqrt(end) / 64, it is sieved only with the primes up to 2^16,
+/// and the survivors are tested with `is_prime_u64`, so that short ranges near 10^18 take milliseconds.
+/// Otherwise the sieving primes up to sqrt(end) are generated segment by segment and not kept.
+pub fn primes_in(range: Range<u64>) -> Vec<u64> {
+    let mut result: Vec<u64> = [2, 3, 5]
+        .into_iter()
+        .filter(|p| range.contains(p))
+        .collect();
+    if range.end <= 7 || range.is_empty() {
+        return result;
     }
-    let mut d = 2;
-    while d * d <= a {
-        if a % d == 0 {
-            return false;
+    let base = range.start / 30 * 30;
+    let len = (range.end - base).div_ceil(30) as usize;
+    if base == 0 {
+        let sieve = Sieve::from_zero(len);
+        result.extend(sieve.iter().filter(|p| range.contains(p)));
+        return result;
+    }
+    let limit = isqrt(range.end - 1);
+    let short = limit > PRESIEVE_BOUND && range.end - range.start < limit / 64;
+    let mut sieve = Sieve::new(base, len);
+    for_each_sieving_prime(if short { PRESIEVE_BOUND } else { limit }, |p| {
+        sieve.cross_off(p)
+    });
+    let candidates = sieve.iter().filter(|p| range.contains(p));
+    if short {
+        result.extend(candidates.filter(|&p| is_prime_u64(p)));
+    } else {
+        result.extend(candidates);
+    }
+    result
+}
+
+/// Calls f with the primes in [7, bound] in the increasing order.
+/// [0, bound] is sieved in segments of `SEGMENT_BYTES` bytes, and only the primes up to sqrt(bound) are kept.
+fn for_each_sieving_prime(bound: u64, mut f: impl FnMut(u64)) {
+    let root = isqrt(bound);
+    let small: Vec<u64> = Sieve::from_zero(root as usize / 30 + 1).iter().collect();
+    let mut base = 0;
+    while base <= bound {
+        let len = SEGMENT_BYTES.min((bound - base) / 30 + 1);
+        let segment = if base == 0 {
+            Sieve::from_zero(len as usize)
+        } else {
+            let mut segment = Sieve::new(base, len as usize);
+            let end = segment.end();
+            for &p in small
+                .iter()
+                .take_while(|&&p| (p as u128) * (p as u128) < end)
+            {
+                segment.cross_off(p);
+            }
+            segment
+        };
+        for p in segment.iter().take_while(|&p| p <= bound) {
+            f(p);
         }
-        d += 1;
+        base += 30 * len;
     }
-    true
 }
 
 /// An iterator that returns primes in the increasing order.
 ///
-/// Complexity: O(n^1.5)
+/// The integers are sieved lazily in segments with a wheel modulo 30.
+/// The sieving primes up to the square root of the current segment are kept,
+/// which takes O(sqrt(n) / log n) memory; use `primes_in` for short ranges of large integers.
 pub struct Primes {
-    now: usize,
+    /// The primes in the current segment that are not returned yet.
+    segment: std::vec::IntoIter<u64>,
+    /// The start of the next segment, or None if the last segment reached 2^64.
+    next: Option<u64>,
+    /// Primes below this bound are not returned.
+    start: u64,
+    segment_bytes: u64,
+    /// The sieving primes >= 7 found so far, and the iterator that finds more of them.
+    sieving: Vec<u32>,
+    sieving_source: Option<Box<Primes>>,
 }
 
 impl Primes {
     pub fn new() -> Self {
-        Self { now: 2 }
+        Self::starting_at(0)
+    }
+
+    /// Returns an iterator of the primes >= start.
+    pub fn starting_at(start: usize) -> Self {
+        let start = start as u64;
+        let small: Vec<u64> = [2, 3, 5].into_iter().filter(|&p| p >= start).collect();
+        Self {
+            segment: small.into_iter(),
+            next: Some(start / 30 * 30),
+            start,
+            segment_bytes: FIRST_SEGMENT_BYTES,
+            sieving: vec![],
+            sieving_source: None,
+        }
+    }
+
+    /// Sieves the next segment. Returns false if there are no more segments.
+    fn sieve_next_segment(&mut self) -> bool {
+        let Some(base) = self.next else {
+            return false;
+        };
+        let len = self.segment_bytes.min((u64::MAX - base) / 30 + 1);
+        let end = base as u128 + 30 * len as u128;
+        self.next = u64::try_from(end).ok();
+        self.segment_bytes = (2 * self.segment_bytes).min(SEGMENT_BYTES);
+        let sieve = if base == 0 {
+            Sieve::from_zero(len as usize)
+        } else {
+            let limit = isqrt(end - 1) as u64;
+            self.extend_sieving_primes(limit);
+            let mut sieve = Sieve::new(base, len as usize);
+            for &p in self.sieving.iter().take_while(|&&p| p as u64 <= limit) {
+                sieve.cross_off(p as u64);
+            }
+            sieve
+        };
+        let start = self.start;
+        let primes: Vec<u64> = sieve.iter().filter(|&p| p >= start).collect();
+        self.segment = primes.into_iter();
+        true
+    }
+
+    /// Finds the sieving primes up to limit, or up to 2^32 if limit is larger.
+    fn extend_sieving_primes(&mut self, limit: u64) {
+        let source = self
+            .sieving_source
+            .get_or_insert_with(|| Box::new(Primes::starting_at(7)));
+        while self.sieving.last().map_or(true, |&p| (p as u64) < limit) {
+            match source.next().map(u32::try_from) {
+                Some(Ok(p)) => self.sieving.push(p),
+                _ => break,
+            }
+        }
     }
 }
 
@@ -51,18 +254,21 @@ impl Default for Primes {
 impl Iterator for Primes {
     type Item = usize;
     fn next(&mut self) -> Option<Self::Item> {
-        let mut now = self.now;
-        while !is_prime(now) {
-            now += 1;
+        loop {
+            if let Some(p) = self.segment.next() {
+                return usize::try_from(p).ok();
+            }
+            if !self.sieve_next_segment() {
+                return None;
+            }
         }
-        self.now = now + 1;
-        Some(now)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::is_prime_u64;
 
     #[test]
     fn primes_works_1() {
@@ -85,6 +291,15 @@ mod tests {
         assert_eq!(primes(bound), expected);
     }
 
+    #[test]
+    fn primes_works_4() {
+        assert_eq!(primes(0), vec![]);
+        assert_eq!(primes(1), vec![]);
+        assert_eq!(primes(2), vec![2]);
+        // pi(10^6) = 78498, across several segments
+        assert_eq!(primes(1_000_000).len(), 78498);
+    }
+
     #[test]
     fn primes_iterator_works_0() {
         let iter = Primes::new();
@@ -92,4 +307,42 @@ mod tests {
         let expected = vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47];
         assert_eq!(primes, expected);
     }
+
+    #[test]
+    fn primes_iterator_works_1() {
+        let primes: Vec<usize> = Primes::starting_at(4).take(3).collect();
+        assert_eq!(primes, vec![5, 7, 11]);
+        let start = 1_000_000_000_000usize;
+        let expected: Vec<usize> = (start..start + 10_000)
+            .filter(|&n| is_prime_u64(n as u64))
+            .collect();
+        let primes: Vec<usize> = Primes::starting_at(start)
+            .take_while(|&p| p < start + 10_000)
+            .collect();
+        assert_eq!(primes, expected);
+    }
+
+    #[test]
+    fn primes_in_works() {
+        for start in 0..40 {
+            for end in start..100 {
+                let expected: Vec<u64> = (start..end).filter(|&n| is_prime_u64(n)).collect();
+                assert_eq!(primes_in(start..end), expected, "{}..{}", start, end);
+            }
+        }
+        let start = 999_999_999_000u64;
+        let expected: Vec<u64> = (start..start + 100_000)
+            .filter(|&n| is_prime_u64(n))
+            .collect();
+        assert_eq!(primes_in(start..start + 100_000), expected);
+        // Short enough to be tested with is_prime_u64 after the pre-sieve
+        let start = 1_000_000_000_000_000_000u64;
+        let expected: Vec<u64> = (start..start + 10_000)
+            .filter(|&n| is_prime_u64(n))
+            .collect();
+        assert_eq!(primes_in(start..start + 10_000), expected);
+        let start = u64::MAX - 100;
+        let expected: Vec<u64> = (start..u64::MAX).filter(|&n| is_prime_u64(n)).collect();
+        assert_eq!(primes_in(start..u64::MAX), expected);
+    }
 }
@@ -0,0 +1,192 @@
+use std::fmt::Debug;
+use std::ops::{Add, BitAnd, Div, Mul, Rem, Shl, Shr, Sub};
+
+/// An unsigned machine word, u64 or u128.
+///
+/// Operations on words never allocate, unlike those on `BigInt`.
+pub trait Word:
+    Copy
+    + Ord
+    + Debug
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Rem<Output = Self>
+    + BitAnd<Output = Self>
+    + Shl<u32, Output = Self>
+    + Shr<u32, Output = Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+    const MAX: Self;
+    const BITS: u32;
+    fn from_u64(x: u64) -> Self;
+    fn trailing_zeros(self) -> u32;
+    fn leading_zeros(self) -> u32;
+    fn wrapping_add(self, other: Self) -> Self;
+    fn wrapping_sub(self, other: Self) -> Self;
+    fn wrapping_mul(self, other: Self) -> Self;
+    fn overflowing_add(self, other: Self) -> (Self, bool);
+    /// Returns the low and high words of self * other.
+    fn mul_wide(self, other: Self) -> (Self, Self);
+}
+
+impl Word for u64 {
+    const ZERO: Self = 0;
+    const ONE: Self = 1;
+    const MAX: Self = u64::MAX;
+    const BITS: u32 = 64;
+    fn from_u64(x: u64) -> Self {
+        x
+    }
+    fn trailing_zeros(self) -> u32 {
+        self.trailing_zeros()
+    }
+    fn leading_zeros(self) -> u32 {
+        self.leading_zeros()
+    }
+    fn wrapping_add(self, other: Self) -> Self {
+        self.wrapping_add(other)
+    }
+    fn wrapping_sub(self, other: Self) -> Self {
+        self.wrapping_sub(other)
+    }
+    fn wrapping_mul(self, other: Self) -> Self {
+        self.wrapping_mul(other)
+    }
+    fn overflowing_add(self, other: Self) -> (Self, bool) {
+        self.overflowing_add(other)
+    }
+    fn mul_wide(self, other: Self) -> (Self, Self) {
+        let prod = self as u128 * other as u128;
+        (prod as u64, (prod >> 64) as u64)
+    }
+}
+
+impl Word for u128 {
+    const ZERO: Self = 0;
+    const ONE: Self = 1;
+    const MAX: Self = u128::MAX;
+    const BITS: u32 = 128;
+    fn from_u64(x: u64) -> Self {
+        x as u128
+    }
+    fn trailing_zeros(self) -> u32 {
+        self.trailing_zeros()
+    }
+    fn leading_zeros(self) -> u32 {
+        self.leading_zeros()
+    }
+    fn wrapping_add(self, other: Self) -> Self {
+        self.wrapping_add(other)
+    }
+    fn wrapping_sub(self, other: Self) -> Self {
+        self.wrapping_sub(other)
+    }
+    fn wrapping_mul(self, other: Self) -> Self {
+        self.wrapping_mul(other)
+    }
+    fn overflowing_add(self, other: Self) -> (Self, bool) {
+        self.overflowing_add(other)
+    }
+    fn mul_wide(self, other: Self) -> (Self, Self) {
+        const MASK: u128 = u64::MAX as u128;
+        let (a0, a1) = (self & MASK, self >> 64);
+        let (b0, b1) = (other & MASK, other >> 64);
+        let p00 = a0 * b0;
+        let p01 = a0 * b1;
+        let p10 = a1 * b0;
+        let p11 = a1 * b1;
+        // The middle column cannot overflow: it is at most 3 (2^64 - 1).
+        let mid = (p00 >> 64) + (p01 & MASK) + (p10 & MASK);
+        let lo = (p00 & MASK) | (mid << 64);
+        let hi = p11 + (p01 >> 64) + (p10 >> 64) + (mid >> 64);
+        (lo, hi)
+    }
+}
+
+/// Computes gcd(a, b) with the binary algorithm.
+pub fn gcd<T: Word>(mut a: T, mut b: T) -> T {
+    if a == T::ZERO {
+        return b;
+    }
+    if b == T::ZERO {
+        return a;
+    }
+    let shift = (a.trailing_zeros()).min(b.trailing_zeros());
+    a = a >> a.trailing_zeros();
+    loop {
+        b = b >> b.trailing_zeros();
+        if a > b {
+            std::mem::swap(&mut a, &mut b);
+        }
+        b = b - a;
+        if b == T::ZERO {
+            return a << shift;
+        }
+    }
+}
+
+/// Computes floor(sqrt(n)).
+pub fn isqrt<T: Word>(n: T) -> T {
+    if n < T::from_u64(2) {
+        return n;
+    }
+    // Newton's method from an initial value above sqrt(n).
+    let bits = T::BITS - n.leading_zeros();
+    let mut x = T::ONE << bits.div_ceil(2);
+    loop {
+        let y = (x + n / x) >> 1;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+/// Returns sqrt(n) if n is a perfect square.
+pub fn exact_sqrt<T: Word>(n: T) -> Option<T> {
+    let r = isqrt(n);
+    if r * r == n {
+        Some(r)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mul_wide_works() {
+        let a = u128::MAX;
+        assert_eq!(a.mul_wide(a), (1, u128::MAX - 1));
+        let b = (1u128 << 100) + 12345;
+        let c = (1u128 << 90) + 678;
+        // b * c = 2^190 + 678 * 2^100 + 12345 * 2^90 + 12345 * 678
+        let (lo, hi) = b.mul_wide(c);
+        assert_eq!(hi, 1 << 62);
+        assert_eq!(lo, (678 << 100) + (12345 << 90) + 12345 * 678);
+    }
+
+    #[test]
+    fn isqrt_works() {
+        for n in 0u64..1000 {
+            let r = isqrt(n);
+            assert!(r * r <= n && (r + 1) * (r + 1) > n, "n = {}", n);
+        }
+        assert_eq!(isqrt(u64::MAX), u32::MAX as u64);
+        assert_eq!(isqrt(u128::MAX), u64::MAX as u128);
+        assert_eq!(exact_sqrt(1u128 << 100), Some(1 << 50));
+        assert_eq!(exact_sqrt((1u128 << 100) + 1), None);
+    }
+
+    #[test]
+    fn gcd_works() {
+        assert_eq!(gcd(12u64, 18), 6);
+        assert_eq!(gcd(0u64, 7), 7);
+        assert_eq!(gcd(1u128 << 100, 3 << 90), 1 << 90);
+    }
+}
@@ -28,6 +28,7 @@ pub fn determinant(a: &[Vec<BigRational>]) -> BigRational {
         }
         for j in i + 1..n {
             let factor = &a[j][i] / &a[i][i];
+            #[allow(clippy::needless_range_loop)]
             for k in i..n {
                 let tmp = &factor * &a[i][k];
                 a[j][k] -= tmp;
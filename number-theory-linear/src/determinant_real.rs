@@ -21,6 +21,7 @@ pub fn determinant_real(a: &[Vec<f64>]) -> f64 {
         }
         for j in i + 1..n {
             let factor = a[j][i] / a[i][i];
+            #[allow(clippy::needless_range_loop)]
             for k in i..n {
                 let tmp = factor * a[i][k];
                 a[j][k] -= tmp;
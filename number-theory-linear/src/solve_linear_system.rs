@@ -22,6 +22,7 @@ pub fn solve_linear_system<Int: Clone + Integer + NumAssign>(
     let mut col = 0;
     for row in 0..n {
         let mut nxt = n;
+        #[allow(clippy::needless_range_loop)]
         for i in col..n {
             if a[row][i] != Ratio::zero() {
                 nxt = i;
@@ -1,4 +1,4 @@
-use crate::polynomial::Polynomial;
+use crate::polynomial::{div_rem_bigrational, Polynomial};
 use num::{traits::Pow, BigInt, BigRational, One, Zero};
 use std::ops::{Add, Mul, Sub};
 
@@ -50,6 +50,37 @@ impl Algebraic {
         expr.extend_from_slice(&vec![BigRational::from_integer(0.into()); deg - expr.len()]);
         expr
     }
+
+    /// Computes the multiplicative inverse, or returns None if self = 0.
+    ///
+    /// The extended Euclidean algorithm on min_poly and expr gives u expr + v min_poly = 1, and u is the inverse.
+    pub fn inv(&self) -> Option<Self> {
+        if self.expr.is_zero() {
+            return None;
+        }
+        let min_poly = Polynomial::from_raw(
+            self.min_poly
+                .dat
+                .iter()
+                .map(|c| BigRational::from_integer(c.clone()))
+                .collect(),
+        );
+        // r = s expr (mod min_poly)
+        let (mut r0, mut s0) = (min_poly, Polynomial::zero());
+        let (mut r1, mut s1) = (self.expr.clone(), Polynomial::from_mono(BigRational::one()));
+        while r1.deg() > 0 {
+            let (q, r2) = div_rem_bigrational(&r0, &r1);
+            let s2 = &s0 - &(&q * &s1);
+            (r0, s0) = (r1, s1);
+            (r1, s1) = (r2, s2);
+        }
+        // r1 is a nonzero constant, because min_poly is irreducible.
+        let c = r1.coef_at(0);
+        Some(Algebraic {
+            min_poly: self.min_poly.clone(),
+            expr: Polynomial::from_raw(s1.dat.into_iter().map(|x| x / &c).collect()),
+        })
+    }
 }
 
 // Operations on Algebraic assume that all numbers' min_poly are the same.
@@ -187,6 +218,7 @@ impl Pow<BigInt> for &Algebraic {
 mod tests {
     use super::Algebraic;
     use crate::polynomial::Polynomial;
+    use num::BigRational;
     #[test]
     fn test_alg_mul() {
         // Let theta be an algebraic number whose minimal polynomial is x^3 + x + 1.
@@ -199,4 +231,22 @@ mod tests {
             - Algebraic::from_int(f.clone(), 1);
         assert_eq!(result, Algebraic::from_int(f, 0));
     }
+    #[test]
+    fn test_alg_inv() {
+        // theta^3 + theta + 1 = 0, so theta^{-1} = -theta^2 - 1.
+        let f = Polynomial::from_raw(vec![1.into(), 1.into(), 0.into(), 1.into()]);
+        let theta = Algebraic::new(f.clone());
+        let inv = theta.inv().unwrap();
+        let expected: Vec<BigRational> = [-1, 0, -1]
+            .into_iter()
+            .map(|c| BigRational::from_integer(c.into()))
+            .collect();
+        assert_eq!(inv.expr, Polynomial::from_raw(expected));
+        let eta = &theta * &theta + Algebraic::from_int(f.clone(), 3);
+        assert_eq!(
+            &eta * &eta.inv().unwrap(),
+            Algebraic::from_int(f.clone(), 1)
+        );
+        assert_eq!(Algebraic::from_int(f, 0).inv(), None);
+    }
 }
@@ -0,0 +1,216 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use num::{BigInt, Integer, One};
+
+/// Computes Euler's totient function phi(n).
+///
+/// The functions in this module take the factorization of n as a list of (p, e) with distinct primes p,
+/// e.g. the one returned by `ecm::factorize`. The empty list stands for n = 1.
+pub fn euler_phi(factors: &[(BigInt, u64)]) -> BigInt {
+    factors
+        .iter()
+        .map(|(p, e)| (p - 1) * num::pow(p.clone(), *e as usize - 1))
+        .product()
+}
+
+/// Computes the Carmichael function lambda(n), the exponent of (Z/nZ)^*.
+pub fn carmichael_lambda(factors: &[(BigInt, u64)]) -> BigInt {
+    factors.iter().fold(BigInt::one(), |acc, (p, e)| {
+        let l = if p == &BigInt::from(2) && *e >= 3 {
+            BigInt::one() << (e - 2)
+        } else {
+            (p - 1) * num::pow(p.clone(), *e as usize - 1)
+        };
+        acc.lcm(&l)
+    })
+}
+
+/// Computes sigma_k(n), the sum of the k-th powers of the divisors of n.
+pub fn divisor_sigma(factors: &[(BigInt, u64)], k: u32) -> BigInt {
+    factors
+        .iter()
+        .map(|(p, e)| {
+            let pk = num::pow(p.clone(), k as usize);
+            // 1 + p^k + ... + p^{ke}
+            (0..*e).fold(BigInt::one(), |sum, _| sum * &pk + 1)
+        })
+        .product()
+}
+
+/// Computes tau(n), the number of divisors of n.
+pub fn divisor_count(factors: &[(BigInt, u64)]) -> BigInt {
+    factors.iter().map(|&(_, e)| BigInt::from(e) + 1).product()
+}
+
+/// Computes the Möbius function mu(n).
+pub fn moebius(factors: &[(BigInt, u64)]) -> i32 {
+    if factors.iter().any(|&(_, e)| e >= 2) {
+        return 0;
+    }
+    if factors.len() % 2 == 0 {
+        1
+    } else {
+        -1
+    }
+}
+
+/// Computes omega(n), the number of distinct prime factors of n.
+pub fn omega(factors: &[(BigInt, u64)]) -> usize {
+    factors.len()
+}
+
+/// Computes Omega(n), the number of prime factors of n counted with multiplicity.
+pub fn big_omega(factors: &[(BigInt, u64)]) -> u64 {
+    factors.iter().map(|&(_, e)| e).sum()
+}
+
+/// Returns an iterator of the divisors of n in no particular order.
+pub fn divisors(factors: &[(BigInt, u64)]) -> Divisors {
+    Divisors {
+        factors: factors.to_vec(),
+        exponents: vec![0; factors.len()],
+        current: Some(BigInt::one()),
+    }
+}
+
+/// Returns an iterator of the divisors of n in the increasing order.
+pub fn divisors_sorted(factors: &[(BigInt, u64)]) -> SortedDivisors {
+    let mut heap = BinaryHeap::new();
+    heap.push(Reverse((BigInt::one(), 0, 0)));
+    SortedDivisors {
+        factors: factors.to_vec(),
+        heap,
+    }
+}
+
+/// An iterator of the divisors of n, which enumerates the exponent vectors like an odometer.
+/// This takes O(omega(n)) memory.
+pub struct Divisors {
+    factors: Vec<(BigInt, u64)>,
+    exponents: Vec<u64>,
+    /// The divisor for `exponents`, or None if the enumeration is over.
+    current: Option<BigInt>,
+}
+
+impl Iterator for Divisors {
+    type Item = BigInt;
+
+    fn next(&mut self) -> Option<BigInt> {
+        let result = self.current.take()?;
+        let mut next = result.clone();
+        for (i, (p, e)) in self.factors.iter().enumerate() {
+            if self.exponents[i] < *e {
+                self.exponents[i] += 1;
+                next *= p;
+                self.current = Some(next);
+                break;
+            }
+            next /= num::pow(p.clone(), *e as usize);
+            self.exponents[i] = 0;
+        }
+        Some(result)
+    }
+}
+
+/// An iterator of the divisors of n in the increasing order.
+///
+/// Every divisor d > 1 has a unique parent d / p_i, where p_i is the last prime factor of d in `factors`.
+/// A divisor is pushed into a heap when its parent is popped,
+/// so the heap holds O(omega(n)) divisors per divisor returned so far.
+pub struct SortedDivisors {
+    factors: Vec<(BigInt, u64)>,
+    /// (d, i, e), where i is the index of the last prime factor of d and e is its exponent in d.
+    /// For d = 1, (i, e) = (0, 0).
+    heap: BinaryHeap<Reverse<(BigInt, usize, u64)>>,
+}
+
+impl Iterator for SortedDivisors {
+    type Item = BigInt;
+
+    fn next(&mut self) -> Option<BigInt> {
+        let Reverse((d, last, e)) = self.heap.pop()?;
+        for j in last..self.factors.len() {
+            let (p, max_e) = &self.factors[j];
+            let e_j = if j == last { e } else { 0 };
+            if e_j < *max_e {
+                self.heap.push(Reverse((&d * p, j, e_j + 1)));
+            }
+        }
+        Some(d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ecm::factorize;
+
+    #[test]
+    fn arithmetic_functions_work() {
+        let n = BigInt::from(720720);
+        let factors = factorize(&n);
+        assert_eq!(euler_phi(&factors), BigInt::from(138240));
+        assert_eq!(carmichael_lambda(&factors), BigInt::from(60));
+        assert_eq!(divisor_sigma(&factors, 0), BigInt::from(240));
+        assert_eq!(divisor_sigma(&factors, 1), BigInt::from(3249792));
+        assert_eq!(divisor_count(&factors), BigInt::from(240));
+        assert_eq!(moebius(&factors), 0);
+        assert_eq!(omega(&factors), 6);
+        assert_eq!(big_omega(&factors), 10);
+        assert_eq!(moebius(&factorize(&BigInt::from(30))), -1);
+        assert_eq!(moebius(&[]), 1);
+        assert_eq!(euler_phi(&[]), BigInt::one());
+        // The Carmichael function of 2^e.
+        let factors = [(BigInt::from(2), 5)];
+        assert_eq!(carmichael_lambda(&factors), BigInt::from(8));
+        assert_eq!(euler_phi(&factors), BigInt::from(16));
+    }
+
+    #[test]
+    fn arithmetic_functions_agree_with_u64() {
+        use number_theory_elementary::{
+            big_omega_u64, carmichael_lambda_u64, divisor_sigma_u64, euler_phi_u64, moebius_u64,
+        };
+        for n in 1u64..500 {
+            let factors = factorize(&n.into());
+            assert_eq!(euler_phi(&factors), euler_phi_u64(n).into());
+            assert_eq!(carmichael_lambda(&factors), carmichael_lambda_u64(n).into());
+            assert_eq!(
+                divisor_sigma(&factors, 3),
+                divisor_sigma_u64(n, 3).unwrap().into()
+            );
+            assert_eq!(moebius(&factors), moebius_u64(n));
+            assert_eq!(big_omega(&factors), big_omega_u64(n));
+        }
+    }
+
+    #[test]
+    fn divisors_work() {
+        assert_eq!(divisors(&[]).collect::<Vec<_>>(), vec![BigInt::one()]);
+        assert_eq!(
+            divisors_sorted(&[]).collect::<Vec<_>>(),
+            vec![BigInt::one()]
+        );
+        let n = BigInt::from(720720);
+        let factors = factorize(&n);
+        let sorted: Vec<BigInt> = divisors_sorted(&factors).collect();
+        let mut unsorted: Vec<BigInt> = divisors(&factors).collect();
+        assert_eq!(sorted.len(), 240);
+        assert!(sorted.windows(2).all(|w| w[0] < w[1]));
+        assert!(sorted.iter().all(|d| (&n % d) == BigInt::from(0)));
+        unsorted.sort();
+        assert_eq!(unsorted, sorted);
+        // Lazy enumeration of the divisors of a number with 2^64 divisors.
+        let primes = number_theory_elementary::primes(320);
+        let factors: Vec<(BigInt, u64)> = primes[..64].iter().map(|&p| (p.into(), 1)).collect();
+        let first: Vec<BigInt> = divisors_sorted(&factors).take(8).collect();
+        let expected: Vec<BigInt> = [1, 2, 3, 5, 6, 7, 10, 11]
+            .into_iter()
+            .map(BigInt::from)
+            .collect();
+        assert_eq!(first, expected);
+        assert_eq!(divisors(&factors).take(1000).count(), 1000);
+    }
+}
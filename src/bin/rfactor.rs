@@ -1,10 +1,15 @@
+use std::io;
 use std::io::Write;
+use std::path::PathBuf;
 use std::str::FromStr;
-use std::{io, time::Instant};
+use std::time::{Duration, Instant};
 
 use num::BigInt;
-use rust_number_theory::ecm::EcmStats;
+use rust_number_theory::certificate::{self, Certificate};
+use rust_number_theory::ecm::{ECMConfig, EcmStats};
 use rust_number_theory::ecm_parallel;
+use rust_number_theory::ecpp;
+use rust_number_theory::prime;
 
 #[derive(bpaf::Bpaf)]
 #[bpaf(options)]
@@ -13,6 +18,28 @@ struct Cli {
     verbose: bool,
 
     json: bool,
+    /// Proves that the prime factors are primes with the N-1 method or ECPP. With --json, the certificates are included in the output
+    certify: bool,
+    /// The number of threads to run ECM curves on (default: the number of CPU cores)
+    #[bpaf(argument("N"))]
+    threads: Option<usize>,
+    /// Gives up on a cofactor after N curves and reports it as composite
+    #[bpaf(argument("N"))]
+    max_curves: Option<u64>,
+    /// Stops after SECONDS seconds and reports the remaining cofactors as composite
+    #[bpaf(
+        argument("SECONDS"),
+        guard(is_valid_timeout, "SECONDS must be between 0 and 1.8e19"),
+        optional
+    )]
+    timeout: Option<f64>,
+    /// Prints the smallest prime greater than the integer instead of factorizing it
+    next_prime: bool,
+    /// Prints the largest prime less than the integer instead of factorizing it
+    prev_prime: bool,
+    /// Saves the progress to FILE, and resumes from it if it exists
+    #[bpaf(argument("FILE"))]
+    checkpoint: Option<PathBuf>,
     /// Optional integer argument to factorize
     #[bpaf(positional)]
     integer: Option<String>,
@@ -40,18 +67,64 @@ fn main() {
     };
 
     let value = BigInt::from_str(&value).unwrap();
+    if cli.next_prime {
+        println!("{}", prime::next_prime(&value));
+        return;
+    }
+    if cli.prev_prime {
+        match prime::prev_prime(&value) {
+            Some(p) => println!("{p}"),
+            None => {
+                eprintln!("No primes are less than {value}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
     let start = Instant::now();
-    let (result, ecm_stats) = ecm_parallel::factorize_verbose(&value, cli.verbose);
+    let threads = cli.threads.unwrap_or_else(ecm_parallel::default_threads);
+    let conf = ECMConfig {
+        verbose: cli.verbose,
+        max_curves: cli.max_curves,
+        // A deadline too far to represent is the same as no deadline.
+        deadline: cli
+            .timeout
+            .and_then(|t| start.checked_add(Duration::from_secs_f64(t))),
+        ..Default::default()
+    };
+    let (result, ecm_stats) = if let Some(path) = cli.checkpoint.as_deref() {
+        match ecm_parallel::factorize_with_checkpoint(&value, &conf, threads, path) {
+            Ok(result) => result,
+            Err(err) => {
+                panic!("{}: {err}", path.display());
+            }
+        }
+    } else {
+        ecm_parallel::factorize_with_config(&value, &conf, threads)
+    };
     let elapsed = start.elapsed();
     present(cli, result, ecm_stats, elapsed);
 }
 
-fn present(
-    cli: Cli,
-    result: Vec<(BigInt, u64)>,
-    ecm_stats: EcmStats,
-    elapsed: std::time::Duration,
-) {
+fn is_valid_timeout(t: &f64) -> bool {
+    Duration::try_from_secs_f64(*t).is_ok()
+}
+
+fn present(cli: Cli, result: Vec<(BigInt, u64)>, ecm_stats: EcmStats, elapsed: Duration) {
+    // None if not asked to certify. Some(None) if p could not be proven prime.
+    let certificates: Vec<Option<Option<Certificate>>> = result
+        .iter()
+        .map(|(p, _)| {
+            if !cli.certify || ecm_stats.composite.contains(p) {
+                return None;
+            }
+            let cert = certificate::certify(p).or_else(|| ecpp::prove(p));
+            if cert.is_none() && !cli.json {
+                eprintln!("Could not prove that {p} is a prime");
+            }
+            Some(cert)
+        })
+        .collect();
     if cli.json {
         #[derive(serde::Serialize)]
         struct Entry {
@@ -59,13 +132,17 @@ fn present(
             e: u64,
             #[serde(skip_serializing_if = "std::ops::Not::not")]
             is_composite: bool,
+            /// null if p could not be proven prime
+            #[serde(skip_serializing_if = "Option::is_none")]
+            certificate: Option<Option<Certificate>>,
         }
         let mut entries = Vec::new();
-        for (p, e) in result {
+        for ((p, e), certificate) in result.into_iter().zip(certificates) {
             entries.push(Entry {
                 p: p.to_string(),
                 e,
-                is_composite: false,
+                is_composite: ecm_stats.composite.contains(&p),
+                certificate,
             });
         }
         let mut object = serde_json::Map::new();
@@ -94,6 +171,11 @@ fn present(
         }
         println!("{}", serde_json::to_string_pretty(&object).unwrap(),);
     } else {
+        if !ecm_stats.composite.is_empty() {
+            let composite: Vec<String> =
+                ecm_stats.composite.iter().map(|c| c.to_string()).collect();
+            eprintln!("Not fully factored; composite: {}", composite.join(" "));
+        }
         let mut first = true;
         for (p, e) in result {
             if !first {
@@ -0,0 +1,351 @@
+use num::{BigInt, Integer, One, Signed, ToPrimitive, Zero};
+use number_theory_elementary::is_prime_u64;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::ecm::{self, ECMConfig, Ell, Point};
+use crate::prime;
+
+/// A certificate that n is a prime, checked by `verify`.
+///
+/// Certificates are serialized as nested JSON objects. Integers are written as decimal strings:
+///
+/// ```text
+/// {
+///   "n": "170141183460469231731687303715884105727",
+///   "type": "n_minus_1",
+///   "factors": [
+///     { "q": { "n": "2", "type": "small" }, "e": 1, "a": "3" },
+///     ...
+///   ]
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Certificate {
+    #[serde(with = "decimal")]
+    pub n: BigInt,
+    #[serde(flatten)]
+    pub proof: Proof,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Proof {
+    /// n < 2^64. The verifier runs a deterministic Miller-Rabin test.
+    Small,
+    /// n - 1 = F R, where F is the product of q^e for the factors.
+    ///
+    /// For each factor, a^{n-1} = 1 (mod n) and gcd(a^{(n-1)/q} - 1, n) = 1 hold.
+    /// Then every prime factor of n is 1 mod F, so n is a prime if F^2 >= n (Pocklington).
+    /// If only F^3 >= n, write n = c_2 F^2 + c_1 F + 1 with 0 <= c_1 < F;
+    /// n is a prime if and only if c_1^2 - 4 c_2 is not a square (Brillhart-Lehmer-Selfridge).
+    /// If F = n - 1, this is Pratt's certificate.
+    #[serde(rename = "n_minus_1")]
+    NMinus1 { factors: Vec<FactorWitness> },
+    /// A point P = (x, y) on the curve y^2 = x^3 + ax + b (mod n) with `[m]P = O` and `[m/q]P != O`,
+    /// where q divides m and q > (n^{1/4} + 1)^2 is a prime.
+    /// If n were composite, `[m/q]P` would be O modulo the smallest prime factor of n (Goldwasser-Kilian).
+    /// See `ecpp::prove` for how the curve is found.
+    Ecpp {
+        #[serde(with = "decimal")]
+        a: BigInt,
+        #[serde(with = "decimal")]
+        b: BigInt,
+        #[serde(with = "decimal")]
+        x: BigInt,
+        #[serde(with = "decimal")]
+        y: BigInt,
+        #[serde(with = "decimal")]
+        m: BigInt,
+        q: Box<Certificate>,
+    },
+}
+
+/// A prime factor q^e of n - 1 with its certificate and the witness a.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FactorWitness {
+    pub q: Certificate,
+    pub e: u64,
+    #[serde(with = "decimal")]
+    pub a: BigInt,
+}
+
+mod decimal {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(x: &BigInt, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&x.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigInt, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// The number of ECM curves tried on each cofactor of n - 1 before it is left unfactored.
+const CERTIFY_CURVES: u64 = 30;
+
+/// B1 for the p-1, p+1 and ECM stages on n - 1, which is suited to factors up to about 20 digits.
+/// B1 chosen from the size of n - 1 would make each curve take too long.
+const CERTIFY_B1: u64 = 10_000;
+
+/// Witnesses a are tried among 2, 3, ..., up to this bound.
+const WITNESS_BOUND: u32 = 1000;
+
+/// Tries to prove that n is a prime with the N-1 method.
+///
+/// n - 1 is factorized with `ecm::factorize_with_config` within a budget of `CERTIFY_CURVES` curves with B1 = `CERTIFY_B1`,
+/// and the prime factors found are certified recursively.
+/// The quadratic sieve is skipped under the budget, so a large cofactor without small factors makes this give up quickly,
+/// after which `ecpp::prove` can be tried instead.
+/// Returns None if n is not a prime, or if too little of n - 1 could be factorized.
+pub fn certify(n: &BigInt) -> Option<Certificate> {
+    if n <= &BigInt::one() {
+        return None;
+    }
+    if let Some(small) = n.to_u64() {
+        return is_prime_u64(small).then(|| Certificate {
+            n: n.clone(),
+            proof: Proof::Small,
+        });
+    }
+    if !prime::is_prime(n) {
+        return None;
+    }
+    let n_minus_1: BigInt = n - 1;
+    let conf = ECMConfig {
+        b1: CERTIFY_B1,
+        b2: 100 * CERTIFY_B1,
+        max_curves: Some(CERTIFY_CURVES),
+        ..Default::default()
+    };
+    let (factors, stats) = ecm::factorize_with_config(&n_minus_1, &conf);
+    let mut witnesses = vec![];
+    let mut f = BigInt::one();
+    for (q, e) in factors {
+        if stats.composite.contains(&q) {
+            continue;
+        }
+        let Some(q_cert) = certify(&q) else {
+            continue;
+        };
+        let a = find_witness(n, &q)?;
+        f *= num::pow(q, e as usize);
+        witnesses.push(FactorWitness { q: q_cert, e, a });
+    }
+    let cert = Certificate {
+        n: n.clone(),
+        proof: Proof::NMinus1 { factors: witnesses },
+    };
+    (&f * &f * &f >= *n && check_n_minus_1_bound(n, &f)).then_some(cert)
+}
+
+/// Finds a with a^{n-1} = 1 (mod n) and gcd(a^{(n-1)/q} - 1, n) = 1.
+/// Returns None if a^{n-1} != 1 for some a, which proves that n is composite.
+fn find_witness(n: &BigInt, q: &BigInt) -> Option<BigInt> {
+    let n_minus_1: BigInt = n - 1;
+    for a in 2..WITNESS_BOUND {
+        let a = BigInt::from(a);
+        if !a.modpow(&n_minus_1, n).is_one() {
+            return None;
+        }
+        let b: BigInt = a.modpow(&(&n_minus_1 / q), n) - 1;
+        if b.gcd(n).is_one() {
+            return Some(a);
+        }
+    }
+    None
+}
+
+/// Given that every prime factor of n is 1 mod f and f^3 >= n, decides if n is a prime.
+fn check_n_minus_1_bound(n: &BigInt, f: &BigInt) -> bool {
+    if f * f >= *n {
+        return true;
+    }
+    let n_minus_1: BigInt = n - 1;
+    let (c2, rest) = n_minus_1.div_rem(&(f * f));
+    let c1 = rest / f;
+    let disc: BigInt = &c1 * &c1 - 4 * c2;
+    if disc < BigInt::zero() {
+        return true;
+    }
+    let root = disc.sqrt();
+    &root * &root != disc
+}
+
+/// Returns true if q > (n^{1/4} + 1)^2 is guaranteed, i.e. (floor(sqrt(q)) - 1)^4 > n.
+pub(crate) fn exceeds_ecpp_bound(q: &BigInt, n: &BigInt) -> bool {
+    let s: BigInt = q.sqrt() - 1;
+    s.is_positive() && num::pow(s, 4) > *n
+}
+
+/// Checks the conditions of `Proof::Ecpp` except the primality of q.
+fn check_ecpp(
+    n: &BigInt,
+    a: &BigInt,
+    b: &BigInt,
+    x: &BigInt,
+    y: &BigInt,
+    m: &BigInt,
+    q: &BigInt,
+) -> bool {
+    if n <= &BigInt::one() || !n.gcd(&BigInt::from(6)).is_one() {
+        return false;
+    }
+    let disc: BigInt = 4 * a * a * a + 27 * b * b;
+    if !disc.gcd(n).is_one() {
+        return false;
+    }
+    let (x, y) = (x.mod_floor(n), y.mod_floor(n));
+    if !(&y * &y - &x * &x * &x - a * &x - b).mod_floor(n).is_zero() {
+        return false;
+    }
+    if !q.is_positive() || !(m % q).is_zero() || !exceeds_ecpp_bound(q, n) {
+        return false;
+    }
+    let curve = Ell::new(a.mod_floor(n), n.clone());
+    let point = Point::new(x, y);
+    match point.mul(m / q, &curve) {
+        Ok(r) if !r.is_inf() => matches!(r.mul(q.clone(), &curve), Ok(o) if o.is_inf()),
+        _ => false,
+    }
+}
+
+/// Verifies a certificate. The certificates of the factors are verified recursively.
+pub fn verify(cert: &Certificate) -> bool {
+    let n = &cert.n;
+    match &cert.proof {
+        Proof::Small => n.to_u64().is_some_and(is_prime_u64),
+        Proof::NMinus1 { factors } => {
+            if n <= &BigInt::from(2) {
+                return false;
+            }
+            let n_minus_1: BigInt = n - 1;
+            let mut f = BigInt::one();
+            // (n - 1) / f. q is divided out one at a time, so that a huge e fails as soon as q stops dividing.
+            let mut rest = n_minus_1.clone();
+            for (i, FactorWitness { q, e, a }) in factors.iter().enumerate() {
+                let q_n = &q.n;
+                if *e == 0 || factors[..i].iter().any(|other| &other.q.n == q_n) || !verify(q) {
+                    return false;
+                }
+                for _ in 0..*e {
+                    let (quo, rem) = rest.div_rem(q_n);
+                    if !rem.is_zero() {
+                        return false;
+                    }
+                    rest = quo;
+                    f *= q_n;
+                }
+                if !a.modpow(&n_minus_1, n).is_one() {
+                    return false;
+                }
+                let b: BigInt = a.modpow(&(&n_minus_1 / q_n), n) - 1;
+                if !b.gcd(n).is_one() {
+                    return false;
+                }
+            }
+            &f * &f * &f >= *n && check_n_minus_1_bound(n, &f)
+        }
+        Proof::Ecpp { a, b, x, y, m, q } => check_ecpp(n, a, b, x, y, m, &q.n) && verify(q),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn certify_works() {
+        // 2^127 - 1: 2^127 - 2 = 2 * 3^3 * 7^2 * 19 * 43 * 73 * 127 * 337 * 5419 * 92737 * 649657 * 77158673929
+        let n = (BigInt::one() << 127u32) - 1;
+        let cert = certify(&n).unwrap();
+        assert!(verify(&cert));
+        let json = serde_json::to_string(&cert).unwrap();
+        assert!(json
+            .starts_with(r#"{"n":"170141183460469231731687303715884105727","type":"n_minus_1","#));
+        let parsed: Certificate = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, cert);
+
+        assert!(certify(&(&n * 3)).is_none());
+        assert!(certify(&BigInt::from(1_000_000_007)).is_some());
+        assert!(certify(&BigInt::from(1_000_000_008)).is_none());
+    }
+
+    #[test]
+    fn certify_gives_up_on_hard_n_minus_1() {
+        // n - 1 = 2 * 1000000000000000000000007 * 3000000000000000000004579, where neither factor is found
+        // within the budget. The 49-digit cofactor must not go to the quadratic sieve, which has no budget,
+        // so that `ecpp::prove` can be tried instead.
+        let n: BigInt = "6000000000000000000009200000000000000000000064107"
+            .parse()
+            .unwrap();
+        assert_eq!(certify(&n), None);
+    }
+
+    #[test]
+    fn verify_rejects_wrong_certificates() {
+        let n = (BigInt::one() << 127u32) - 1;
+        let cert = certify(&n).unwrap();
+        // Wrong n
+        let wrong = Certificate {
+            n: &n + 2,
+            ..cert.clone()
+        };
+        assert!(!verify(&wrong));
+        // Too small F
+        let Proof::NMinus1 { factors } = &cert.proof else {
+            panic!();
+        };
+        let wrong = Certificate {
+            n: n.clone(),
+            proof: Proof::NMinus1 {
+                factors: factors[..1].to_vec(),
+            },
+        };
+        assert!(!verify(&wrong));
+        // A bad witness: 1 has gcd(1 - 1, n) = n.
+        let mut bad = factors.clone();
+        bad[0].a = BigInt::one();
+        let wrong = Certificate {
+            n: n.clone(),
+            proof: Proof::NMinus1 { factors: bad },
+        };
+        assert!(!verify(&wrong));
+        // A composite certified as small
+        let wrong = Certificate {
+            n: BigInt::from(91),
+            proof: Proof::Small,
+        };
+        assert!(!verify(&wrong));
+        // F = 2 suffices for n = 7, since F^3 >= n.
+        // Exponents of 0 and u64::MAX are rejected, the latter without computing 2^e.
+        for e in [1, 0, u64::MAX] {
+            let json = format!(
+                r#"{{"n":"7","type":"n_minus_1","factors":[{{"q":{{"n":"2","type":"small"}},"e":{e},"a":"3"}}]}}"#
+            );
+            let cert: Certificate = serde_json::from_str(&json).unwrap();
+            assert_eq!(verify(&cert), e == 1);
+        }
+    }
+
+    #[test]
+    fn bls_works() {
+        // n - 1 = 10000019 * 31622866794 and n^{1/3} < 10000019 < n^{1/2},
+        // so the factor 10000019 alone proves n prime only with the Brillhart-Lehmer-Selfridge criterion.
+        let n = BigInt::from(316_229_268_774_469_087u64);
+        let q = BigInt::from(10_000_019u64);
+        let a = find_witness(&n, &q).unwrap();
+        let q = Certificate {
+            n: q,
+            proof: Proof::Small,
+        };
+        let cert = Certificate {
+            n,
+            proof: Proof::NMinus1 {
+                factors: vec![FactorWitness { q, e: 1, a }],
+            },
+        };
+        assert!(verify(&cert));
+    }
+}
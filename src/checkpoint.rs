@@ -0,0 +1,223 @@
+use num::{BigInt, One};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The state of a factorization, saved so that it can be resumed after an interruption.
+///
+/// A checkpoint is saved as JSON. Integers are written as decimal strings:
+///
+/// ```text
+/// {
+///   "n": "1000000000100000000002379000",
+///   "factors": [{ "p": "2", "e": 3 }, { "p": "5", "e": 3 }],
+///   "cofactors": [{ "n": "1000000000100000000002379", "e": 1, "curves": { "1200": 35 } }]
+/// }
+/// ```
+///
+/// The product of the factors and the cofactors, with multiplicity, is always n.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// The number being factorized.
+    pub n: BigInt,
+    /// Prime factors found so far, with their multiplicity.
+    pub factors: Vec<(BigInt, u64)>,
+    /// Factors that are not known to be prime yet.
+    pub cofactors: Vec<Cofactor>,
+}
+
+/// A factor that is not known to be prime yet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cofactor {
+    pub n: BigInt,
+    pub multiplicity: u64,
+    /// The number of ECM curves already tried on n, for each B1.
+    /// If this is not empty, the methods before ECM have failed on n and are not tried again.
+    pub curves: BTreeMap<u64, u64>,
+}
+
+impl Checkpoint {
+    /// The checkpoint of a factorization that has not started.
+    pub fn new(n: BigInt) -> Self {
+        let cofactors = vec![Cofactor {
+            n: n.clone(),
+            multiplicity: 1,
+            curves: BTreeMap::new(),
+        }];
+        Checkpoint {
+            n,
+            factors: vec![],
+            cofactors,
+        }
+    }
+
+    /// Returns true if no cofactors remain.
+    pub fn is_done(&self) -> bool {
+        self.cofactors.is_empty()
+    }
+
+    /// Reads a checkpoint from a file.
+    ///
+    /// Fails with `io::ErrorKind::InvalidData` if the file is not a checkpoint
+    /// or its factors and cofactors do not multiply to n.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        let file: CheckpointFile = serde_json::from_str(&json)?;
+        let checkpoint = Checkpoint::try_from(file)
+            .map_err(|()| io::Error::new(io::ErrorKind::InvalidData, "malformed checkpoint"))?;
+        let bits = checkpoint.n.bits();
+        let powers = checkpoint
+            .factors
+            .iter()
+            .map(|(p, e)| (p, *e))
+            .chain(checkpoint.cofactors.iter().map(|c| (&c.n, c.multiplicity)));
+        let mut product = BigInt::one();
+        for (p, e) in powers {
+            // Rejected before the power is computed, since p^e would not fit in memory for a large e.
+            if p.bits() > bits || e > bits {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "checkpoint factors do not multiply to n",
+                ));
+            }
+            product *= num::pow(p.clone(), e as usize);
+        }
+        if product != checkpoint.n {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "checkpoint factors do not multiply to n",
+            ));
+        }
+        Ok(checkpoint)
+    }
+
+    /// Writes a checkpoint to a file.
+    ///
+    /// The checkpoint is written to a temporary file next to path first and then renamed,
+    /// so that an interruption never leaves a truncated checkpoint.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(&CheckpointFile::from(self))?;
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, json + "\n")?;
+        fs::rename(&tmp, path)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CheckpointFile {
+    n: String,
+    factors: Vec<FactorEntry>,
+    cofactors: Vec<CofactorEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FactorEntry {
+    p: String,
+    e: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CofactorEntry {
+    n: String,
+    e: u64,
+    curves: BTreeMap<u64, u64>,
+}
+
+impl From<&Checkpoint> for CheckpointFile {
+    fn from(checkpoint: &Checkpoint) -> Self {
+        CheckpointFile {
+            n: checkpoint.n.to_string(),
+            factors: checkpoint
+                .factors
+                .iter()
+                .map(|(p, e)| FactorEntry {
+                    p: p.to_string(),
+                    e: *e,
+                })
+                .collect(),
+            cofactors: checkpoint
+                .cofactors
+                .iter()
+                .map(|c| CofactorEntry {
+                    n: c.n.to_string(),
+                    e: c.multiplicity,
+                    curves: c.curves.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<CheckpointFile> for Checkpoint {
+    type Error = ();
+    fn try_from(file: CheckpointFile) -> Result<Self, ()> {
+        let parse = |s: &str| s.parse::<BigInt>().map_err(|_| ());
+        let factors = file
+            .factors
+            .iter()
+            .map(|f| Ok((parse(&f.p)?, f.e)))
+            .collect::<Result<_, ()>>()?;
+        let cofactors = file
+            .cofactors
+            .into_iter()
+            .map(|c| {
+                Ok(Cofactor {
+                    n: parse(&c.n)?,
+                    multiplicity: c.e,
+                    curves: c.curves,
+                })
+            })
+            .collect::<Result<_, ()>>()?;
+        Ok(Checkpoint {
+            n: parse(&file.n)?,
+            factors,
+            cofactors,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_load_works() {
+        let path = std::env::temp_dir().join(format!("checkpoint-{}.json", std::process::id()));
+        let checkpoint = Checkpoint {
+            n: BigInt::from(1_000_000_000_100_000_000_002_379_000u128),
+            factors: vec![(2.into(), 3), (5.into(), 3)],
+            cofactors: vec![Cofactor {
+                n: BigInt::from(1_000_000_000_100_000_000_002_379u128),
+                multiplicity: 1,
+                curves: BTreeMap::from([(1200, 35)]),
+            }],
+        };
+        checkpoint.save(&path).unwrap();
+        assert_eq!(Checkpoint::load(&path).unwrap(), checkpoint);
+
+        // The factors do not multiply to n.
+        let wrong = Checkpoint {
+            factors: vec![(2.into(), 3)],
+            ..checkpoint.clone()
+        };
+        wrong.save(&path).unwrap();
+        let err = Checkpoint::load(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // An exponent this large is rejected without computing the power.
+        let huge = Checkpoint {
+            factors: vec![(2.into(), u64::MAX)],
+            ..checkpoint
+        };
+        huge.save(&path).unwrap();
+        let err = Checkpoint::load(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        fs::write(&path, "{}").unwrap();
+        assert!(Checkpoint::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,436 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use num::{BigInt, BigRational, Integer, One, Signed, Zero};
+
+use crate::algebraic::Algebraic;
+use crate::polynomial::Polynomial;
+
+/// Returns the continued fraction expansion [a_0; a_1, ..., a_n] of x.
+/// The last term is > 1 unless n = 0.
+pub fn expand_rational(x: &BigRational) -> Vec<BigInt> {
+    let mut num = x.numer().clone();
+    let mut den = x.denom().clone();
+    let mut terms = vec![];
+    while !den.is_zero() {
+        let (q, r) = num.div_mod_floor(&den);
+        terms.push(q);
+        num = den;
+        den = r;
+    }
+    terms
+}
+
+/// The eventually periodic continued fraction expansion of a quadratic irrational,
+/// [pre_period; period, period, ...].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeriodicExpansion {
+    pub pre_period: Vec<BigInt>,
+    pub period: Vec<BigInt>,
+}
+
+impl PeriodicExpansion {
+    /// Returns the infinite sequence of terms.
+    pub fn terms(&self) -> impl Iterator<Item = BigInt> + '_ {
+        self.pre_period
+            .iter()
+            .chain(self.period.iter().cycle())
+            .cloned()
+    }
+}
+
+/// Returns the continued fraction expansion of the quadratic irrational (a + b sqrt(d)) / c,
+/// where b != 0, c != 0 and d > 0 is not a square.
+///
+/// x is written as (P + sqrt(D)) / Q with Q | D - P^2. Then the complete quotients are (P_i + sqrt(D)) / Q_i with
+/// a_i = floor((P_i + sqrt(D)) / Q_i), P_{i+1} = a_i Q_i - P_i, Q_{i+1} = (D - P_{i+1}^2) / Q_i,
+/// and the period is detected by the first repetition of (P_i, Q_i).
+pub fn expand_quadratic(a: &BigInt, b: &BigInt, d: &BigInt, c: &BigInt) -> PeriodicExpansion {
+    assert!(!b.is_zero() && !c.is_zero());
+    let s = d.sqrt();
+    assert!(d.is_positive() && &(&s * &s) != d, "d = {} is a square", d);
+    // (a + b sqrt(d)) / c = (a + sqrt(b^2 d)) / c if b > 0, and (-a + sqrt(b^2 d)) / (-c) if b < 0
+    let (mut p, mut q) = if b.is_positive() {
+        (a.clone(), c.clone())
+    } else {
+        (-a, -c)
+    };
+    let mut d = b * b * d;
+    if !(&d - &p * &p).is_multiple_of(&q) {
+        p *= q.abs();
+        d *= &q * &q;
+        q *= q.abs();
+    }
+    let s = d.sqrt();
+    let mut terms = vec![];
+    let mut seen = HashMap::new();
+    loop {
+        if let Some(&start) = seen.get(&(p.clone(), q.clone())) {
+            let period = terms.split_off(start);
+            return PeriodicExpansion {
+                pre_period: terms,
+                period,
+            };
+        }
+        seen.insert((p.clone(), q.clone()), terms.len());
+        // floor((P + sqrt(D)) / Q), where sqrt(D) is in (s, s + 1)
+        let t = if q.is_positive() {
+            (&p + &s).div_floor(&q)
+        } else {
+            (&p + &s + 1u32).div_floor(&q)
+        };
+        p = &t * &q - &p;
+        q = (&d - &p * &p) / &q;
+        terms.push(t);
+    }
+}
+
+/// A real algebraic number: an element of Q(theta), where theta is the only root of
+/// `value.min_poly` in the open interval (lower, upper).
+#[derive(Clone, Debug)]
+pub struct RealAlgebraic {
+    pub value: Algebraic,
+    lower: BigRational,
+    upper: BigRational,
+}
+
+impl RealAlgebraic {
+    /// theta must be the only root of `value.min_poly` in (lower, upper), and not a root at the endpoints.
+    pub fn new(value: Algebraic, lower: BigRational, upper: BigRational) -> Self {
+        let x = RealAlgebraic {
+            value,
+            lower,
+            upper,
+        };
+        assert!(
+            x.min_poly_sign(&x.lower) * x.min_poly_sign(&x.upper) < 0,
+            "(lower, upper) does not isolate a root"
+        );
+        x
+    }
+
+    fn min_poly_sign(&self, x: &BigRational) -> i32 {
+        let coefs: Vec<BigRational> = self
+            .value
+            .min_poly
+            .dat
+            .iter()
+            .map(|c| BigRational::from_integer(c.clone()))
+            .collect();
+        sign(&eval(&coefs, x))
+    }
+
+    /// Compares g(theta) with 0, bisecting (lower, upper) until the sign of g on it is determined.
+    ///
+    /// On an interval with center m and radius r, |g(x) - g(m)| <= r sum_k k |g_k| R^{k - 1}, where R = max(|lower|, |upper|).
+    /// This terminates unless g(theta) = 0, which only happens for g = 0 since deg g < deg min_poly.
+    fn sign_of(&mut self, g: &Polynomial<BigRational>) -> Ordering {
+        if g.is_zero() {
+            return Ordering::Equal;
+        }
+        loop {
+            let two = BigRational::from_integer(2.into());
+            let mid = (&self.lower + &self.upper) / &two;
+            let radius = (&self.upper - &self.lower) / &two;
+            let big_r = std::cmp::max(self.lower.abs(), self.upper.abs());
+            let value = eval(&g.dat, &mid);
+            let mut slope = BigRational::zero();
+            let mut power = BigRational::one();
+            for (k, c) in g.dat.iter().enumerate().skip(1) {
+                slope += c.abs() * BigRational::from_integer(k.into()) * &power;
+                power *= &big_r;
+            }
+            if value.abs() > radius * slope {
+                return value.cmp(&BigRational::zero());
+            }
+            let s = self.min_poly_sign(&mid);
+            if s == 0 {
+                // theta = mid is rational.
+                return value.cmp(&BigRational::zero());
+            }
+            if s == self.min_poly_sign(&self.lower) {
+                self.lower = mid;
+            } else {
+                self.upper = mid;
+            }
+        }
+    }
+
+    /// Returns floor(x) for x in Q(theta).
+    fn floor(&mut self, x: &Algebraic) -> BigInt {
+        let approx = eval(&x.expr.dat, &self.lower);
+        let mut n = approx.floor().to_integer();
+        let shifted = |n: &BigInt| {
+            (x - &Algebraic::new_const(x.min_poly.clone(), BigRational::from_integer(n.clone())))
+                .expr
+        };
+        while self.sign_of(&shifted(&n)) == Ordering::Less {
+            n -= 1;
+        }
+        while self.sign_of(&shifted(&(&n + 1))) != Ordering::Less {
+            n += 1;
+        }
+        n
+    }
+}
+
+/// Returns the first len terms of the continued fraction expansion of x, or fewer if x is rational.
+///
+/// The complete quotients x_{i+1} = 1 / (x_i - a_i) are computed exactly in Q(theta),
+/// and a_i = floor(x_i) is found by comparing x_i with integers, for which the isolating interval of theta is refined.
+pub fn expand_algebraic(x: &RealAlgebraic, len: usize) -> Vec<BigInt> {
+    let mut x = x.clone();
+    let mut current = x.value.clone();
+    let mut terms = vec![];
+    while terms.len() < len {
+        let t = x.floor(&current);
+        let rest = &current
+            - &Algebraic::new_const(
+                current.min_poly.clone(),
+                BigRational::from_integer(t.clone()),
+            );
+        terms.push(t);
+        match rest.inv() {
+            Some(next) => current = next,
+            None => break,
+        }
+    }
+    terms
+}
+
+/// An iterator of the convergents p_n / q_n of a continued fraction.
+pub struct Convergents<I> {
+    terms: I,
+    /// (p_{n-1}, q_{n-1}) and (p_{n-2}, q_{n-2})
+    prev: (BigInt, BigInt),
+    prev2: (BigInt, BigInt),
+}
+
+/// Returns an iterator of the convergents [a_0; a_1, ..., a_n] of the continued fraction given by terms.
+/// terms may be infinite, e.g. `PeriodicExpansion::terms`.
+pub fn convergents<I: IntoIterator<Item = BigInt>>(terms: I) -> Convergents<I::IntoIter> {
+    Convergents {
+        terms: terms.into_iter(),
+        prev: (BigInt::one(), BigInt::zero()),
+        prev2: (BigInt::zero(), BigInt::one()),
+    }
+}
+
+impl<I: Iterator<Item = BigInt>> Iterator for Convergents<I> {
+    type Item = BigRational;
+
+    fn next(&mut self) -> Option<BigRational> {
+        let a = self.terms.next()?;
+        let p = &a * &self.prev.0 + &self.prev2.0;
+        let q = &a * &self.prev.1 + &self.prev2.1;
+        self.prev2 = std::mem::replace(&mut self.prev, (p.clone(), q.clone()));
+        Some(BigRational::new(p, q))
+    }
+}
+
+/// Returns the semiconvergents (p_{n-2} + k p_{n-1}) / (q_{n-2} + k q_{n-1}) for 1 <= k <= a_n and n >= 1,
+/// in the increasing order of denominators. They include the convergents (k = a_n) except a_0.
+pub fn semiconvergents(terms: &[BigInt]) -> Vec<BigRational> {
+    let mut result = vec![];
+    let (mut p1, mut q1) = (BigInt::one(), BigInt::zero());
+    let (mut p2, mut q2) = (BigInt::zero(), BigInt::one());
+    for (n, a) in terms.iter().enumerate() {
+        if n >= 1 {
+            for k in num::range_inclusive(BigInt::one(), a.clone()) {
+                result.push(BigRational::new(&p2 + &k * &p1, &q2 + &k * &q1));
+            }
+        }
+        let p = a * &p1 + &p2;
+        let q = a * &q1 + &q2;
+        (p2, q2) = (p1, q1);
+        (p1, q1) = (p, q);
+    }
+    result
+}
+
+/// Returns the best rational approximation p/q of x with 0 < q <= max_den,
+/// which minimizes |x - p/q| and then q, where terms is the continued fraction expansion of x.
+///
+/// It is the last convergent p_n / q_n with q_n <= max_den or the semiconvergent
+/// (p_{n-1} + k p_n) / (q_{n-1} + k q_n) with the largest k allowed by max_den.
+/// The latter is closer iff x_{n+1} < 2k + q_{n-1} / q_n, where x_{n+1} = [a_{n+1}; a_{n+2}, ...].
+/// If terms is a prefix of the expansion, it stands for the rational number [a_0; ..., a_m].
+pub fn best_approximation(terms: &[BigInt], max_den: &BigInt) -> BigRational {
+    assert!(max_den.is_positive() && !terms.is_empty());
+    let (mut p1, mut q1) = (BigInt::one(), BigInt::zero());
+    let (mut p2, mut q2) = (BigInt::zero(), BigInt::one());
+    for (n, a) in terms.iter().enumerate() {
+        let p = a * &p1 + &p2;
+        let q = a * &q1 + &q2;
+        if &q > max_den {
+            // p1 / q1 = p_{n-1} / q_{n-1} is the last convergent within the bound.
+            let k = (max_den - &q2) / &q1;
+            if k.is_zero() {
+                return BigRational::new(p1, q1);
+            }
+            let threshold = BigRational::new(2 * &k * &q1 + &q2, q1.clone());
+            // Compared exactly, since terms may end in 1 and then term-by-term comparison is wrong at a tie.
+            let rest = convergents(terms[n..].iter().cloned()).last().unwrap();
+            return if rest < threshold {
+                BigRational::new(&p2 + &k * &p1, &q2 + &k * &q1)
+            } else {
+                BigRational::new(p1, q1)
+            };
+        }
+        (p2, q2) = (p1, q1);
+        (p1, q1) = (p, q);
+    }
+    BigRational::new(p1, q1)
+}
+
+/// Evaluates the polynomial with coefficients coefs at x. This also works for the zero polynomial.
+fn eval(coefs: &[BigRational], x: &BigRational) -> BigRational {
+    coefs
+        .iter()
+        .rev()
+        .fold(BigRational::zero(), |acc, c| acc * x + c)
+}
+
+fn sign(x: &BigRational) -> i32 {
+    match x.cmp(&BigRational::zero()) {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ints(v: &[i64]) -> Vec<BigInt> {
+        v.iter().map(|&x| BigInt::from(x)).collect()
+    }
+
+    fn ratio(p: i64, q: i64) -> BigRational {
+        BigRational::new(p.into(), q.into())
+    }
+
+    #[test]
+    fn expand_rational_works() {
+        assert_eq!(expand_rational(&ratio(415, 93)), ints(&[4, 2, 6, 7]));
+        assert_eq!(expand_rational(&ratio(-415, 93)), ints(&[-5, 1, 1, 6, 7]));
+        assert_eq!(expand_rational(&ratio(3, 1)), ints(&[3]));
+        let x = ratio(-355, 113);
+        assert_eq!(convergents(expand_rational(&x)).last(), Some(x));
+    }
+
+    #[test]
+    fn expand_quadratic_works() {
+        let one = BigInt::one();
+        // sqrt(2) = [1; 2, 2, ...]
+        let e = expand_quadratic(&0.into(), &one, &2.into(), &one);
+        assert_eq!(e.pre_period, ints(&[1]));
+        assert_eq!(e.period, ints(&[2]));
+        // sqrt(7) = [2; 1, 1, 1, 4, ...]
+        let e = expand_quadratic(&0.into(), &one, &7.into(), &one);
+        assert_eq!((e.pre_period, e.period), (ints(&[2]), ints(&[1, 1, 1, 4])));
+        // The golden ratio (1 + sqrt(5)) / 2 = [1; 1, ...]
+        let e = expand_quadratic(&one, &one, &5.into(), &2.into());
+        assert_eq!((e.pre_period, e.period), (ints(&[]), ints(&[1])));
+        // (2 - 3 sqrt(5)) / 7 = -0.672... = [-1; 3, 20, 1, 1, 1, 1, 1, 1, 4, ...]
+        let e = expand_quadratic(&2.into(), &(-3).into(), &5.into(), &7.into());
+        let value = (2.0 - 3.0 * 5f64.sqrt()) / 7.0;
+        let approx: Vec<BigRational> = convergents(e.terms()).take(12).collect();
+        let last = approx.last().unwrap();
+        let last = last.numer().to_string().parse::<f64>().unwrap()
+            / last.denom().to_string().parse::<f64>().unwrap();
+        assert!((last - value).abs() < 1e-9, "{:?}", e);
+        assert_eq!(e.terms().next(), Some((-1).into()));
+    }
+
+    #[test]
+    fn expand_algebraic_works() {
+        // theta = 2^{1/3} = [1; 3, 1, 5, 1, 1, 4, 1, 1, 8, 1, 14, 1, 10, 2, 1, 4, 12, 2, 3, 2, 1, 3, 4, 1, 1, 2, 14, 3, ...]
+        let f = Polynomial::from_raw(ints(&[-2, 0, 0, 1]));
+        let theta = Algebraic::new(f.clone());
+        let x = RealAlgebraic::new(theta.clone(), ratio(1, 1), ratio(2, 1));
+        assert_eq!(
+            expand_algebraic(&x, 20),
+            ints(&[1, 3, 1, 5, 1, 1, 4, 1, 1, 8, 1, 14, 1, 10, 2, 1, 4, 12, 2, 3])
+        );
+        // theta^2 - theta = 2^{2/3} - 2^{1/3} = 0.3276... = [0; 3, 19, 1, ...]
+        let y = RealAlgebraic::new(&(&theta * &theta) - &theta, ratio(1, 1), ratio(2, 1));
+        let terms = expand_algebraic(&y, 4);
+        let value = 4f64.cbrt() - 2f64.cbrt();
+        let approx = convergents(terms.clone()).last().unwrap();
+        let approx = approx.numer().to_string().parse::<f64>().unwrap()
+            / approx.denom().to_string().parse::<f64>().unwrap();
+        assert!((approx - value).abs() < 1e-3, "{:?}", terms);
+        // The negative root of x^2 - 2: -sqrt(2) = [-2; 1, 1, 2, 2, ...]
+        let g = Polynomial::from_raw(ints(&[-2, 0, 1]));
+        let z = RealAlgebraic::new(Algebraic::new(g), ratio(-2, 1), ratio(0, 1));
+        assert_eq!(expand_algebraic(&z, 6), ints(&[-2, 1, 1, 2, 2, 2]));
+        // Rational numbers terminate.
+        let w = RealAlgebraic::new(
+            Algebraic::new_const(f, ratio(7, 3)),
+            ratio(1, 1),
+            ratio(2, 1),
+        );
+        assert_eq!(expand_algebraic(&w, 10), ints(&[2, 3]));
+    }
+
+    #[test]
+    fn semiconvergents_work() {
+        // 415 / 93 = [4; 2, 6, 7]
+        let s = semiconvergents(&ints(&[4, 2, 6, 7]));
+        assert_eq!(s.len(), 2 + 6 + 7);
+        assert_eq!(s[0], ratio(5, 1));
+        assert_eq!(s[1], ratio(9, 2));
+        assert_eq!(s[2], ratio(13, 3));
+        assert_eq!(s.last(), Some(&ratio(415, 93)));
+        assert!(s.windows(2).all(|w| w[0].denom() < w[1].denom()));
+    }
+
+    #[test]
+    fn best_approximation_works() {
+        // pi = [3; 7, 15, 1, 292, 1, 1, 1, 2, 1, 3, ...]
+        let pi = ints(&[3, 7, 15, 1, 292, 1, 1, 1, 2, 1, 3]);
+        assert_eq!(best_approximation(&pi, &1.into()), ratio(3, 1));
+        assert_eq!(best_approximation(&pi, &7.into()), ratio(22, 7));
+        assert_eq!(best_approximation(&pi, &56.into()), ratio(22, 7));
+        // 179 / 57 is a semiconvergent between 22 / 7 and 333 / 106.
+        assert_eq!(best_approximation(&pi, &57.into()), ratio(179, 57));
+        assert_eq!(best_approximation(&pi, &200.into()), ratio(355, 113));
+        // k = 264 >= 292 / 2, so (333 + 264 * 355) / (106 + 264 * 113) beats 355 / 113.
+        assert_eq!(best_approximation(&pi, &30000.into()), ratio(94053, 29938));
+        // Brute force on rational numbers
+        for (p, q) in [(415, 93), (-113, 355), (100, 99), (17, 12), (3, 4)] {
+            let x = ratio(p, q);
+            let terms = expand_rational(&x);
+            // [..., a_n - 1, 1] is another expansion of x.
+            let mut other = terms.clone();
+            *other.last_mut().unwrap() -= 1;
+            other.push(BigInt::one());
+            for max_den in 1..=q {
+                let mut best = ratio(0, 1);
+                let mut best_diff = None;
+                for den in 1..=max_den {
+                    let lower = (p * den).div_euclid(q);
+                    for num in [lower, lower + 1] {
+                        let y = ratio(num, den);
+                        let diff = (&x - &y).abs();
+                        if best_diff.as_ref().map_or(true, |d| &diff < d) {
+                            best = y;
+                            best_diff = Some(diff);
+                        }
+                    }
+                }
+                for terms in [&terms, &other] {
+                    assert_eq!(
+                        best_approximation(terms, &max_den.into()),
+                        best,
+                        "terms = {:?}, max_den = {}",
+                        terms,
+                        max_den
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,406 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use num::bigint::RandBigInt;
+use num::{BigInt, Integer, One, Signed, ToPrimitive, Zero};
+
+use crate::ecm::{factorize, Ell, Point};
+use crate::inverse::{crt, inv};
+
+/// Prime-order subgroups up to this size are solved by baby-step giant-step, which takes O(sqrt(p)) memory.
+/// Larger ones are solved by Pollard's rho method.
+const BSGS_BOUND: u64 = 1 << 32;
+
+/// A finite abelian group, written multiplicatively.
+/// An implementor is the context of the group (e.g. the modulus) rather than an element.
+pub trait Group {
+    type Element: Clone + Eq + Hash;
+
+    fn identity(&self) -> Self::Element;
+    fn op(&self, a: &Self::Element, b: &Self::Element) -> Self::Element;
+    fn inverse(&self, a: &Self::Element) -> Self::Element;
+
+    /// Computes a^e by the binary method. e may be negative.
+    fn pow(&self, a: &Self::Element, e: &BigInt) -> Self::Element {
+        let (mut base, e) = if e.is_negative() {
+            (self.inverse(a), -e)
+        } else {
+            (a.clone(), e.clone())
+        };
+        let mut result = self.identity();
+        for i in 0..e.bits() {
+            if e.bit(i) {
+                result = self.op(&result, &base);
+            }
+            base = self.op(&base, &base);
+        }
+        result
+    }
+}
+
+/// The unit group (Z/nZ)^*. Elements are represented by integers in [0, n).
+#[derive(Clone, Debug)]
+pub struct UnitGroup {
+    pub n: BigInt,
+}
+
+impl Group for UnitGroup {
+    type Element = BigInt;
+
+    fn identity(&self) -> BigInt {
+        BigInt::one() % &self.n
+    }
+    fn op(&self, a: &BigInt, b: &BigInt) -> BigInt {
+        a * b % &self.n
+    }
+    fn inverse(&self, a: &BigInt) -> BigInt {
+        inv(a, &self.n).expect("not a unit")
+    }
+    fn pow(&self, a: &BigInt, e: &BigInt) -> BigInt {
+        if e.is_negative() {
+            self.inverse(a).modpow(&-e, &self.n)
+        } else {
+            a.modpow(e, &self.n)
+        }
+    }
+}
+
+/// The group of points of an elliptic curve over F_p. The modulus of the curve must be a prime,
+/// and points must have coordinates in [0, p).
+impl Group for Ell {
+    type Element = Point;
+
+    fn identity(&self) -> Point {
+        Point::inf()
+    }
+    fn op(&self, a: &Point, b: &Point) -> Point {
+        a.add(b, self).expect("the modulus is not a prime")
+    }
+    fn inverse(&self, a: &Point) -> Point {
+        a.neg(self)
+    }
+}
+
+/// Solves g^x = h (mod n) for x >= 0, where g and h are coprime to n > 0.
+///
+/// Returns the least such x, or None if h is not a power of g.
+/// n is factorized with `ecm::factorize`, and so is p - 1 for each prime p | n,
+/// which gives the factorization of phi(n) without factorizing phi(n) itself.
+pub fn discrete_log(g: &BigInt, h: &BigInt, n: &BigInt) -> Option<BigInt> {
+    assert!(n.is_positive());
+    let group = UnitGroup { n: n.clone() };
+    let g = g.mod_floor(n);
+    let h = h.mod_floor(n);
+    pohlig_hellman(&group, &g, &h, &unit_group_order(n))
+}
+
+/// Returns the factorization of phi(n) = |(Z/nZ)^*|.
+pub(crate) fn unit_group_order(n: &BigInt) -> Vec<(BigInt, u64)> {
+    let mut exponents = std::collections::BTreeMap::new();
+    for (p, e) in factorize(n) {
+        if e >= 2 {
+            *exponents.entry(p.clone()).or_insert(0) += e - 1;
+        }
+        if p > BigInt::from(2) {
+            for (q, f) in factorize(&(&p - 1)) {
+                *exponents.entry(q).or_insert(0) += f;
+            }
+        }
+    }
+    exponents.into_iter().collect()
+}
+
+/// Solves g^x = h in a group where g^order = 1. The order need not be the exact order of g.
+///
+/// Returns the least x >= 0, or None if h is not a power of g.
+/// The order is factorized with `ecm::factorize`.
+pub fn discrete_log_in<G: Group>(
+    group: &G,
+    g: &G::Element,
+    h: &G::Element,
+    order: &BigInt,
+) -> Option<BigInt> {
+    pohlig_hellman(group, g, h, &factorize(order))
+}
+
+/// Returns the factorization of the exact order of g, where g^m = 1 for m = prod p^e for (p, e) in factors.
+pub fn element_order<G: Group>(
+    group: &G,
+    g: &G::Element,
+    factors: &[(BigInt, u64)],
+) -> Vec<(BigInt, u64)> {
+    let identity = group.identity();
+    let mut m: BigInt = factors
+        .iter()
+        .map(|(p, e)| num::pow(p.clone(), *e as usize))
+        .product();
+    debug_assert!(group.pow(g, &m) == identity);
+    let mut result = vec![];
+    for (p, e) in factors {
+        let mut f = *e;
+        while f > 0 && group.pow(g, &(&m / p)) == identity {
+            m /= p;
+            f -= 1;
+        }
+        if f > 0 {
+            result.push((p.clone(), f));
+        }
+    }
+    result
+}
+
+/// Solves g^x = h with the Pohlig-Hellman algorithm, where g^m = 1 for m = prod p^e for (p, e) in factors.
+///
+/// The exact order of g is found first. Then x modulo each prime power p^f dividing it is found digit by digit,
+/// by solving logarithms in the subgroup of order p, and the results are combined by the CRT.
+/// Returns the least x >= 0, or None if h is not a power of g.
+pub fn pohlig_hellman<G: Group>(
+    group: &G,
+    g: &G::Element,
+    h: &G::Element,
+    factors: &[(BigInt, u64)],
+) -> Option<BigInt> {
+    let order_factors = element_order(group, g, factors);
+    let order: BigInt = order_factors
+        .iter()
+        .map(|(p, e)| num::pow(p.clone(), *e as usize))
+        .product();
+    let mut residues = vec![];
+    let mut moduli = vec![];
+    for (p, f) in &order_factors {
+        let pf = num::pow(p.clone(), *f as usize);
+        let cofactor = &order / &pf;
+        // g_i has order p^f.
+        let g_i = group.pow(g, &cofactor);
+        let h_i = group.pow(h, &cofactor);
+        // gamma has order p.
+        let gamma = group.pow(&g_i, &(&pf / p));
+        let solver = PrimeOrderLog::new(group, &gamma, p);
+        let mut x = BigInt::zero();
+        let mut pk = BigInt::one();
+        for k in 0..*f {
+            // (g_i^{-x} h_i)^{p^{f - 1 - k}} = gamma^{d_k}
+            let rest = group.op(&group.pow(&g_i, &-&x), &h_i);
+            let target = group.pow(&rest, &num::pow(p.clone(), (f - 1 - k) as usize));
+            let d = solver.log(group, &target)?;
+            x += d * &pk;
+            pk *= p;
+        }
+        residues.push(x);
+        moduli.push(pf);
+    }
+    let (x, _) = crt(&residues, &moduli)?;
+    if group.pow(g, &x) == *h {
+        Some(x)
+    } else {
+        None
+    }
+}
+
+/// Solves g^x = h for 0 <= x < order with the baby-step giant-step algorithm, where g^order = 1.
+/// This takes O(sqrt(order)) group operations and memory.
+pub fn bsgs<G: Group>(group: &G, g: &G::Element, h: &G::Element, order: &BigInt) -> Option<BigInt> {
+    BabyStepGiantStep::new(group, g, order).log(group, h)
+}
+
+/// Solves g^x = h with Pollard's rho method, where g has a prime order p.
+///
+/// The walk multiplies the current element g^a h^b by g, h or itself depending on its hash,
+/// and Floyd's cycle detection finds a collision g^a h^b = g^a' h^b', from which x = (a - a') / (b' - b) mod p.
+/// This takes O(sqrt(p)) group operations and O(1) memory.
+/// Returns None if h is not a power of g.
+///
+/// Pollard, John M. "Monte Carlo methods for index computation (mod p)." Mathematics of Computation 32.143 (1978): 918-924.
+pub fn pollard_rho<G: Group>(
+    group: &G,
+    g: &G::Element,
+    h: &G::Element,
+    p: &BigInt,
+) -> Option<BigInt> {
+    let identity = group.identity();
+    if *h == identity {
+        return Some(BigInt::zero());
+    }
+    let step = |(y, a, b): &(G::Element, BigInt, BigInt)| {
+        let mut hasher = DefaultHasher::new();
+        y.hash(&mut hasher);
+        match hasher.finish() % 3 {
+            0 => (group.op(y, g), (a + 1u32) % p, b.clone()),
+            1 => (group.op(y, h), a.clone(), (b + 1u32) % p),
+            _ => (group.op(y, y), a * 2u32 % p, b * 2u32 % p),
+        }
+    };
+    let mut rng = rand::thread_rng();
+    // Each attempt fails with probability about 1/p, so a few attempts suffice unless h is not in <g>.
+    for _ in 0..8 {
+        let a0 = rng.gen_bigint_range(&BigInt::zero(), p);
+        let b0 = rng.gen_bigint_range(&BigInt::zero(), p);
+        let y0 = group.op(&group.pow(g, &a0), &group.pow(h, &b0));
+        let mut tortoise = (y0, a0, b0);
+        let mut hare = step(&tortoise);
+        while tortoise.0 != hare.0 {
+            tortoise = step(&tortoise);
+            hare = step(&step(&hare));
+        }
+        let db = (&hare.2 - &tortoise.2).mod_floor(p);
+        if db.is_zero() {
+            continue;
+        }
+        let x = ((&tortoise.1 - &hare.1) * inv(&db, p).unwrap()).mod_floor(p);
+        if group.pow(g, &x) == *h {
+            return Some(x);
+        }
+        // h is not a power of g, since the collision gives g^{a - a'} = h^{b' - b} with b' - b invertible.
+        return None;
+    }
+    None
+}
+
+/// Logarithms to the base gamma of prime order p, by BSGS for small p and Pollard's rho method for large p.
+enum PrimeOrderLog<G: Group> {
+    Bsgs(BabyStepGiantStep<G::Element>),
+    Rho(G::Element, BigInt),
+}
+
+impl<G: Group> PrimeOrderLog<G> {
+    fn new(group: &G, gamma: &G::Element, p: &BigInt) -> Self {
+        if p <= &BigInt::from(BSGS_BOUND) {
+            PrimeOrderLog::Bsgs(BabyStepGiantStep::new(group, gamma, p))
+        } else {
+            PrimeOrderLog::Rho(gamma.clone(), p.clone())
+        }
+    }
+
+    fn log(&self, group: &G, target: &G::Element) -> Option<BigInt> {
+        match self {
+            PrimeOrderLog::Bsgs(bsgs) => bsgs.log(group, target),
+            PrimeOrderLog::Rho(gamma, p) => pollard_rho(group, gamma, target, p),
+        }
+    }
+}
+
+/// The baby steps g^i for 0 <= i < m and the giant step g^{-m}, where m = ceil(sqrt(order)).
+pub(crate) struct BabyStepGiantStep<E> {
+    m: u64,
+    order: BigInt,
+    giant: E,
+    baby: HashMap<E, u64>,
+}
+
+impl<E: Clone + Eq + Hash> BabyStepGiantStep<E> {
+    pub(crate) fn new<G: Group<Element = E>>(group: &G, g: &E, order: &BigInt) -> Self {
+        let mut m = order.sqrt();
+        if &(&m * &m) < order {
+            m += 1;
+        }
+        let m = m.to_u64().expect("the order is too large");
+        let mut baby = HashMap::new();
+        let mut x = group.identity();
+        for i in 0..m {
+            baby.entry(x.clone()).or_insert(i);
+            x = group.op(&x, g);
+        }
+        let giant = group.inverse(&x);
+        BabyStepGiantStep {
+            m,
+            order: order.clone(),
+            giant,
+            baby,
+        }
+    }
+
+    /// Returns the least x in [0, order) with g^x = h, or None if there is none.
+    pub(crate) fn log<G: Group<Element = E>>(&self, group: &G, h: &E) -> Option<BigInt> {
+        let mut y = h.clone();
+        for j in 0..=self.m {
+            if let Some(&i) = self.baby.get(&y) {
+                let x = BigInt::from(j) * self.m + i;
+                if x < self.order {
+                    return Some(x);
+                }
+            }
+            y = group.op(&y, &self.giant);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discrete_log_works() {
+        // 5 is a primitive root modulo 1000000007.
+        let p = BigInt::from(1_000_000_007u64);
+        for x in [0u64, 1, 12345, 999_999_999] {
+            let h = BigInt::from(5).modpow(&x.into(), &p);
+            assert_eq!(discrete_log(&5.into(), &h, &p), Some(x.into()));
+        }
+        // 4 has order 9 modulo 27, and 2 is not a power of 4.
+        assert_eq!(
+            discrete_log(&4.into(), &16.into(), &27.into()),
+            Some(2.into())
+        );
+        assert_eq!(discrete_log(&4.into(), &2.into(), &27.into()), None);
+        // 2 has order 5100 modulo 10403 = 101 * 103.
+        let n = BigInt::from(10403);
+        let h = BigInt::from(2).modpow(&6000.into(), &n);
+        assert_eq!(discrete_log(&2.into(), &h, &n), Some(900.into()));
+    }
+
+    #[test]
+    fn discrete_log_works_with_large_subgroups() {
+        // p = 2 q + 1 with a prime q > 2^32, so that the subgroup of order q is solved by rho.
+        let q = BigInt::from(4_294_967_681u64);
+        let p: BigInt = 2 * &q + 1;
+        assert!(crate::prime::is_prime(&p));
+        let g = BigInt::from(4);
+        let x = BigInt::from(3_141_592_653u64);
+        let h = g.modpow(&x, &p);
+        assert_eq!(discrete_log(&g, &h, &p), Some(x));
+    }
+
+    #[test]
+    fn bsgs_and_pollard_rho_work() {
+        // 2 has order 1019 modulo 2039 = 2 * 1019 + 1.
+        let group = UnitGroup { n: 2039.into() };
+        let q = BigInt::from(1019);
+        let g = BigInt::from(4);
+        for x in [0, 1, 500, 1018] {
+            let h = group.pow(&g, &x.into());
+            assert_eq!(bsgs(&group, &g, &h, &q), Some(x.into()));
+            assert_eq!(pollard_rho(&group, &g, &h, &q), Some(x.into()));
+        }
+        // 2039 - 1 is not a power of 4.
+        assert_eq!(bsgs(&group, &g, &2038.into(), &q), None);
+        assert_eq!(pollard_rho(&group, &g, &2038.into(), &q), None);
+    }
+
+    #[test]
+    fn discrete_log_works_on_elliptic_curves() {
+        // y^2 = x^3 + 2x + 3 over F_1009
+        let p = BigInt::from(1009);
+        let curve = Ell::new(2.into(), p.clone());
+        let rhs = |x: &BigInt| (x * x * x + 2 * x + 3) % &p;
+        let mut count = BigInt::one();
+        let mut base = None;
+        for x in 0..1009 {
+            let x = BigInt::from(x);
+            let roots = crate::modular_root::sqrt_mod_prime_power(&rhs(&x), &p, 1);
+            count += roots.len();
+            if base.is_none() && !roots.is_empty() {
+                base = Some(Point::new(x, roots[0].clone()));
+            }
+        }
+        let base = base.unwrap();
+        assert!(curve.pow(&base, &count).is_inf());
+        for k in [0u64, 7, 123, 1000] {
+            let target = curve.pow(&base, &k.into());
+            let x = discrete_log_in(&curve, &base, &target, &count).unwrap();
+            assert_eq!(curve.pow(&base, &x), target);
+            assert!(x <= BigInt::from(k));
+        }
+    }
+}
@@ -1,13 +1,27 @@
 use num::bigint::RandBigInt;
-use num::{BigInt, One, Zero};
-use std::collections::HashMap;
+use num::{BigInt, Integer, One, ToPrimitive, Zero};
+use number_theory_elementary::{factorize_u128, primes};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Debug};
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use crate::checkpoint::{Checkpoint, Cofactor};
+use crate::factorize::trial_division;
 use crate::inverse::{inv, zmod};
 use crate::perfect_power::perfect_power;
 use crate::prime;
+use crate::qs::qs_cancellable;
+use crate::rho::pollard_brent;
 
+#[derive(Debug)]
 pub struct EcmStats {
     pub curve_count: u64,
+    /// Factors in the result that are composite, because the budget ran out before they were split.
+    pub composite: Vec<BigInt>,
 }
 
 /// Factorizes an integer.
@@ -17,19 +31,158 @@ pub fn factorize(x: &BigInt) -> Vec<(BigInt, u64)> {
 }
 
 pub fn factorize_verbose(x: &BigInt, verbose: bool) -> (Vec<(BigInt, u64)>, EcmStats) {
-    if x <= &BigInt::zero() {
-        panic!("x <= 0: x = {}", x);
+    let conf = ECMConfig {
+        verbose,
+        ..Default::default()
+    };
+    factorize_with_config(x, &conf)
+}
+
+/// Factorizes an integer within the budget of conf. See `factorize_staged`.
+pub fn factorize_with_config(x: &BigInt, conf: &ECMConfig) -> (Vec<(BigInt, u64)>, EcmStats) {
+    factorize_staged(x, conf, ecm)
+}
+
+/// Factorizes an integer within the budget of conf, saving checkpoints to path.
+/// If path already has a checkpoint of x, the factorization resumes from it.
+/// See `factorize_resumable`.
+pub fn factorize_with_checkpoint(
+    x: &BigInt,
+    conf: &ECMConfig,
+    path: &Path,
+) -> io::Result<(Vec<(BigInt, u64)>, EcmStats)> {
+    let state = load_checkpoint(x, path)?;
+    Ok(factorize_resumable(state, conf, Some(path), ecm))
+}
+
+/// Loads the checkpoint of x from path, or returns a fresh checkpoint if path does not exist.
+pub(crate) fn load_checkpoint(x: &BigInt, path: &Path) -> io::Result<Checkpoint> {
+    if !path.exists() {
+        return Ok(Checkpoint::new(x.clone()));
     }
+    let state = Checkpoint::load(path)?;
+    if &state.n != x {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("the checkpoint is for {}, not {}", state.n, x),
+        ));
+    }
+    Ok(state)
+}
+
+/// Primes up to this bound are removed by trial division before other methods are tried.
+pub(crate) const TRIAL_DIVISION_BOUND: usize = 10_000;
+
+/// The number of iterations of Pollard's rho method tried before ECM.
+/// This is enough to find factors up to about 10^12.
+pub(crate) const RHO_ITERATIONS: u64 = 1 << 21;
+
+/// The first method `factorize_staged` tries on a cofactor.
+///
+/// Cofactors split off by some method do not go back to the methods before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Stage {
+    Rho,
+    PPlusMinus1,
+    Qs,
+    Ecm,
+}
+
+/// Composites with this many decimal digits are handed to the quadratic sieve instead of ECM.
+pub(crate) const QS_DIGITS: RangeInclusive<usize> = 30..=100;
+
+/// Factorizes x in stages: trial division, Pollard's rho method,
+/// the p-1 and p+1 methods, the quadratic sieve, and then `ecm` on what remains.
+/// Cofactors below 2^128 are instead factorized on machine words with `factorize_u128`.
+///
+/// If conf.b1 is 0, B1 and B2 are chosen from the size of x. The budget of conf applies to each call of `ecm`,
+/// except for the deadline, which applies to the whole factorization.
+/// The quadratic sieve stops at the deadline, and is skipped if conf.max_curves is set.
+/// A cofactor is left composite, and reported in `EcmStats::composite`, if `ecm` gives up on it
+/// or if the deadline has passed before it is split.
+pub(crate) fn factorize_staged(
+    x: &BigInt,
+    conf: &ECMConfig,
+    ecm: impl Fn(&BigInt, ECMConfig) -> (BigInt, u64),
+) -> (Vec<(BigInt, u64)>, EcmStats) {
+    factorize_resumable(Checkpoint::new(x.clone()), conf, None, ecm)
+}
 
-    let b = select_b(x);
+/// Checkpoints are saved at most this often while ECM runs.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Continues `factorize_staged` from state.
+///
+/// If path is given, the state is saved there whenever a cofactor is split,
+/// and every `CHECKPOINT_INTERVAL` during ECM.
+/// Cofactors given up on stay in the saved state, so that they are tried again on resumption.
+/// The curves already tried on a cofactor count towards conf.max_curves.
+/// Failures to save are reported on stderr and do not stop the factorization.
+pub(crate) fn factorize_resumable(
+    state: Checkpoint,
+    conf: &ECMConfig,
+    path: Option<&Path>,
+    ecm: impl Fn(&BigInt, ECMConfig) -> (BigInt, u64),
+) -> (Vec<(BigInt, u64)>, EcmStats) {
+    if state.n <= BigInt::zero() {
+        panic!("x <= 0: x = {}", state.n);
+    }
+
+    let verbose = conf.verbose;
+    let b = select_b(&state.n);
+
+    let mut map: HashMap<BigInt, u64> = state.factors.into_iter().collect();
+    let mut curves: HashMap<BigInt, BTreeMap<u64, u64>> = HashMap::new();
+    let mut stack = vec![];
+    for Cofactor {
+        n,
+        multiplicity,
+        curves: tried,
+    } in state.cofactors
+    {
+        if !tried.is_empty() {
+            curves.insert(n.clone(), tried);
+            stack.push((n, multiplicity, Stage::Ecm));
+            continue;
+        }
+        if n.bits() <= 128 {
+            // Left to factorize_u128
+            stack.push((n, multiplicity, Stage::Rho));
+            continue;
+        }
+        let (small, n) = trial_division(&n, TRIAL_DIVISION_BOUND);
+        if verbose && !small.is_empty() {
+            eprintln!("Trial division found {} prime factor(s)", small.len());
+        }
+        for (p, e) in small {
+            *map.entry(p).or_insert(0) += e * multiplicity;
+        }
+        stack.push((n, multiplicity, Stage::Rho));
+    }
 
-    let mut stack = vec![(x.clone(), 1)];
-    let mut map = HashMap::new();
     let mut count = 0;
-    while let Some((now, multiplicity)) = stack.pop() {
+    let mut composite = vec![];
+    loop {
+        if let Some(path) = path {
+            let remaining = stack
+                .iter()
+                .map(|(n, m, _)| (n, *m))
+                .chain(composite.iter().map(|(n, m)| (n, *m)));
+            let checkpoint = make_checkpoint(&state.n, &map, remaining, &curves);
+            save_checkpoint(&checkpoint, path);
+        }
+        let Some((now, multiplicity, stage)) = stack.pop() else {
+            break;
+        };
         if now <= BigInt::one() {
             continue;
         }
+        if let Some(small) = now.to_u128() {
+            for (p, e) in factorize_u128(small) {
+                *map.entry(p.into()).or_insert(0) += e * multiplicity;
+            }
+            continue;
+        }
         if prime::is_prime(&now) {
             *map.entry(now).or_insert(0) += multiplicity;
             continue;
@@ -37,30 +190,180 @@ pub fn factorize_verbose(x: &BigInt, verbose: bool) -> (Vec<(BigInt, u64)>, EcmS
         {
             let (b, k) = perfect_power(&now);
             if k >= 2 {
-                stack.push((b, multiplicity * k as u64));
+                stack.push((b, multiplicity * k as u64, stage));
                 continue;
             }
         }
-        let (fac, nowcount) = ecm(
-            &now,
+        if conf.deadline_passed() {
+            composite.push((now, multiplicity));
+            continue;
+        }
+        let mut conf = if conf.b1 == 0 {
             ECMConfig {
                 b1: b,
                 b2: 100 * b,
-                verbose,
-            },
-        );
+                ..conf.clone()
+            }
+        } else {
+            conf.clone()
+        };
+        if stage <= Stage::Rho {
+            if let Some(fac) = pollard_brent(&now, RHO_ITERATIONS) {
+                if verbose {
+                    eprintln!("Pollard rho found factor {}", fac);
+                }
+                let other = &now / &fac;
+                stack.push((fac, multiplicity, Stage::Rho));
+                stack.push((other, multiplicity, Stage::Rho));
+                continue;
+            }
+        }
+        if stage <= Stage::PPlusMinus1 {
+            let fac = p_minus_1(&now, conf.clone()).or_else(|| p_plus_1(&now, conf.clone()));
+            if let Some(fac) = fac {
+                if verbose {
+                    eprintln!("p-1 or p+1 found factor {}", fac);
+                }
+                let other = &now / &fac;
+                stack.push((fac, multiplicity, Stage::PPlusMinus1));
+                stack.push((other, multiplicity, Stage::PPlusMinus1));
+                continue;
+            }
+        }
+        // The running time of the quadratic sieve is not bounded by curves, so it is skipped if conf limits them.
+        if stage <= Stage::Qs
+            && conf.max_curves.is_none()
+            && QS_DIGITS.contains(&now.to_string().len())
+        {
+            if let Some(fac) = qs_cancellable(&now, verbose, &|| conf.deadline_passed()) {
+                if verbose {
+                    eprintln!("Quadratic sieve found factor {}", fac);
+                }
+                let other = &now / &fac;
+                stack.push((fac, multiplicity, Stage::Qs));
+                stack.push((other, multiplicity, Stage::Qs));
+                continue;
+            }
+        }
+        let b1 = conf.b1;
+        let tried = curves
+            .get(&now)
+            .and_then(|c| c.get(&b1))
+            .copied()
+            .unwrap_or(0);
+        conf.max_curves = conf.max_curves.map(|max| max.saturating_sub(tried));
+        if let Some(path) = path {
+            let remaining = stack
+                .iter()
+                .map(|(n, m, _)| (n, *m))
+                .chain(composite.iter().map(|(n, m)| (n, *m)))
+                .chain([(&now, multiplicity)]);
+            let checkpoint = make_checkpoint(&state.n, &map, remaining, &curves);
+            conf.progress = Some(Arc::new(CheckpointProgress {
+                inner: conf.progress.take(),
+                path: path.to_owned(),
+                cofactor: now.clone(),
+                tried,
+                state: Mutex::new((checkpoint, Instant::now())),
+            }));
+        }
+        let (fac, nowcount) = ecm(&now, conf);
         count += nowcount;
         if fac == BigInt::one() {
-            stack.push((now, multiplicity));
+            if verbose {
+                eprintln!("ECM gave up on {}", now);
+            }
+            *curves
+                .entry(now.clone())
+                .or_default()
+                .entry(b1)
+                .or_insert(0) += nowcount;
+            composite.push((now, multiplicity));
             continue;
         }
+        curves.remove(&now);
         let other = &now / &fac;
-        stack.push((fac, multiplicity));
-        stack.push((other, multiplicity));
+        stack.push((fac, multiplicity, Stage::Ecm));
+        stack.push((other, multiplicity, Stage::Ecm));
+    }
+    for (c, multiplicity) in &composite {
+        *map.entry(c.clone()).or_insert(0) += multiplicity;
     }
     let mut result: Vec<(BigInt, u64)> = map.into_iter().collect();
     result.sort();
-    (result, EcmStats { curve_count: count })
+    let mut composite: Vec<BigInt> = composite.into_iter().map(|(c, _)| c).collect();
+    composite.sort();
+    (
+        result,
+        EcmStats {
+            curve_count: count,
+            composite,
+        },
+    )
+}
+
+fn make_checkpoint<'a>(
+    n: &BigInt,
+    factors: &HashMap<BigInt, u64>,
+    remaining: impl Iterator<Item = (&'a BigInt, u64)>,
+    curves: &HashMap<BigInt, BTreeMap<u64, u64>>,
+) -> Checkpoint {
+    let mut factors: Vec<(BigInt, u64)> = factors.iter().map(|(p, &e)| (p.clone(), e)).collect();
+    factors.sort();
+    let cofactors = remaining
+        .filter(|(c, _)| *c > &BigInt::one())
+        .map(|(c, multiplicity)| Cofactor {
+            n: c.clone(),
+            multiplicity,
+            curves: curves.get(c).cloned().unwrap_or_default(),
+        })
+        .collect();
+    Checkpoint {
+        n: n.clone(),
+        factors,
+        cofactors,
+    }
+}
+
+fn save_checkpoint(checkpoint: &Checkpoint, path: &Path) {
+    if let Err(err) = checkpoint.save(path) {
+        eprintln!(
+            "Failed to save the checkpoint to {}: {}",
+            path.display(),
+            err
+        );
+    }
+}
+
+/// Records the curves tried on a cofactor in the checkpoint while ECM runs on it.
+struct CheckpointProgress {
+    inner: Option<Arc<dyn Progress>>,
+    path: PathBuf,
+    cofactor: BigInt,
+    /// Curves tried on the cofactor before this run of ECM.
+    tried: u64,
+    /// The checkpoint and the last time it was saved.
+    state: Mutex<(Checkpoint, Instant)>,
+}
+
+impl Progress for CheckpointProgress {
+    fn curve_done(&self, curves: u64, b1: u64, b2: u64) {
+        if let Some(inner) = &self.inner {
+            inner.curve_done(curves, b1, b2);
+        }
+        let mut state = self.state.lock().unwrap();
+        let (checkpoint, saved) = &mut *state;
+        if saved.elapsed() < CHECKPOINT_INTERVAL {
+            return;
+        }
+        for c in &mut checkpoint.cofactors {
+            if c.n == self.cofactor {
+                c.curves.insert(b1, self.tried + curves);
+            }
+        }
+        save_checkpoint(checkpoint, &self.path);
+        *saved = Instant::now();
+    }
 }
 
 /// Select appropriate B1.
@@ -75,15 +378,61 @@ pub(crate) fn select_b(n: &BigInt) -> u64 {
 }
 
 /// Configuration for ECM.
+///
+/// The same configuration is used for the p-1 and p+1 methods.
+/// `ecm` stops and gives up after max_curves curves or at the deadline, whichever comes first.
+/// Without them, it runs until it finds a factor.
 #[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Default)]
 pub struct ECMConfig {
     pub b1: u64,
     pub b2: u64,
     pub verbose: bool,
+    pub max_curves: Option<u64>,
+    pub deadline: Option<Instant>,
+    pub progress: Option<Arc<dyn Progress>>,
+}
+
+impl ECMConfig {
+    pub fn deadline_passed(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Returns true if count curves exhaust max_curves.
+    pub fn curves_exhausted(&self, count: u64) -> bool {
+        self.max_curves.is_some_and(|max| count >= max)
+    }
+
+    fn report(&self, curves: u64) {
+        if let Some(progress) = &self.progress {
+            progress.curve_done(curves, self.b1, self.b2);
+        }
+    }
+}
+
+impl Debug for ECMConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ECMConfig")
+            .field("b1", &self.b1)
+            .field("b2", &self.b2)
+            .field("verbose", &self.verbose)
+            .field("max_curves", &self.max_curves)
+            .field("deadline", &self.deadline)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Receives progress reports from ECM.
+pub trait Progress: Send + Sync {
+    /// Called after each curve with the number of curves finished on the current number and its B1 and B2.
+    fn curve_done(&self, curves: u64, b1: u64, b2: u64);
 }
 
 /// Finds a factor.
-#[allow(clippy::many_single_char_names)]
+///
+/// Curves are Montgomery curves with Suyama's parametrization and random sigma. See `ecm_curve`.
+/// Returns 1 as the factor if the budget of conf runs out.
 pub fn ecm(n: &BigInt, conf: ECMConfig) -> (BigInt, u64) {
     debug_assert!(!prime::is_prime(n));
 
@@ -92,26 +441,17 @@ pub fn ecm(n: &BigInt, conf: ECMConfig) -> (BigInt, u64) {
     let mut count = 0u64;
 
     loop {
+        if conf.curves_exhausted(count) || conf.deadline_passed() {
+            return (BigInt::one(), count);
+        }
         count += 1;
         if conf.verbose {
             eprintln!("Trying curve {}, B1 = {}, B2 = {}", count, conf.b1, conf.b2);
         }
-        // randomly pick a
-        let a = rng.gen_bigint_range(&1.into(), n);
-        let curve = Ell { a, n: n.clone() };
-
-        // randomly select a point
-        let x = rng.gen_bigint_range(&1.into(), n);
-        let y = rng.gen_bigint_range(&1.into(), n);
-        let pt = Point {
-            x,
-            y,
-            z: BigInt::one(),
-        };
-        if let Err(fac) = ecm_oneshot(pt, curve, conf.b1, conf.b2) {
-            if fac == BigInt::one() || &fac == n {
-                continue;
-            }
+        let sigma = rng.gen_bigint_range(&6.into(), &(n + 6u32));
+        let result = ecm_curve(n, &sigma, conf.b1, conf.b2, &|| conf.deadline_passed());
+        conf.report(count);
+        if let Some(fac) = result {
             debug_assert_eq!(n % &fac, BigInt::zero());
             if conf.verbose {
                 eprintln!("Found factor after {count} trials");
@@ -121,55 +461,362 @@ pub fn ecm(n: &BigInt, conf: ECMConfig) -> (BigInt, u64) {
     }
 }
 
-fn ecm_oneshot(mut pt: Point, curve: Ell, b1: u64, b2: u64) -> Result<(), BigInt> {
-    for k in 1..b1 + 1 {
-        pt = pt.mul(k.into(), &curve)?;
-        if pt.is_inf() {
-            return Ok(());
+/// Runs ECM with a single curve, given by Suyama's parameter sigma.
+///
+/// Stage 1 multiplies the starting point by every prime power <= B1.
+/// Stage 2 is the baby-step giant-step continuation: every prime p in (B1, B2] is written as kD +- j,
+/// and the differences X_{kD} Z_j - X_j Z_{kD} of the points kD P and j P are multiplied together,
+/// so that a single gcd at the end finds p if the order of P is p times a B1-smooth number.
+/// `cancelled` is polled between steps, and None is returned as soon as it returns true.
+pub(crate) fn ecm_curve(
+    n: &BigInt,
+    sigma: &BigInt,
+    b1: u64,
+    b2: u64,
+    cancelled: &dyn Fn() -> bool,
+) -> Option<BigInt> {
+    let (curve, mut pt) = match MontgomeryCurve::suyama(sigma, n) {
+        Ok(result) => result,
+        Err(g) => return nontrivial(g, n),
+    };
+    for q in prime_powers(b1) {
+        if cancelled() {
+            return None;
+        }
+        pt = curve.mul(&pt, q);
+    }
+    let g = pt.z.gcd(n);
+    if !g.is_one() {
+        return nontrivial(g, n);
+    }
+    nontrivial(curve.stage2(&pt, b1, b2, cancelled)?.gcd(n), n)
+}
+
+/// Returns prime powers p^e <= b1 such that p^{e+1} > b1, for all primes p <= b1.
+fn prime_powers(b1: u64) -> impl Iterator<Item = u64> {
+    primes(b1 as usize).into_iter().map(move |p| {
+        let p = p as u64;
+        let mut q = p;
+        while q <= b1 / p {
+            q *= p;
         }
+        q
+    })
+}
+
+/// Returns the smallest numbers of the form 6k + 1 and 6k + 5 that exceed b1.
+/// All primes in (b1, b2] are visited by walking from them by 6.
+fn step2_starts(b1: u64) -> [u64; 2] {
+    let base = b1 / 6 * 6;
+    [1, 5].map(|r| {
+        if base + r > b1 {
+            base + r
+        } else {
+            base + 6 + r
+        }
+    })
+}
+
+/// Returns g if it is a nontrivial factor of n.
+fn nontrivial(g: BigInt, n: &BigInt) -> Option<BigInt> {
+    if g.is_one() || &g == n {
+        None
+    } else {
+        Some(g)
+    }
+}
+
+/// Finds a factor with Pollard's p-1 method.
+///
+/// Stage 1 raises 2 to every prime power <= B1.
+/// Stage 2 walks through the numbers 6k +- 1 in (B1, B2].
+/// This finds a prime factor p if p-1 is a product of prime powers <= B1 and at most one prime <= B2.
+/// Returns None at the deadline of conf.
+pub fn p_minus_1(n: &BigInt, conf: ECMConfig) -> Option<BigInt> {
+    if conf.verbose {
+        eprintln!("Trying p-1, B1 = {}, B2 = {}", conf.b1, conf.b2);
+    }
+    let mut a = BigInt::from(2);
+    for q in prime_powers(conf.b1) {
+        if conf.deadline_passed() {
+            return None;
+        }
+        a = a.modpow(&q.into(), n);
+    }
+    let g = (&a - 1u32).gcd(n);
+    if !g.is_one() {
+        return nontrivial(g, n);
     }
     // Step 2: try all primes in range (b1, b2]
-    for &init in &[b1.saturating_sub(1) / 6 * 6 + 1, (b1 + 1) / 6 * 6 - 1] {
+    let a6 = a.modpow(&6.into(), n);
+    let mut acc = BigInt::one();
+    for init in step2_starts(conf.b1) {
         let mut cur_e = init;
-        let p6 = {
-            let p2 = pt.add(&pt, &curve)?;
-            let p4 = p2.add(&p2, &curve)?;
-            p2.add(&p4, &curve)?
+        let mut cur = a.modpow(&init.into(), n);
+        while cur_e <= conf.b2 {
+            if conf.deadline_passed() {
+                return None;
+            }
+            acc = acc * (&cur - 1) % n;
+            cur = cur * &a6 % n;
+            cur_e += 6;
+        }
+    }
+    nontrivial(acc.gcd(n), n)
+}
+
+/// Finds a factor with Williams' p+1 method.
+///
+/// This uses the Lucas sequence V_k(A) for the seeds A = 2/7 and A = 6/5 suggested by Montgomery.
+/// A prime factor p is found if p+1 is smooth and A^2 - 4 is a quadratic nonresidue mod p;
+/// the seeds cover p = 2 (mod 3) and p = 3 (mod 4), respectively.
+/// Stages 1 and 2 are the same as in `p_minus_1`.
+pub fn p_plus_1(n: &BigInt, conf: ECMConfig) -> Option<BigInt> {
+    if conf.verbose {
+        eprintln!("Trying p+1, B1 = {}, B2 = {}", conf.b1, conf.b2);
+    }
+    for (num, den) in [(2, 7), (6, 5)] {
+        let mut v = match inv(&den.into(), n) {
+            Ok(inv) => inv * num % n,
+            Err(g) => return nontrivial(g, n),
         };
-        pt = pt.mul(init.into(), &curve)?;
-        if pt.is_inf() {
-            return Ok(());
+        for q in prime_powers(conf.b1) {
+            if conf.deadline_passed() {
+                return None;
+            }
+            v = lucas_v(&v, q, n);
         }
-        while cur_e <= b2 {
-            cur_e += 6;
-            pt = pt.add(&p6, &curve)?;
-            if pt.is_inf() {
-                return Ok(());
+        let g = (&v - 2u32).gcd(n);
+        if !g.is_one() {
+            if let Some(fac) = nontrivial(g, n) {
+                return Some(fac);
             }
+            continue;
+        }
+        // Step 2: try all primes in range (b1, b2], using V_{m+6} = V_m V_6 - V_{m-6}
+        let v6 = lucas_v(&v, 6, n);
+        let mut acc = BigInt::one();
+        for init in step2_starts(conf.b1) {
+            let mut cur_e = init;
+            let mut prev = lucas_v(&v, init.abs_diff(6), n);
+            let mut cur = lucas_v(&v, init, n);
+            while cur_e <= conf.b2 {
+                if conf.deadline_passed() {
+                    return None;
+                }
+                acc = acc * (&cur - 2) % n;
+                let next = zmod::<BigInt>(&(&cur * &v6 - &prev), n);
+                prev = std::mem::replace(&mut cur, next);
+                cur_e += 6;
+            }
+        }
+        if let Some(fac) = nontrivial(acc.gcd(n), n) {
+            return Some(fac);
         }
     }
-    Ok(())
+    None
 }
 
-/// Projective coordinates
+/// Computes V_k(v) mod n, where V_0 = 2, V_1 = v, V_{j+1} = v V_j - V_{j-1}.
+fn lucas_v(v: &BigInt, k: u64, n: &BigInt) -> BigInt {
+    // Invariant: (x, y) = (V_j, V_{j+1})
+    let mut x = BigInt::from(2);
+    let mut y = v.clone();
+    for bit in (0..64 - k.leading_zeros()).rev() {
+        if (k >> bit) & 1 == 1 {
+            x = zmod::<BigInt>(&(&x * &y - v), n);
+            y = zmod::<BigInt>(&(&y * &y - 2), n);
+        } else {
+            y = zmod::<BigInt>(&(&x * &y - v), n);
+            x = zmod::<BigInt>(&(&x * &x - 2), n);
+        }
+    }
+    x
+}
+
+/// A point on a Montgomery curve By^2 = x^3 + Ax^2 + x in XZ coordinates (x = X / Z).
+///
+/// y is not tracked, so P and -P are the same. The point at infinity has Z = 0.
 #[derive(Clone, Debug, PartialEq, Eq)]
+struct XzPoint {
+    x: BigInt,
+    z: BigInt,
+}
+
+/// A Montgomery curve modulo n, represented by (A + 2) / 4.
+#[derive(Clone, Debug)]
+struct MontgomeryCurve {
+    a24: BigInt,
+    n: BigInt,
+}
+
+impl MontgomeryCurve {
+    /// Creates a curve and a point on it with Suyama's parametrization:
+    /// u = sigma^2 - 5, v = 4 sigma, x = u^3 / v^3, (A + 2) / 4 = (v - u)^3 (3u + v) / (16 u^3 v).
+    /// The group order of the curve is divisible by 12 modulo every prime.
+    /// Returns Err(g) if 16 u^3 v is not invertible modulo n, where g = gcd(16 u^3 v, n).
+    fn suyama(sigma: &BigInt, n: &BigInt) -> Result<(Self, XzPoint), BigInt> {
+        let u = (sigma * sigma - 5u32) % n;
+        let v = sigma * 4u32 % n;
+        let u3 = &u * &u % n * &u % n;
+        let vmu = &v - &u;
+        let num = &vmu * &vmu % n * &vmu % n * (&u * 3u32 + &v) % n;
+        let den = &u3 * &v % n * 16u32 % n;
+        let a24 = zmod::<BigInt>(&(num * inv(&zmod::<BigInt>(&den, n), n)?), n);
+        let pt = XzPoint {
+            x: u3,
+            z: &v * &v % n * &v % n,
+        };
+        Ok((MontgomeryCurve { a24, n: n.clone() }, pt))
+    }
+
+    /// Computes 2P.
+    fn dbl(&self, p: &XzPoint) -> XzPoint {
+        let n = &self.n;
+        let s = &p.x + &p.z;
+        let d = &p.x - &p.z;
+        let s2 = &s * &s % n;
+        let d2 = &d * &d % n;
+        let t = &s2 - &d2;
+        XzPoint {
+            x: &s2 * &d2 % n,
+            z: &t * (d2 + &self.a24 * &t) % n,
+        }
+    }
+
+    /// Computes P + Q from P, Q and P - Q.
+    fn add(&self, p: &XzPoint, q: &XzPoint, diff: &XzPoint) -> XzPoint {
+        let n = &self.n;
+        let u = (&p.x - &p.z) * (&q.x + &q.z) % n;
+        let v = (&p.x + &p.z) * (&q.x - &q.z) % n;
+        let sum = &u + &v;
+        let dif = u - v;
+        XzPoint {
+            x: &diff.z * (&sum * &sum % n) % n,
+            z: &diff.x * (&dif * &dif % n) % n,
+        }
+    }
+
+    /// Computes kP with the Montgomery ladder.
+    fn mul(&self, p: &XzPoint, k: u64) -> XzPoint {
+        if k == 0 {
+            return XzPoint {
+                x: BigInt::one(),
+                z: BigInt::zero(),
+            };
+        }
+        // Invariant: (r0, r1) = (jP, (j+1)P)
+        let mut r0 = p.clone();
+        let mut r1 = self.dbl(p);
+        for bit in (0..63 - k.leading_zeros()).rev() {
+            if (k >> bit) & 1 == 1 {
+                r0 = self.add(&r1, &r0, p);
+                r1 = self.dbl(&r1);
+            } else {
+                r1 = self.add(&r1, &r0, p);
+                r0 = self.dbl(&r0);
+            }
+        }
+        r0
+    }
+
+    /// Performs the baby-step giant-step stage 2 on P and returns the product of the differences.
+    /// Returns None if cancelled.
+    fn stage2(
+        &self,
+        p: &XzPoint,
+        b1: u64,
+        b2: u64,
+        cancelled: &dyn Fn() -> bool,
+    ) -> Option<BigInt> {
+        let n = &self.n;
+        let d = stage2_width(b2);
+        // Baby steps: baby[i] = (2i + 1) P for 2i + 1 <= d / 2
+        let p2 = self.dbl(p);
+        let mut baby = vec![p.clone(), self.add(&p2, p, p)];
+        while 2 * (baby.len() as u64) < d / 2 {
+            let len = baby.len();
+            let next = self.add(&baby[len - 1], &p2, &baby[len - 2]);
+            baby.push(next);
+        }
+        // Giant steps: cur = kD P, prev = (k-1)D P
+        let q = self.mul(p, d);
+        let mut k = 1;
+        let mut prev = self.mul(p, 0);
+        let mut cur = q.clone();
+        let mut acc = BigInt::one();
+        for prime in primes(b2 as usize + 1) {
+            let prime = prime as u64;
+            if prime <= b1 {
+                continue;
+            }
+            if cancelled() {
+                return None;
+            }
+            let target = (prime + d / 2) / d;
+            let j = prime.abs_diff(target * d);
+            let baby_j = &baby[(j / 2) as usize];
+            if target == 0 {
+                acc = acc * &baby_j.z % n;
+                continue;
+            }
+            while k < target {
+                let next = if k == 1 {
+                    self.dbl(&cur)
+                } else {
+                    self.add(&cur, &q, &prev)
+                };
+                prev = std::mem::replace(&mut cur, next);
+                k += 1;
+            }
+            acc = acc * (&cur.x * &baby_j.z - &baby_j.x * &cur.z) % n;
+        }
+        Some(acc)
+    }
+}
+
+/// The width D of baby-step giant-step stage 2: a product of small primes around sqrt(B2).
+fn stage2_width(b2: u64) -> u64 {
+    [2310, 210]
+        .into_iter()
+        .find(|&d| d * d <= 2 * b2)
+        .unwrap_or(30)
+}
+
+/// Projective coordinates
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Point {
     x: BigInt,
     y: BigInt,
     z: BigInt,
 }
 
-// y^2 = x^3 + ax + b (mod n)
+/// A Weierstrass curve y^2 = x^3 + ax + b (mod n).
 // not (2 | n)
 // In the actual computation, b is irrelevant.
 #[derive(Debug, Clone)]
-struct Ell {
+pub struct Ell {
     a: BigInt,
     n: BigInt,
 }
 
+impl Ell {
+    pub fn new(a: BigInt, n: BigInt) -> Self {
+        Ell { a, n }
+    }
+}
+
 impl Point {
-    fn add(&self, other: &Self, curve: &Ell) -> Result<Self, BigInt> {
+    /// Creates the affine point (x, y).
+    pub fn new(x: BigInt, y: BigInt) -> Self {
+        Point {
+            x,
+            y,
+            z: BigInt::one(),
+        }
+    }
+    /// Adds two points. Returns Err(g) if an inversion modulo n fails, where g = gcd(denominator, n).
+    pub fn add(&self, other: &Self, curve: &Ell) -> Result<Self, BigInt> {
         if self.is_inf() {
             return Ok(other.clone());
         }
@@ -210,7 +857,18 @@ impl Point {
         }
         .simplify(curve)
     }
-    fn mul(&self, mut e: BigInt, curve: &Ell) -> Result<Self, BigInt> {
+    /// Returns -P = (x, -y).
+    pub fn neg(&self, curve: &Ell) -> Self {
+        if self.is_inf() {
+            return Self::inf();
+        }
+        Self {
+            x: self.x.clone(),
+            y: zmod::<BigInt>(&-&self.y, &curve.n),
+            z: self.z.clone(),
+        }
+    }
+    pub fn mul(&self, mut e: BigInt, curve: &Ell) -> Result<Self, BigInt> {
         let mut sum = Self::inf();
         let mut cur = self.clone();
         while e > BigInt::zero() {
@@ -225,14 +883,14 @@ impl Point {
         }
         Ok(sum)
     }
-    fn inf() -> Self {
+    pub fn inf() -> Self {
         Self {
             x: BigInt::zero(),
             y: BigInt::one(),
             z: BigInt::zero(),
         }
     }
-    fn is_inf(&self) -> bool {
+    pub fn is_inf(&self) -> bool {
         self.z == BigInt::zero()
     }
     fn simplify(&self, curve: &Ell) -> Result<Self, BigInt> {
@@ -362,6 +1020,7 @@ mod tests {
                 b1: 1000,
                 b2: 100000,
                 verbose: false,
+                ..Default::default()
             },
         )
         .0;
@@ -378,15 +1037,14 @@ mod tests {
                 b1: 1000,
                 b2: 100000,
                 verbose: false,
+                ..Default::default()
             },
         )
         .0;
         assert_eq!(a % factor, BigInt::zero());
     }
 
-    // Too slow. This test takes about 16 sec in *release* build, about 1 min in debug build.
     #[test]
-    #[ignore]
     fn ecm_works_2() {
         let large1 = BigInt::from(1_000_000_007u128);
         let large2 = BigInt::from(1_000_000_009u128);
@@ -397,6 +1055,7 @@ mod tests {
                 b1: 1000,
                 b2: 100000,
                 verbose: false,
+                ..Default::default()
             },
         )
         .0;
@@ -404,10 +1063,248 @@ mod tests {
         assert_eq!(a % factor, BigInt::zero());
     }
 
+    #[test]
+    fn montgomery_ladder_works() {
+        let n = BigInt::from(1_000_003);
+        let (curve, pt) = MontgomeryCurve::suyama(&11.into(), &n).unwrap();
+        let same = |p: &XzPoint, q: &XzPoint| ((&p.x * &q.z - &q.x * &p.z) % &n).is_zero();
+        let p3 = curve.mul(&pt, 3);
+        let p4 = curve.mul(&pt, 4);
+        assert!(same(&curve.mul(&pt, 7), &curve.add(&p4, &p3, &pt)));
+        assert!(same(&curve.mul(&curve.mul(&pt, 6), 5), &curve.mul(&pt, 30)));
+        assert!(same(&curve.dbl(&p3), &curve.mul(&pt, 6)));
+    }
+
+    #[test]
+    fn ecm_curve_works() {
+        // Most curves that find a factor do so only in stage 2.
+        let n = BigInt::from(1_000_003u64 * 1_000_000_007u64);
+        let mut stage1 = 0;
+        let mut stage2 = 0;
+        for sigma in 6..200 {
+            let sigma = BigInt::from(sigma);
+            if ecm_curve(&n, &sigma, 100, 100, &|| false).is_some() {
+                stage1 += 1;
+            } else if let Some(fac) = ecm_curve(&n, &sigma, 100, 100_000, &|| false) {
+                assert!((&n % fac).is_zero());
+                stage2 += 1;
+            }
+        }
+        assert!(stage2 > stage1, "{} {}", stage1, stage2);
+        assert!(stage1 > 0);
+    }
+
     #[test]
     fn factorize_works_0() {
         let n = BigInt::from(1_000_000_007u128 * 1_000_000_007u128);
         let factors = factorize(&n);
         assert_eq!(factors.len(), 1);
     }
+
+    #[test]
+    fn factorize_works_1() {
+        // Small primes are removed by trial division, and the rest is a prime.
+        let n = BigInt::from(8293702863045600u64);
+        let factors = factorize(&n);
+        let expected: Vec<(BigInt, u64)> = vec![
+            (2.into(), 5),
+            (3.into(), 3),
+            (5.into(), 2),
+            (7.into(), 1),
+            (11.into(), 1),
+            (13.into(), 1),
+            (17.into(), 1),
+            (19.into(), 1),
+            (1187567.into(), 1),
+        ];
+        assert_eq!(factors, expected);
+    }
+
+    #[test]
+    fn factorize_works_2() {
+        // Both factors are found by Pollard's rho method.
+        let n = BigInt::from(1_000_003u128 * 998_244_353u128 * 1_000_000_007u128);
+        let factors = factorize(&n);
+        let expected: Vec<(BigInt, u64)> = vec![
+            (1_000_003.into(), 1),
+            (998_244_353.into(), 1),
+            (1_000_000_007.into(), 1),
+        ];
+        assert_eq!(factors, expected);
+    }
+
+    #[test]
+    fn factorize_works_3() {
+        // Below 2^64, so factorized on machine words.
+        let n = BigInt::from(4_294_967_291u64 * 4_294_967_279u64);
+        let factors = factorize(&n);
+        let expected: Vec<(BigInt, u64)> =
+            vec![(4_294_967_279u64.into(), 1), (4_294_967_291u64.into(), 1)];
+        assert_eq!(factors, expected);
+        // Below 2^128, so factorized with factorize_u128.
+        let n = BigInt::from(10_000_000_000_000_000_051u128 * 10_000_000_000_000_000_087u128);
+        let factors = factorize(&n);
+        let expected: Vec<(BigInt, u64)> = vec![
+            (10_000_000_000_000_000_051u128.into(), 1),
+            (10_000_000_000_000_000_087u128.into(), 1),
+        ];
+        assert_eq!(factors, expected);
+    }
+
+    #[test]
+    fn factorize_deadline_works() {
+        // The deadline has already passed, so only trial division runs.
+        let pq = BigInt::from(100_000_000_000_000_000_039u128)
+            * BigInt::from(100_000_000_000_000_000_129u128);
+        let conf = ECMConfig {
+            deadline: Some(Instant::now()),
+            ..Default::default()
+        };
+        let (factors, stats) = factorize_with_config(&(&pq * 32), &conf);
+        assert_eq!(factors, vec![(2.into(), 5), (pq.clone(), 1)]);
+        assert_eq!(stats.composite, vec![pq]);
+    }
+
+    #[test]
+    fn factorize_with_checkpoint_works() {
+        let path = std::env::temp_dir().join(format!("ecm-checkpoint-{}.json", std::process::id()));
+        let pq = BigInt::from(100_000_000_000_000_000_039u128)
+            * BigInt::from(100_000_000_000_000_000_129u128);
+        let n = &pq * 32;
+        let conf = ECMConfig {
+            deadline: Some(Instant::now()),
+            ..Default::default()
+        };
+        let (_, stats) = factorize_with_checkpoint(&n, &conf, &path).unwrap();
+        assert_eq!(stats.composite, vec![pq.clone()]);
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        assert_eq!(checkpoint.factors, vec![(2.into(), 5)]);
+        assert_eq!(checkpoint.cofactors.len(), 1);
+
+        // Resumes from the checkpoint.
+        let (factors, stats) = factorize_with_checkpoint(&n, &Default::default(), &path).unwrap();
+        assert!(stats.composite.is_empty());
+        let expected: Vec<(BigInt, u64)> = vec![
+            (2.into(), 5),
+            (100_000_000_000_000_000_039u128.into(), 1),
+            (100_000_000_000_000_000_129u128.into(), 1),
+        ];
+        assert_eq!(factors, expected);
+        assert!(Checkpoint::load(&path).unwrap().is_done());
+
+        // The checkpoint is for another number.
+        let err = factorize_with_checkpoint(&pq, &conf, &path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn factorize_resumable_counts_curves() {
+        // The curves in the checkpoint use up max_curves, so ECM is not run.
+        let n = BigInt::from(100_000_000_000_000_000_039u128)
+            * BigInt::from(100_000_000_000_000_000_129u128);
+        let mut state = Checkpoint::new(n.clone());
+        state.cofactors[0].curves.insert(10, 5);
+        let conf = ECMConfig {
+            b1: 10,
+            b2: 100,
+            max_curves: Some(5),
+            ..Default::default()
+        };
+        let (factors, stats) = factorize_resumable(state, &conf, None, ecm);
+        assert_eq!(factors, vec![(n.clone(), 1)]);
+        assert_eq!(stats.composite, vec![n]);
+        assert_eq!(stats.curve_count, 0);
+    }
+
+    #[test]
+    fn ecm_max_curves_works() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        struct Counter(AtomicU64);
+        impl Progress for Counter {
+            fn curve_done(&self, curves: u64, b1: u64, b2: u64) {
+                assert_eq!((b1, b2), (10, 100));
+                self.0.store(curves, Ordering::Relaxed);
+            }
+        }
+        // Curves with B1 = 10 are very unlikely to split a product of two 20-digit primes.
+        let n = BigInt::from(10_000_000_000_000_000_051u128)
+            * BigInt::from(10_000_000_000_000_000_087u128);
+        let counter = Arc::new(Counter(AtomicU64::new(0)));
+        let conf = ECMConfig {
+            b1: 10,
+            b2: 100,
+            max_curves: Some(5),
+            progress: Some(counter.clone()),
+            ..Default::default()
+        };
+        let (fac, count) = ecm(&n, conf);
+        if fac == BigInt::one() {
+            assert_eq!(count, 5);
+        }
+        assert!(count <= 5);
+        assert_eq!(counter.0.load(Ordering::Relaxed), count);
+    }
+
+    #[test]
+    fn p_minus_1_works_0() {
+        // p - 1 = 2 * 3^2 * 5^2 * 149 * 157 * 193 * 509 * 967
+        let p = BigInt::from(1_000_000_000_056_151u64);
+        let q = BigInt::from(1_000_000_000_000_000_009u64);
+        let conf = ECMConfig {
+            b1: 1000,
+            b2: 100000,
+            verbose: false,
+            ..Default::default()
+        };
+        assert_eq!(p_minus_1(&(&p * &q), conf), Some(p));
+    }
+
+    #[test]
+    fn p_minus_1_works_1() {
+        // p - 1 = 2^2 * 3 * 113 * 353 * 773 * 797 * 3391, found in stage 2
+        let p = BigInt::from(1_000_000_000_026_229u64);
+        let q = BigInt::from(1_000_000_000_000_000_009u64);
+        let conf = ECMConfig {
+            b1: 1000,
+            b2: 100000,
+            verbose: false,
+            ..Default::default()
+        };
+        assert_eq!(p_minus_1(&(&p * &q), conf.clone()), Some(p.clone()));
+        let conf = ECMConfig { b2: 1000, ..conf };
+        assert_eq!(p_minus_1(&(&p * &q), conf.clone()), None);
+        // Both methods stop at the deadline.
+        let conf = ECMConfig {
+            b2: 100000,
+            deadline: Some(Instant::now()),
+            ..conf
+        };
+        assert_eq!(p_minus_1(&(&p * &q), conf.clone()), None);
+        assert_eq!(p_plus_1(&(&p * &q), conf), None);
+    }
+
+    #[test]
+    fn p_plus_1_works_0() {
+        // p + 1 = 2^2 * 3 * 7 * 11 * 17 * 29 * 53 * 193 * 317 * 677
+        let p = BigInt::from(1_000_000_000_020_251u64);
+        let q = BigInt::from(1_000_000_000_000_000_009u64);
+        let conf = ECMConfig {
+            b1: 1000,
+            b2: 100000,
+            verbose: false,
+            ..Default::default()
+        };
+        assert_eq!(p_minus_1(&(&p * &q), conf.clone()), None);
+        assert_eq!(p_plus_1(&(&p * &q), conf), Some(p));
+    }
+
+    #[test]
+    fn lucas_v_works() {
+        // V_k(3) = 2, 3, 7, 18, 47, 123, 322, ...
+        let n = BigInt::from(1000);
+        let v: Vec<BigInt> = (0..7).map(|k| lucas_v(&3.into(), k, &n)).collect();
+        let expected: Vec<BigInt> = [2, 3, 7, 18, 47, 123, 322].map(BigInt::from).to_vec();
+        assert_eq!(v, expected);
+    }
 }
@@ -1,369 +1,144 @@
 use num::bigint::RandBigInt;
-use num::{BigInt, One, Zero};
-use std::collections::HashMap;
-
-use crate::ecm::{select_b, ECMConfig, EcmStats};
-use crate::inverse::{inv, zmod};
-use crate::perfect_power::perfect_power;
+use num::{BigInt, One};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::ecm::{
+    ecm_curve, factorize_resumable, factorize_staged, load_checkpoint, ECMConfig, EcmStats,
+};
 use crate::prime;
 
 /// Factorizes an integer.
 /// This function calls other functions as subroutines.
+/// ECM curves are run on all available CPU cores.
 pub fn factorize(x: &BigInt) -> Vec<(BigInt, u64)> {
     factorize_verbose(x, false).0
 }
 
+/// Factorizes an integer, running ECM curves on all available CPU cores.
 pub fn factorize_verbose(x: &BigInt, verbose: bool) -> (Vec<(BigInt, u64)>, EcmStats) {
-    if x <= &BigInt::zero() {
-        panic!("x <= 0: x = {}", x);
-    }
-
-    let b = select_b(x);
-
-    let mut stack = vec![(x.clone(), 1)];
-    let mut map = HashMap::new();
-    let mut count = 0;
-    while let Some((now, multiplicity)) = stack.pop() {
-        if now <= BigInt::one() {
-            continue;
-        }
-        if prime::is_prime(&now) {
-            *map.entry(now).or_insert(0) += multiplicity;
-            continue;
-        }
-        {
-            let (b, k) = perfect_power(&now);
-            if k >= 2 {
-                stack.push((b, multiplicity * k as u64));
-                continue;
-            }
-        }
-        let (fac, nowcount) = ecm(
-            &now,
-            ECMConfig {
-                b1: b,
-                b2: 100 * b,
-                verbose,
-            },
-        );
-        count += nowcount;
-        if fac == BigInt::one() {
-            stack.push((now, multiplicity));
-            continue;
-        }
-        let other = &now / &fac;
-        stack.push((fac, multiplicity));
-        stack.push((other, multiplicity));
-    }
-    let mut result: Vec<(BigInt, u64)> = map.into_iter().collect();
-    result.sort();
-    (result, EcmStats { curve_count: count })
+    factorize_verbose_with_threads(x, verbose, default_threads())
 }
 
-/// Finds a factor.
-#[allow(clippy::many_single_char_names)]
-pub fn ecm(n: &BigInt, conf: ECMConfig) -> (BigInt, u64) {
-    debug_assert!(!prime::is_prime(n));
-
-    let mut rng = rand::thread_rng();
-
-    let mut count = 0u64;
-    let parallel_count = (conf.b1 as f64).sqrt() as usize; // TODO: find better values
-
-    loop {
-        count += 1;
-        if conf.verbose {
-            eprintln!(
-                "Trying curves {}, B1 = {}, B2 = {}",
-                count, conf.b1, conf.b2
-            );
-        }
-        // randomly pick curves
-        let mut curves = vec![];
-        for _ in 0..parallel_count {
-            let a = rng.gen_bigint_range(&1.into(), n);
-            let curve = Ell { a, n: n.clone() };
-            curves.push(curve);
-        }
+/// Factorizes an integer, running ECM curves on the given number of threads.
+pub fn factorize_verbose_with_threads(
+    x: &BigInt,
+    verbose: bool,
+    threads: usize,
+) -> (Vec<(BigInt, u64)>, EcmStats) {
+    let conf = ECMConfig {
+        verbose,
+        ..Default::default()
+    };
+    factorize_with_config(x, &conf, threads)
+}
 
-        // randomly select points
-        let mut points = vec![];
-        for _ in 0..parallel_count {
-            let x = rng.gen_bigint_range(&1.into(), n);
-            let y = rng.gen_bigint_range(&1.into(), n);
-            let pt = Point {
-                x,
-                y,
-                z: BigInt::one(),
-            };
-            points.push(pt);
-        }
-        if let Err(fac) = ecm_oneshot_parallel(points, curves, conf.b1, conf.b2) {
-            if fac == BigInt::one() || &fac == n {
-                continue;
-            }
-            debug_assert_eq!(n % &fac, BigInt::zero());
-            if conf.verbose {
-                eprintln!("Found factor after {count} trials");
-            }
-            return (fac, count * parallel_count as u64);
-        }
-    }
+/// Factorizes an integer within the budget of conf, running ECM curves on the given number of threads.
+/// Cofactors left unsplit are reported in `EcmStats::composite`.
+pub fn factorize_with_config(
+    x: &BigInt,
+    conf: &ECMConfig,
+    threads: usize,
+) -> (Vec<(BigInt, u64)>, EcmStats) {
+    factorize_staged(x, conf, |n, conf| ecm_with_threads(n, conf, threads))
 }
 
-fn ecm_oneshot_parallel(pts: Vec<Point>, curves: Vec<Ell>, b1: u64, b2: u64) -> Result<(), BigInt> {
-    let k = pts.len();
-    let mut joint = Vec::with_capacity(k);
-    for i in 0..k {
-        joint.push((pts[i].clone(), curves[i].clone()));
-    }
-    for mult in 1..b1 + 1 {
-        let mut tmp = Point::many_muls(&joint, mult.into())?;
-        for i in 0..k {
-            joint[i].0 = std::mem::take(&mut tmp[i]);
-        }
-    }
-    // Step 2: try all primes in range (b1, b2]
-    for &init in &[b1.saturating_sub(1) / 6 * 6 + 1, (b1 + 1) / 6 * 6 - 1] {
-        let mut cur_e = init;
-        let p6 = {
-            let mut tmp = Vec::with_capacity(k);
-            for elem in &joint {
-                tmp.push((elem.0.clone(), elem.0.clone(), elem.1.clone()));
-            }
-            let p2 = Point::many_adds(&tmp)?;
-            for i in 0..k {
-                tmp[i].0 = p2[i].clone();
-                tmp[i].1 = p2[i].clone();
-            }
-            let p4 = Point::many_adds(&tmp)?;
-            for i in 0..k {
-                tmp[i].1 = p4[i].clone();
-            }
-            Point::many_adds(&tmp)?
-        };
-        let tmp = Point::many_muls(&joint, init.into())?;
-        for i in 0..k {
-            joint[i].0 = tmp[i].clone();
-        }
-        let mut tmp = Vec::with_capacity(k);
-        for i in 0..k {
-            tmp.push((joint[i].0.clone(), p6[i].clone(), joint[i].1.clone()));
-        }
-        while cur_e <= b2 {
-            cur_e += 6;
-            let result = Point::many_adds(&tmp)?;
-            for i in 0..k {
-                tmp[i].0 = result[i].clone();
-            }
-        }
-    }
-    Ok(())
+/// Factorizes an integer within the budget of conf, running ECM curves on the given number of threads
+/// and saving checkpoints to path.
+/// If path already has a checkpoint of x, the factorization resumes from it.
+pub fn factorize_with_checkpoint(
+    x: &BigInt,
+    conf: &ECMConfig,
+    threads: usize,
+    path: &Path,
+) -> io::Result<(Vec<(BigInt, u64)>, EcmStats)> {
+    let state = load_checkpoint(x, path)?;
+    Ok(factorize_resumable(state, conf, Some(path), |n, conf| {
+        ecm_with_threads(n, conf, threads)
+    }))
 }
 
-/// Projective coordinates
-#[derive(Clone, Debug, PartialEq, Eq, Default)]
-pub struct Point {
-    x: BigInt,
-    y: BigInt,
-    z: BigInt,
+/// The number of threads the system can run in parallel, or 1 if it is unknown.
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
 }
 
-// y^2 = x^3 + ax + b (mod n)
-// not (2 | n)
-// In the actual computation, b is irrelevant.
-#[derive(Debug, Clone)]
-struct Ell {
-    a: BigInt,
-    n: BigInt,
+/// Finds a factor with ECM on all available CPU cores.
+/// See `ecm_with_threads`.
+pub fn ecm(n: &BigInt, conf: ECMConfig) -> (BigInt, u64) {
+    ecm_with_threads(n, conf, default_threads())
 }
 
-impl Point {
-    fn many_adds(pts: &[(Self, Self, Ell)]) -> Result<Vec<Self>, BigInt> {
-        let mut points = Vec::with_capacity(pts.len());
-        for (p1, p2, curve) in pts {
-            if p1.is_inf() {
-                points.push(p2.clone());
-                continue;
-            }
-            if p2.is_inf() {
-                points.push(p1.clone());
-                continue;
-            }
-            let xdif = &p1.x - &p2.x;
-            let n = &curve.n;
-            if xdif == BigInt::zero() {
-                if &(&p1.y + &p2.y) % n == BigInt::zero() {
-                    points.push(Self::inf());
-                    continue;
-                }
-                let lambda = &(&p1.x * &p1.x * 3) + &curve.a;
-                let lambda: BigInt = &lambda % n;
-                let den: BigInt = &(&p1.y * 2) % n;
-                let den2 = &(&den * &den) % n;
-                let den3 = &(&den2 * &den) % n;
-                let x3 = &lambda * &lambda - (&p1.x * 2) * &den2;
-                let y3 = &lambda * &(&(&p1.x * &den2) - &x3);
-                let y3 = y3 - &p1.y * &den3;
-                points.push(Self {
-                    x: zmod::<BigInt>(&(x3 * den), n),
-                    y: zmod::<BigInt>(&y3, n),
-                    z: den3,
-                });
-            } else {
-                let lambda = zmod::<BigInt>(&(&p1.y - &p2.y), n);
-                let xdif2 = &(&xdif * &xdif) % n;
-                let xdif3 = &(&xdif2 * &xdif) % n;
-                let x3 = &lambda * &lambda - &(&p1.x + &p2.x) * &xdif2;
-                let y3 = &lambda * &(&(&p1.x * &xdif2) - &x3);
-                let y3 = y3 - &p1.y * &xdif3;
-                points.push(Self {
-                    x: zmod::<BigInt>(&(x3 * xdif), n),
-                    y: zmod::<BigInt>(&y3, n),
-                    z: xdif3,
-                });
-            }
-        }
-        Self::many_simplify(&points, &pts[0].2.n)
-    }
-    fn many_muls(pts: &[(Self, Ell)], mut e: BigInt) -> Result<Vec<Self>, BigInt> {
-        let k = pts.len();
-        let mut sum = vec![Self::inf(); k];
-        let mut cur = pts.to_vec();
-        while e > BigInt::zero() {
-            if &e % 2 == BigInt::one() {
-                let mut dat = vec![];
-                for i in 0..k {
-                    dat.push((sum[i].clone(), cur[i].0.clone(), cur[i].1.clone()));
+/// Finds a factor with ECM on the given number of threads.
+///
+/// Every thread tries curves with random sigma until some thread finds a factor.
+/// The other threads are cancelled in the middle of their current curves.
+/// Returns the factor and the total number of curves tried by all threads.
+/// If the budget of conf runs out, returns 1 as the factor.
+/// The curves running at the deadline are cancelled and counted.
+pub fn ecm_with_threads(n: &BigInt, conf: ECMConfig, threads: usize) -> (BigInt, u64) {
+    debug_assert!(!prime::is_prime(n));
+
+    let done = AtomicBool::new(false);
+    let count = AtomicU64::new(0);
+    let finished = AtomicU64::new(0);
+    let found = Mutex::new(None);
+    let cancelled = || done.load(Ordering::Relaxed) || conf.deadline_passed();
+
+    thread::scope(|s| {
+        for _ in 0..threads.max(1) {
+            s.spawn(|| {
+                let mut rng = rand::thread_rng();
+                while !cancelled() {
+                    // Claims a curve, unless max_curves curves are already claimed.
+                    let Ok(index) = count.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |c| {
+                        (!conf.curves_exhausted(c)).then_some(c + 1)
+                    }) else {
+                        break;
+                    };
+                    if conf.verbose {
+                        eprintln!(
+                            "Trying curve {}, B1 = {}, B2 = {}",
+                            index + 1,
+                            conf.b1,
+                            conf.b2
+                        );
+                    }
+                    let sigma = rng.gen_bigint_range(&6.into(), &(n + 6u32));
+                    let result = ecm_curve(n, &sigma, conf.b1, conf.b2, &cancelled);
+                    if let Some(progress) = &conf.progress {
+                        let curves = finished.fetch_add(1, Ordering::Relaxed) + 1;
+                        progress.curve_done(curves, conf.b1, conf.b2);
+                    }
+                    if let Some(fac) = result {
+                        found.lock().unwrap().get_or_insert(fac);
+                        done.store(true, Ordering::Relaxed);
+                    }
                 }
-                sum = Self::many_adds(&dat)?;
-            }
-            e /= 2;
-            if e == BigInt::zero() {
-                break;
-            }
-            let mut dat = vec![];
-            for elem in &cur {
-                dat.push((elem.0.clone(), elem.0.clone(), elem.1.clone()));
-            }
-            let tmp = Self::many_adds(&dat)?;
-            for i in 0..k {
-                cur[i].0 = tmp[i].clone();
-            }
-        }
-        Ok(sum)
-    }
-    fn inf() -> Self {
-        Self {
-            x: BigInt::zero(),
-            y: BigInt::one(),
-            z: BigInt::zero(),
-        }
-    }
-    fn is_inf(&self) -> bool {
-        self.z.is_zero()
-    }
-    fn many_simplify(pts: &[Self], n: &BigInt) -> Result<Vec<Self>, BigInt> {
-        let k = pts.len();
-        let mut zarr = vec![];
-        let mut zprod = BigInt::one();
-        for i in 0..k {
-            zarr.push(pts[i].z.clone());
-            if zarr[i] != BigInt::zero() {
-                zprod = &zprod * &zarr[i];
-            }
-        }
-        let mut zacc_l = vec![BigInt::one(); k + 1];
-        let mut zacc_r = vec![BigInt::one(); k + 1];
-        for i in 0..k {
-            if zarr[i] != BigInt::zero() {
-                zacc_l[i + 1] = &(&zacc_l[i] * &zarr[i]) % n;
-            } else {
-                zacc_l[i + 1] = zacc_l[i].clone();
-            }
-        }
-        for i in (0..k).rev() {
-            if zarr[i] != BigInt::zero() {
-                zacc_r[i] = &(&zacc_r[i + 1] * &zarr[i]) % n;
-            } else {
-                zacc_r[i] = zacc_r[i + 1].clone();
-            }
+            });
         }
-        let invzprod = inv(&zprod, n)?;
-        let mut result = Vec::with_capacity(k);
-        for i in 0..k {
-            if zarr[i] == BigInt::zero() {
-                result.push(Self::inf());
-                continue;
-            }
-            let invz = &zacc_l[i] * &zacc_r[i + 1] * &invzprod % n;
-            let x = &(&pts[i].x * &invz) % n;
-            let y = &(&pts[i].y * &invz) % n;
-            result.push(Self {
-                x,
-                y,
-                z: BigInt::one(),
-            })
+    });
+
+    let count = count.into_inner();
+    let Some(fac) = found.into_inner().unwrap() else {
+        if conf.verbose {
+            eprintln!("Gave up after {count} trials");
         }
-        Ok(result)
+        return (BigInt::one(), count);
+    };
+    if conf.verbose {
+        eprintln!("Found factor after {count} trials");
     }
+    (fac, count)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-
-    #[test]
-    fn many_simplify_works_0() {
-        let curve = Ell {
-            a: 3.into(),
-            n: 5.into(),
-        };
-        let pts = vec![
-            Point {
-                x: 2.into(),
-                y: 1.into(),
-                z: 4.into(),
-            },
-            Point::inf(),
-            Point {
-                x: 1.into(),
-                y: 4.into(),
-                z: 3.into(),
-            },
-            Point {
-                x: 2.into(),
-                y: 3.into(),
-                z: 2.into(),
-            },
-        ];
-        let simplified = Point::many_simplify(&pts, &curve.n).unwrap();
-        assert_eq!(
-            simplified[0],
-            Point {
-                x: 3.into(),
-                y: 4.into(),
-                z: 1.into(),
-            }
-        );
-        assert_eq!(simplified[1], Point::inf());
-        assert_eq!(
-            simplified[2],
-            Point {
-                x: 2.into(),
-                y: 3.into(),
-                z: 1.into(),
-            }
-        );
-        assert_eq!(
-            simplified[3],
-            Point {
-                x: 1.into(),
-                y: 4.into(),
-                z: 1.into(),
-            }
-        );
-    }
+    use num::Zero;
 
     #[test]
     fn ecm_works_0() {
@@ -374,6 +149,7 @@ mod tests {
                 b1: 1000,
                 b2: 100000,
                 verbose: false,
+                ..Default::default()
             },
         )
         .0;
@@ -390,30 +166,30 @@ mod tests {
                 b1: 1000,
                 b2: 100000,
                 verbose: false,
+                ..Default::default()
             },
         )
         .0;
         assert_eq!(a % factor, BigInt::zero());
     }
 
-    // Too slow. This test takes about 16 sec in *release* build, about 1 min in debug build.
     #[test]
-    #[ignore]
     fn ecm_works_2() {
         let large1 = BigInt::from(1_000_000_007u128);
         let large2 = BigInt::from(1_000_000_009u128);
         let a = large1 * large2;
-        let factor = ecm(
+        let (factor, count) = ecm_with_threads(
             &a,
             ECMConfig {
                 b1: 1000,
                 b2: 100000,
                 verbose: false,
+                ..Default::default()
             },
-        )
-        .0;
-        eprintln!("factor={}", factor);
+            4,
+        );
         assert_eq!(a % factor, BigInt::zero());
+        assert!(count >= 1);
     }
 
     #[test]
@@ -422,4 +198,23 @@ mod tests {
         let factors = factorize(&n);
         assert_eq!(factors.len(), 1);
     }
+
+    #[test]
+    fn factorize_with_config_works() {
+        // p q > 2^128, and p - 1, p + 1, q - 1 and q + 1 all have a prime factor above B2,
+        // so p is left to ECM on 2 threads after rho and p+-1 fail.
+        // The quadratic sieve is skipped since max_curves is set.
+        let p = BigInt::from(10_000_000_000_000_000_097u128);
+        let q = BigInt::from(1_000_000_000_000_000_000_117u128);
+        let conf = ECMConfig {
+            b1: 2000,
+            b2: 200_000,
+            max_curves: Some(100_000),
+            ..Default::default()
+        };
+        let (factors, stats) = factorize_with_config(&(&p * &q), &conf, 2);
+        assert_eq!(factors, vec![(p, 1), (q, 1)]);
+        assert!(stats.curve_count >= 1);
+        assert!(stats.composite.is_empty());
+    }
 }
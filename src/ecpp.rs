@@ -0,0 +1,467 @@
+use num::{BigInt, Complex, Integer, One, Signed, ToPrimitive, Zero};
+use number_theory_elementary::is_prime_u64;
+use std::collections::HashMap;
+use std::f64::consts::{LN_2, PI};
+
+use crate::certificate::{exceeds_ecpp_bound, Certificate, Proof};
+use crate::ecm::{Ell, Point};
+use crate::factorize::trial_division;
+use crate::inverse::inv;
+use crate::kronecker::jacobi_symbol;
+use crate::modular_root::{quadratic_non_residue, sqrt_mod_prime};
+use crate::poly_mod::find_linear_factors;
+use crate::polynomial::Polynomial;
+use crate::prime;
+
+/// Fundamental discriminants D with |D| <= this bound are tried.
+const MAX_DISCRIMINANT: i64 = 20_000;
+
+/// Discriminants with a larger class number are not tried, since their class polynomials are expensive.
+const MAX_CLASS_NUMBER: usize = 30;
+
+/// The order m is divided by the primes below this bound to find its prime factor q.
+const TRIAL_BOUND: usize = 1 << 16;
+
+/// The number of x-coordinates tried for a point on each curve.
+const POINT_TRIALS: u32 = 100;
+
+/// One step of the down-run: the curve and the point that prove n prime if q is.
+struct Step {
+    n: BigInt,
+    a: BigInt,
+    b: BigInt,
+    x: BigInt,
+    y: BigInt,
+    m: BigInt,
+    q: BigInt,
+}
+
+/// Tries to prove that n is a prime by the elliptic curve primality proving of Atkin and Morain.
+///
+/// Each step of the down-run looks for an imaginary quadratic discriminant D with 4n = u^2 + |D| v^2,
+/// so that a curve with complex multiplication by D has m = n + 1 +- u points,
+/// and m is divided by small primes to leave a probable prime q > (n^{1/4} + 1)^2.
+/// The curve is built from a root of the Hilbert class polynomial H_D modulo n.
+/// The steps continue with q until it is below 2^64.
+/// Returns None if n is not a prime, or if no suitable discriminant is found.
+///
+/// Atkin, A. O. L., and F. Morain. "Elliptic curves and primality proving." Mathematics of Computation 61.203 (1993): 29-68.
+pub fn prove(n: &BigInt) -> Option<Certificate> {
+    if n <= &BigInt::one() {
+        return None;
+    }
+    if let Some(small) = n.to_u64() {
+        return is_prime_u64(small).then(|| Certificate {
+            n: n.clone(),
+            proof: Proof::Small,
+        });
+    }
+    if !prime::is_prime(n) {
+        return None;
+    }
+    let discriminants = discriminants();
+    let mut class_polynomials = HashMap::new();
+    let mut steps = vec![];
+    let mut cur = n.clone();
+    while cur.to_u64().is_none() {
+        let step = find_step(&cur, &discriminants, &mut class_polynomials)?;
+        cur = step.q.clone();
+        steps.push(step);
+    }
+    let mut cert = Certificate {
+        n: cur,
+        proof: Proof::Small,
+    };
+    for Step {
+        n,
+        a,
+        b,
+        x,
+        y,
+        m,
+        q,
+    } in steps.into_iter().rev()
+    {
+        debug_assert_eq!(cert.n, q);
+        cert = Certificate {
+            n,
+            proof: Proof::Ecpp {
+                a,
+                b,
+                x,
+                y,
+                m,
+                q: Box::new(cert),
+            },
+        };
+    }
+    Some(cert)
+}
+
+fn find_step(
+    n: &BigInt,
+    discriminants: &[(i64, usize)],
+    class_polynomials: &mut HashMap<i64, Polynomial<BigInt>>,
+) -> Option<Step> {
+    for &(d, _) in discriminants {
+        if jacobi_symbol(&d.into(), n) != 1 {
+            continue;
+        }
+        let Some(u) = cornacchia(d, n) else {
+            continue;
+        };
+        for m in [n + 1 - &u, n + 1 + &u] {
+            let (_, q) = trial_division(&m, TRIAL_BOUND);
+            if &q >= n || !exceeds_ecpp_bound(&q, n) || !prime::is_prime(&q) {
+                continue;
+            }
+            let h = class_polynomials
+                .entry(d)
+                .or_insert_with(|| hilbert_class_polynomial(d));
+            if let Some(step) = find_point(n, h, &m, &q) {
+                return Some(step);
+            }
+        }
+    }
+    None
+}
+
+/// Finds a point P with [m/q]P != O and [m]P = O on a curve whose j-invariant is a root of h modulo n,
+/// or on its quadratic twist.
+fn find_point(n: &BigInt, h: &Polynomial<BigInt>, m: &BigInt, q: &BigInt) -> Option<Step> {
+    let h = Polynomial::from_raw(h.dat.iter().map(|c| c.mod_floor(n)).collect());
+    let roots = find_linear_factors::<BigInt>(&h, n.clone());
+    let j = roots.first()?;
+    // y^2 = x^3 + 3kx + 2k has the j-invariant 1728k / (k + 1) = j.
+    let k: BigInt = (j * inv(&(BigInt::from(1728) - j), n).ok()?).mod_floor(n);
+    let a: BigInt = (3 * &k) % n;
+    let b: BigInt = (2 * &k) % n;
+    let c = quadratic_non_residue(n)?;
+    let twist: (BigInt, BigInt) = ((&a * &c * &c) % n, (&b * &c * &c * &c) % n);
+    for (a, b) in [(a, b), twist] {
+        let curve = Ell::new(a.clone(), n.clone());
+        for x in 0..POINT_TRIALS {
+            let x = BigInt::from(x);
+            let rhs = (&x * &x * &x + &a * &x + &b) % n;
+            if jacobi_symbol(&rhs, n) != 1 {
+                continue;
+            }
+            let y = sqrt_mod_prime(&rhs, n)?;
+            let r = Point::new(x.clone(), y.clone()).mul(m / q, &curve).ok()?;
+            if r.is_inf() {
+                continue;
+            }
+            if r.mul(q.clone(), &curve).ok()?.is_inf() {
+                return Some(Step {
+                    n: n.clone(),
+                    a,
+                    b,
+                    x,
+                    y,
+                    m: m.clone(),
+                    q: q.clone(),
+                });
+            }
+            // The order of this curve is not m.
+            break;
+        }
+    }
+    None
+}
+
+/// Finds u with 4n = u^2 + |d| v^2, by the modified Cornacchia algorithm (Algorithm 1.5.3 in \[Cohen\]).
+///
+/// \[Cohen\]: Cohen, Henri. A course in computational algebraic number theory. Vol. 138. Springer Science & Business Media, 2013.
+fn cornacchia(d: i64, n: &BigInt) -> Option<BigInt> {
+    let mut x0 = sqrt_mod_prime(&d.into(), n)?;
+    if x0.is_odd() != (d % 2 != 0) {
+        x0 = n - x0;
+    }
+    let four_n: BigInt = 4 * n;
+    let l = four_n.sqrt();
+    let (mut a, mut b) = (2 * n, x0);
+    while b > l {
+        let r = &a % &b;
+        a = b;
+        b = r;
+    }
+    let (c, rest) = (&four_n - &b * &b).div_rem(&BigInt::from(-d));
+    if !rest.is_zero() {
+        return None;
+    }
+    let v = c.sqrt();
+    (&v * &v == c).then_some(b)
+}
+
+fn is_squarefree(n: i64) -> bool {
+    (2..).take_while(|p| p * p <= n).all(|p| n % (p * p) != 0)
+}
+
+fn is_fundamental_discriminant(d: i64) -> bool {
+    match d.rem_euclid(4) {
+        1 => is_squarefree(d.abs()),
+        0 => matches!((d / 4).rem_euclid(4), 2 | 3) && is_squarefree((d / 4).abs()),
+        _ => false,
+    }
+}
+
+/// Fundamental discriminants -`MAX_DISCRIMINANT` <= D < -4 with class numbers at most `MAX_CLASS_NUMBER`,
+/// paired with their class numbers and sorted by the class number and |D|.
+fn discriminants() -> Vec<(i64, usize)> {
+    let mut result = vec![];
+    for d in 5..=MAX_DISCRIMINANT {
+        let d = -d;
+        if !is_fundamental_discriminant(d) {
+            continue;
+        }
+        let h = reduced_forms(d).len();
+        if h <= MAX_CLASS_NUMBER {
+            result.push((d, h));
+        }
+    }
+    result.sort_by_key(|&(d, h)| (h, -d));
+    result
+}
+
+/// Returns the reduced primitive positive definite forms ax^2 + bxy + cy^2 of discriminant d = b^2 - 4ac < 0.
+/// Their number is the class number h(d).
+pub fn reduced_forms(d: i64) -> Vec<(i64, i64, i64)> {
+    assert!(d < 0 && d.rem_euclid(4) <= 1, "d = {}", d);
+    let mut forms = vec![];
+    // |b| <= a <= c implies 3b^2 <= |d|.
+    let mut b = d.rem_euclid(2);
+    while 3 * b * b <= -d {
+        let ac = (b * b - d) / 4;
+        let mut a = b.max(1);
+        while a * a <= ac {
+            if ac % a == 0 {
+                let c = ac / a;
+                if a.gcd(&b).gcd(&c) == 1 {
+                    forms.push((a, b, c));
+                    // (a, -b, c) is reduced unless |b| = a or a = c.
+                    if b > 0 && b < a && a < c {
+                        forms.push((a, -b, c));
+                    }
+                }
+            }
+            a += 1;
+        }
+        b += 2;
+    }
+    forms
+}
+
+/// Computes the Hilbert class polynomial H_D, the product of X - j((-b + sqrt(D)) / 2a)
+/// over the reduced forms (a, b, c) of discriminant D.
+///
+/// The values of j are computed in fixed-point arithmetic with enough precision to round the coefficients.
+pub fn hilbert_class_polynomial(d: i64) -> Polynomial<BigInt> {
+    let forms = reduced_forms(d);
+    // |j(tau)| <= e^{pi sqrt|D| / a} + 2079, and the coefficients are at most the product of 1 + |j(tau)|.
+    let bits: f64 = forms
+        .iter()
+        .map(|&(a, _, _)| PI * (-d as f64).sqrt() / a as f64 / LN_2 + 12.0)
+        .sum();
+    // Twice as many bits, since j(tau) is computed by dividing by a number as small as 1 / |j(tau)|.
+    let fixed = Fixed {
+        prec: 2 * bits.ceil() as u64 + 64,
+    };
+    let mut coefs = vec![Complex::new(fixed.one(), BigInt::zero())];
+    for &(a, b, _) in &forms {
+        let j = fixed.j_invariant(d, a, b);
+        let mut next = vec![Complex::zero(); coefs.len() + 1];
+        for (i, c) in coefs.iter().enumerate() {
+            next[i + 1] += c;
+            next[i] -= fixed.mul(&j, c);
+        }
+        coefs = next;
+    }
+    Polynomial::from_raw(coefs.iter().map(|c| fixed.round(&c.re)).collect())
+}
+
+/// Fixed-point arithmetic on complex numbers: x is represented by round(x 2^prec).
+struct Fixed {
+    prec: u64,
+}
+
+impl Fixed {
+    fn one(&self) -> BigInt {
+        BigInt::one() << self.prec
+    }
+    fn round(&self, x: &BigInt) -> BigInt {
+        (x + (self.one() >> 1u32)) >> self.prec
+    }
+    fn mul(&self, x: &Complex<BigInt>, y: &Complex<BigInt>) -> Complex<BigInt> {
+        let z = x * y;
+        Complex::new(self.round(&z.re), self.round(&z.im))
+    }
+    fn div(&self, x: &Complex<BigInt>, y: &Complex<BigInt>) -> Complex<BigInt> {
+        let z = x * y.conj();
+        let norm = y.norm_sqr();
+        Complex::new((z.re << self.prec) / &norm, (z.im << self.prec) / &norm)
+    }
+    /// Computes atan(1/x) = sum (-1)^k / ((2k + 1) x^{2k+1}).
+    fn atan_inv(&self, x: u64) -> BigInt {
+        let mut power = self.one() / x;
+        let mut sum = BigInt::zero();
+        let mut k = 0u64;
+        while !power.is_zero() {
+            let term = &power / (2 * k + 1);
+            if k % 2 == 0 {
+                sum += term;
+            } else {
+                sum -= term;
+            }
+            power /= x * x;
+            k += 1;
+        }
+        sum
+    }
+    /// pi = 16 atan(1/5) - 4 atan(1/239) (Machin's formula)
+    fn pi(&self) -> BigInt {
+        16 * self.atan_inv(5) - 4 * self.atan_inv(239)
+    }
+    fn exp(&self, z: &Complex<BigInt>) -> Complex<BigInt> {
+        // exp(z) = exp(z / 2^s)^{2^s} with |z / 2^s| < 1
+        let mut s = 0;
+        while (z.re.abs() >> s) + (z.im.abs() >> s) >= self.one() {
+            s += 1;
+        }
+        let y = Complex::new(&z.re >> s, &z.im >> s);
+        let mut term = Complex::new(self.one(), BigInt::zero());
+        let mut sum = term.clone();
+        let mut k = 1u64;
+        while !term.is_zero() {
+            term = self.mul(&term, &y) / BigInt::from(k);
+            sum += &term;
+            k += 1;
+        }
+        for _ in 0..s {
+            sum = self.mul(&sum, &sum);
+        }
+        sum
+    }
+    /// Computes j(tau) for tau = (-b + sqrt(d)) / 2a.
+    ///
+    /// With q = e^{2 pi i tau} and f = q prod (1 + q^n)^24 = Delta(2 tau) / Delta(tau), j = (256 f + 1)^3 / f.
+    fn j_invariant(&self, d: i64, a: i64, b: i64) -> Complex<BigInt> {
+        let pi = self.pi();
+        let sqrt_d = (BigInt::from(-d) << (2 * self.prec)).sqrt();
+        // 2 pi i tau = -pi sqrt|d| / a - pi b i / a
+        let z = Complex::new(-((&pi * sqrt_d) >> self.prec) / a, -(&pi * b) / a);
+        let q = self.exp(&z);
+        let one = Complex::new(self.one(), BigInt::zero());
+        let mut prod = one.clone();
+        let mut qn = q.clone();
+        while !qn.is_zero() {
+            prod = self.mul(&prod, &(&one + &qn));
+            qn = self.mul(&qn, &q);
+        }
+        let p2 = self.mul(&prod, &prod);
+        let p4 = self.mul(&p2, &p2);
+        let p8 = self.mul(&p4, &p4);
+        let p16 = self.mul(&p8, &p8);
+        let f = self.mul(&q, &self.mul(&p16, &p8));
+        let g = &f * BigInt::from(256) + &one;
+        let g3 = self.mul(&self.mul(&g, &g), &g);
+        self.div(&g3, &f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::certificate::verify;
+
+    #[test]
+    fn reduced_forms_works() {
+        assert_eq!(reduced_forms(-3), vec![(1, 1, 1)]);
+        assert_eq!(reduced_forms(-4), vec![(1, 0, 1)]);
+        assert_eq!(reduced_forms(-23), vec![(1, 1, 6), (2, 1, 3), (2, -1, 3)]);
+        let class_numbers: Vec<usize> = [-7, -15, -20, -47, -71, -163]
+            .iter()
+            .map(|&d| reduced_forms(d).len())
+            .collect();
+        assert_eq!(class_numbers, vec![1, 2, 2, 5, 7, 1]);
+    }
+
+    #[test]
+    fn hilbert_class_polynomial_works() {
+        let h = |d| hilbert_class_polynomial(d).into_vec();
+        assert_eq!(h(-7), vec![3375.into(), 1.into()]);
+        assert_eq!(
+            h(-163),
+            vec!["262537412640768000".parse().unwrap(), 1.into()]
+        );
+        assert_eq!(h(-15), vec![(-121287375).into(), 191025.into(), 1.into()]);
+        assert_eq!(
+            h(-23),
+            vec![
+                12771880859375i64.into(),
+                (-5151296875i64).into(),
+                3491750.into(),
+                1.into()
+            ]
+        );
+    }
+
+    #[test]
+    fn cornacchia_works() {
+        let n = BigInt::from(1_000_003);
+        let u = cornacchia(-7, &n).unwrap();
+        let rest: BigInt = 4 * &n - &u * &u;
+        assert!((&rest % 7u32).is_zero());
+        let v = (&rest / 7u32).sqrt();
+        assert_eq!(&v * &v * 7, rest);
+        // (-1/1000003) = -1
+        assert_eq!(cornacchia(-4, &n), None);
+    }
+
+    #[test]
+    fn prove_works() {
+        for n in [
+            (BigInt::one() << 127u32) - 1,
+            num::pow(BigInt::from(10), 50) + 151,
+        ] {
+            let cert = prove(&n).unwrap();
+            assert!(matches!(cert.proof, Proof::Ecpp { .. }));
+            assert!(verify(&cert));
+            let json = serde_json::to_string(&cert).unwrap();
+            let parsed: Certificate = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed, cert);
+        }
+        assert!(prove(&BigInt::from(1_000_000_007)).is_some());
+        assert!(prove(&((BigInt::one() << 127u32) + 1)).is_none());
+    }
+
+    #[test]
+    fn verify_rejects_wrong_ecpp_certificates() {
+        let n = (BigInt::one() << 127u32) - 1;
+        let cert = prove(&n).unwrap();
+        let Proof::Ecpp { a, b, x, y, m, q } = cert.proof.clone() else {
+            panic!();
+        };
+        let with = |a: &BigInt, b: &BigInt, x: &BigInt, y: &BigInt, m: &BigInt| Certificate {
+            n: n.clone(),
+            proof: Proof::Ecpp {
+                a: a.clone(),
+                b: b.clone(),
+                x: x.clone(),
+                y: y.clone(),
+                m: m.clone(),
+                q: q.clone(),
+            },
+        };
+        assert!(verify(&with(&a, &b, &x, &y, &m)));
+        // Not on the curve
+        assert!(!verify(&with(&a, &(&b + 1), &x, &y, &m)));
+        // Wrong order
+        assert!(!verify(&with(&a, &b, &x, &y, &(&m + &q.n))));
+        // Wrong n
+        let wrong = Certificate {
+            n: &n + 2,
+            ..cert.clone()
+        };
+        assert!(!verify(&wrong));
+    }
+}
@@ -1,6 +1,7 @@
 extern crate num;
 
 use num::{BigInt, Zero};
+use number_theory_elementary::primes;
 
 pub fn factorize(n: &BigInt) -> Vec<(BigInt, u64)> {
     assert!(*n >= 1.into());
@@ -25,9 +26,37 @@ pub fn factorize(n: &BigInt) -> Vec<(BigInt, u64)> {
     fac
 }
 
+/// Divides n by all primes <= bound.
+///
+/// Returns the prime factors found and the remaining cofactor, which has no prime factors <= bound.
+pub fn trial_division(n: &BigInt, bound: usize) -> (Vec<(BigInt, u64)>, BigInt) {
+    assert!(*n >= 1.into());
+    let mut n = n.clone();
+    let mut fac = Vec::new();
+    for p in primes(bound) {
+        let p = BigInt::from(p);
+        if &p * &p > n {
+            break;
+        }
+        let mut e = 0;
+        while (&n % &p).is_zero() {
+            e += 1;
+            n /= &p;
+        }
+        if e > 0 {
+            fac.push((p, e));
+        }
+    }
+    if n > 1.into() && n <= BigInt::from(bound) * bound {
+        fac.push((n, 1));
+        n = 1.into();
+    }
+    (fac, n)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::factorize;
+    use super::{factorize, trial_division};
     #[test]
     fn test_factorize() {
         let mut res = factorize(&10.into());
@@ -49,4 +78,29 @@ mod tests {
             ]
         );
     }
+    #[test]
+    fn trial_division_works_0() {
+        let (fac, rest) = trial_division(&8293702863045600i64.into(), 100);
+        assert_eq!(
+            fac,
+            [
+                (2.into(), 5),
+                (3.into(), 3),
+                (5.into(), 2),
+                (7.into(), 1),
+                (11.into(), 1),
+                (13.into(), 1),
+                (17.into(), 1),
+                (19.into(), 1),
+            ]
+        );
+        assert_eq!(rest, 1187567.into());
+    }
+    #[test]
+    fn trial_division_works_1() {
+        // 1187567 < 1200^2 has no prime factors <= 1200, hence it is a prime.
+        let (fac, rest) = trial_division(&(1187567 * 4).into(), 1200);
+        assert_eq!(fac, [(2.into(), 2), (1187567.into(), 1)]);
+        assert_eq!(rest, 1.into());
+    }
 }
@@ -1,6 +1,6 @@
 use std::ops::{AddAssign, Div, Mul, Rem, Sub};
 
-use num::{BigInt, Integer, One, Signed, Zero};
+use num::{BigInt, BigRational, Integer, One, Signed, Zero};
 
 /// Perform extended gcd.
 /// Returns (g, x, y) that satisfies g = gcd(a, b), g = xa + yb.
@@ -93,6 +93,122 @@ pub fn inv(a: &BigInt, mo: &BigInt) -> Result<BigInt, BigInt> {
     Ok(zmod::<BigInt>(&(&x * &g), mo))
 }
 
+/// Solves the system x = `residues[i]` (mod `moduli[i]`) with the Chinese remainder theorem.
+/// The moduli should be positive, but need not be pairwise coprime.
+///
+/// Returns (x, m) with m = lcm(moduli) and 0 <= x < m, or None if the congruences are inconsistent.
+pub fn crt(residues: &[BigInt], moduli: &[BigInt]) -> Option<(BigInt, BigInt)> {
+    assert_eq!(residues.len(), moduli.len());
+    let mut garner = Garner::new();
+    for (r, m) in residues.iter().zip(moduli) {
+        if !garner.push(r, m) {
+            return None;
+        }
+    }
+    Some((garner.value, garner.modulus))
+}
+
+/// An incremental solver of x = r_i (mod m_i), which adds one congruence at a time.
+///
+/// Only the solution x so far and the modulus M = lcm(m_0, ..., m_{i-1}) are kept.
+/// Adding x = r (mod m) merges it into x (mod M) by the Chinese remainder theorem
+/// with one extended gcd of M and m, so the moduli need not be pairwise coprime.
+#[derive(Clone, Debug)]
+pub struct Garner {
+    value: BigInt,
+    modulus: BigInt,
+}
+
+impl Garner {
+    /// Returns the solver of the empty system, whose solution is x = 0 (mod 1).
+    pub fn new() -> Self {
+        Garner {
+            value: BigInt::zero(),
+            modulus: BigInt::one(),
+        }
+    }
+
+    /// Adds the congruence x = r (mod m), where m > 0.
+    /// Returns false and leaves self unchanged if it is inconsistent with the congruences so far.
+    pub fn push(&mut self, r: &BigInt, m: &BigInt) -> bool {
+        assert!(m.is_positive(), "m = {}", m);
+        // u modulus + v m = g
+        let (g, u, _) = extgcd(&self.modulus, m);
+        let (q, rem) = (r - &self.value).div_rem(&g);
+        if !rem.is_zero() {
+            return false;
+        }
+        let m_g = m / &g;
+        let t = (q * u).mod_floor(&m_g);
+        self.value += &self.modulus * t;
+        self.modulus *= m_g;
+        true
+    }
+
+    /// Returns the solution x in [0, modulus).
+    pub fn value(&self) -> &BigInt {
+        &self.value
+    }
+
+    /// Returns the solution x in (-modulus / 2, modulus / 2].
+    /// This is the integer x if |x| < modulus / 2 is known.
+    pub fn signed_value(&self) -> BigInt {
+        if &self.value * 2 > self.modulus {
+            &self.value - &self.modulus
+        } else {
+            self.value.clone()
+        }
+    }
+
+    /// Returns the lcm of the moduli so far.
+    pub fn modulus(&self) -> &BigInt {
+        &self.modulus
+    }
+}
+
+impl Default for Garner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Finds the rational number p/q with p = aq (mod m), |p| <= num_bound and 0 < q <= den_bound.
+/// m should be positive.
+///
+/// If 2 num_bound den_bound < m, such a p/q is unique if it exists.
+/// It is found among the remainders of the extended Euclidean algorithm on (m, a),
+/// and None is returned if it does not exist or gcd(q, m) > 1.
+///
+/// Wang, Paul S., M. J. T. Guy, and J. H. Davenport. "P-adic reconstruction of rational numbers." ACM SIGSAM Bulletin 16.2 (1982): 2-3.
+pub fn rational_reconstruction(
+    a: &BigInt,
+    m: &BigInt,
+    num_bound: &BigInt,
+    den_bound: &BigInt,
+) -> Option<BigRational> {
+    // (r0, s0) and (r1, s1) satisfy r = s a (mod m).
+    let (mut r0, mut s0) = (m.clone(), BigInt::zero());
+    let (mut r1, mut s1) = (a.mod_floor(m), BigInt::one());
+    while &r1 > num_bound {
+        let q = &r0 / &r1;
+        let r2 = &r0 - &q * &r1;
+        let s2 = &s0 - &q * &s1;
+        (r0, s0) = (r1, s1);
+        (r1, s1) = (r2, s2);
+    }
+    if s1.is_zero() || &s1.abs() > den_bound || !s1.gcd(m).is_one() {
+        return None;
+    }
+    Some(BigRational::new(r1, s1))
+}
+
+/// Finds the rational number p/q with p = aq (mod m) and |p|, q <= sqrt(m / 2), which is unique if it exists.
+/// See `rational_reconstruction`.
+pub fn rational_reconstruction_balanced(a: &BigInt, m: &BigInt) -> Option<BigRational> {
+    let bound = (m / 2u32).sqrt();
+    rational_reconstruction(a, m, &bound, &bound)
+}
+
 /// Computes x % mo. The answer is always in [0, mo).
 pub fn zmod<Int: Zero + Ord + for<'a> AddAssign<&'a Int>>(x: &Int, mo: &Int) -> Int
 where
@@ -107,3 +223,66 @@ where
     }
     res
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crt_works() {
+        let residues: Vec<BigInt> = [2, 3, 2].into_iter().map(BigInt::from).collect();
+        let moduli: Vec<BigInt> = [3, 5, 7].into_iter().map(BigInt::from).collect();
+        assert_eq!(crt(&residues, &moduli), Some((23.into(), 105.into())));
+        // Non-coprime moduli
+        let residues: Vec<BigInt> = [3, -1, 7].into_iter().map(BigInt::from).collect();
+        let moduli: Vec<BigInt> = [4, 6, 10].into_iter().map(BigInt::from).collect();
+        assert_eq!(crt(&residues, &moduli), Some((47.into(), 60.into())));
+        let residues: Vec<BigInt> = [1, 2].into_iter().map(BigInt::from).collect();
+        let moduli: Vec<BigInt> = [4, 6].into_iter().map(BigInt::from).collect();
+        assert_eq!(crt(&residues, &moduli), None);
+        assert_eq!(crt(&[], &[]), Some((0.into(), 1.into())));
+    }
+
+    #[test]
+    fn garner_works() {
+        let x = BigInt::from(-123_456_789_012_345i64);
+        let mut garner = Garner::new();
+        for p in [1_000_000_007u64, 998_244_353, 1_000_000_009] {
+            let p = BigInt::from(p);
+            assert!(garner.push(&x.mod_floor(&p), &p));
+        }
+        assert_eq!(garner.signed_value(), x);
+        assert_eq!(garner.value(), &(&x + garner.modulus()));
+        assert!(garner.push(&x.mod_floor(&14.into()), &14.into()));
+        // x + 1 (mod 21) is inconsistent with x (mod 14), and garner is unchanged.
+        let before = garner.clone();
+        let r: BigInt = &x + 1;
+        assert!(!garner.push(&r.mod_floor(&21.into()), &21.into()));
+        assert_eq!(garner.value(), before.value());
+        assert_eq!(garner.modulus(), before.modulus());
+    }
+
+    #[test]
+    fn rational_reconstruction_works() {
+        let m = BigInt::from(1_000_000_007u64);
+        for (p, q) in [(1, 3), (-22, 7), (0, 1), (355, 113), (-1, 20000)] {
+            let p = BigInt::from(p);
+            let q = BigInt::from(q);
+            let a = (&p * inv(&q, &m).unwrap()).mod_floor(&m);
+            assert_eq!(
+                rational_reconstruction_balanced(&a, &m),
+                Some(BigRational::new(p, q))
+            );
+        }
+        // 2/3 is out of the bounds.
+        let a = (BigInt::from(2) * inv(&3.into(), &m).unwrap()).mod_floor(&m);
+        assert_eq!(
+            rational_reconstruction(&a, &m, &1.into(), &1000.into()),
+            None
+        );
+        assert_eq!(
+            rational_reconstruction(&a, &m, &2.into(), &3.into()),
+            Some(BigRational::new(2.into(), 3.into()))
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use num::{BigInt, Integer, One, Signed, Zero};
+
+/// Returns x mod 8 for x >= 0.
+fn mod8(x: &BigInt) -> u32 {
+    x.iter_u32_digits().next().unwrap_or(0) & 7
+}
+
+/// Computes the Jacobi symbol (a/n) for an odd n > 0.
+///
+/// This is the binary algorithm, Algorithm 1.4.10 in \[Cohen\] for odd n,
+/// which only takes remainders by n and shifts.
+///
+/// \[Cohen\]: Cohen, Henri. A course in computational algebraic number theory. Vol. 138. Springer Science & Business Media, 2013.
+pub fn jacobi_symbol(a: &BigInt, n: &BigInt) -> i32 {
+    assert!(n.is_positive() && n.is_odd(), "n = {}", n);
+    let mut a = a.mod_floor(n);
+    let mut n = n.clone();
+    let mut k = 1;
+    while !a.is_zero() {
+        let v = a.trailing_zeros().unwrap();
+        a >>= v;
+        if v % 2 == 1 && (mod8(&n) == 3 || mod8(&n) == 5) {
+            k = -k;
+        }
+        if mod8(&a) % 4 == 3 && mod8(&n) % 4 == 3 {
+            k = -k;
+        }
+        std::mem::swap(&mut a, &mut n);
+        a %= &n;
+    }
+    if n == BigInt::from(1) {
+        k
+    } else {
+        0
+    }
+}
+
+/// Computes the Kronecker symbol (a/b) for any integers a and b.
+///
+/// The powers of 2 and the sign of b are handled as in Algorithm 1.4.10 in \[Cohen\],
+/// and the rest is `jacobi_symbol`.
+///
+/// \[Cohen\]: Cohen, Henri. A course in computational algebraic number theory. Vol. 138. Springer Science & Business Media, 2013.
+pub fn kronecker_symbol(a: &BigInt, b: &BigInt) -> i32 {
+    if b.is_zero() {
+        return if a.abs().is_one() { 1 } else { 0 };
+    }
+    if a.is_even() && b.is_even() {
+        return 0;
+    }
+    // (a/2) = (-1)^{(a^2 - 1)/8} for odd a
+    const RECIP_TABLE: [i32; 8] = [0, 1, 0, -1, 0, -1, 0, 1];
+    let v = b.trailing_zeros().unwrap();
+    let mut k = if v % 2 == 1 {
+        RECIP_TABLE[mod8(&a.mod_floor(&8.into())) as usize]
+    } else {
+        1
+    };
+    // (a/-1) = -1 if a < 0
+    if b.is_negative() && a.is_negative() {
+        k = -k;
+    }
+    k * jacobi_symbol(a, &(b.abs() >> v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use number_theory_elementary::kronecker_symbol_i64;
+
+    #[test]
+    fn jacobi_symbol_works() {
+        for n in (1..100i64).step_by(2) {
+            for a in -100..100i64 {
+                assert_eq!(
+                    jacobi_symbol(&a.into(), &n.into()),
+                    kronecker_symbol_i64(a.rem_euclid(n), n),
+                    "a = {}, n = {}",
+                    a,
+                    n
+                );
+            }
+        }
+        // 2^127 - 1 is a prime = 7 mod 8, so (2/p) = 1 and (-1/p) = -1.
+        let p = (BigInt::from(1) << 127u32) - 1;
+        assert_eq!(jacobi_symbol(&2.into(), &p), 1);
+        assert_eq!(jacobi_symbol(&(-1).into(), &p), -1);
+        assert_eq!(jacobi_symbol(&(&p * 3), &(&p * 5)), 0);
+    }
+
+    #[test]
+    fn kronecker_symbol_works() {
+        for b in -100..100i64 {
+            for a in -100..100i64 {
+                assert_eq!(
+                    kronecker_symbol(&a.into(), &b.into()),
+                    kronecker_symbol_i64(a, b),
+                    "a = {}, b = {}",
+                    a,
+                    b
+                );
+            }
+        }
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        for _ in 0..10000 {
+            let a: i64 = rng.gen_range(-(1 << 62)..1 << 62);
+            let b: i64 = rng.gen_range(-(1 << 59)..1 << 59) << rng.gen_range(0..4);
+            assert_eq!(
+                kronecker_symbol(&a.into(), &b.into()),
+                kronecker_symbol_i64(a, b),
+                "a = {}, b = {}",
+                a,
+                b
+            );
+        }
+        // (D/p) for a large discriminant D = -(2^127 - 1) * 4 and p = 2^61 - 1
+        let m127 = (BigInt::from(1) << 127u32) - 1;
+        let m61 = (BigInt::from(1) << 61u32) - 1;
+        let d = -&m127 * 4;
+        assert_eq!(kronecker_symbol(&d, &m61), jacobi_symbol(&(-&m127), &m61));
+        assert_eq!(kronecker_symbol(&d, &2.into()), 0);
+        assert_eq!(kronecker_symbol(&1.into(), &0.into()), 1);
+        assert_eq!(kronecker_symbol(&m127, &0.into()), 0);
+    }
+}
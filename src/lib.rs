@@ -1,22 +1,35 @@
 #![allow(clippy::suspicious_arithmetic_impl)]
 
 pub mod algebraic;
+pub mod arith;
+pub mod certificate;
+pub mod checkpoint;
 pub mod class;
+pub mod continued_fraction;
 pub mod discriminant;
+pub mod dlog;
 pub mod ecm;
 pub mod ecm_parallel;
+pub mod ecpp;
 pub mod embeddings;
 pub mod factorize;
 pub mod ideal;
 pub mod integral_basis;
 pub mod inverse;
+pub mod kronecker;
+pub mod modular_root;
 pub mod mult_table;
+pub mod nfs;
 pub mod numerical_roots;
 pub mod order;
+pub mod pell;
 pub mod perfect_power;
 pub mod poly_mod;
 pub mod poly_z;
 pub mod polynomial;
 pub mod prime;
 pub mod prime_decomp;
+pub mod primitive_root;
+pub mod qs;
 pub mod resultant;
+pub mod rho;
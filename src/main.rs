@@ -1,5 +1,6 @@
 use num::bigint::Sign;
 use num::BigInt;
+use num::Signed;
 use num::ToPrimitive;
 use rust_number_theory::poly_mod::factorize_mod_p;
 use rust_number_theory::poly_z;
@@ -14,6 +15,8 @@ use rust_number_theory::algebraic::Algebraic;
 use rust_number_theory::discriminant;
 use rust_number_theory::ecm;
 use rust_number_theory::integral_basis;
+use rust_number_theory::nfs::polyselect::{select_polynomials, PolySelectConfig};
+use rust_number_theory::nfs::sieve::{lattice_sieve, special_qs, FactorBase, SieveConfig};
 use rust_number_theory::order;
 use rust_number_theory::polynomial::Polynomial;
 use rust_number_theory::resultant::resultant;
@@ -69,6 +72,16 @@ fn polynomial_unbridge(x: Vec<BigIntBridge>) -> Polynomial<BigInt> {
     Polynomial { dat }
 }
 
+/// The number of polynomial pairs printed for nfs-polynomial.
+const NFS_POLYNOMIAL_CANDIDATES: usize = 10;
+
+/// The number of special-q's sieved for nfs-relations.
+const NFS_SPECIAL_QS: usize = 10;
+
+/// nfs-relations rejects integers with fewer digits. The factor bases of `SieveConfig::for_digits` are too large
+/// for them, so that almost every (a, b) is a relation and the output takes minutes.
+const NFS_MIN_DIGITS: usize = 50;
+
 #[derive(Debug, Clone)]
 struct Opts {
     config: PathBuf,
@@ -309,6 +322,78 @@ fn main() {
             println!("{}", serde_json::to_string_pretty(&data).unwrap());
             continue;
         }
+        if to_find == "nfs-polynomial" {
+            let n: BigInt = match input_config.input {
+                Input::Integer(ref value) => value.clone().into(),
+                _ => {
+                    eprintln!("nfs-polynomial accepts integer only");
+                    continue;
+                }
+            };
+            let conf = PolySelectConfig::for_digits(n.to_string().len());
+            let pairs = select_polynomials(&n, &conf);
+            #[derive(Serialize)]
+            struct Candidate {
+                f: Vec<BigIntBridge>,
+                f_str: String,
+                g: Vec<BigIntBridge>,
+                m: BigIntBridge,
+                skew: f64,
+                alpha: f64,
+                murphy_e: f64,
+            }
+            let mut data = vec![];
+            for pair in pairs.into_iter().take(NFS_POLYNOMIAL_CANDIDATES) {
+                data.push(Candidate {
+                    f_str: format!("{:?}", pair.f),
+                    f: polynomial_bridge(pair.f),
+                    g: polynomial_bridge(pair.g),
+                    m: pair.m.into(),
+                    skew: pair.skew,
+                    alpha: pair.alpha,
+                    murphy_e: pair.murphy_e,
+                });
+            }
+            println!("{}", serde_json::to_string_pretty(&data).unwrap());
+            continue;
+        }
+        if to_find == "nfs-relations" {
+            let n: BigInt = match input_config.input {
+                Input::Integer(ref value) => value.clone().into(),
+                _ => {
+                    eprintln!("nfs-relations accepts integer only");
+                    continue;
+                }
+            };
+            let digits = n.to_string().len();
+            if n.is_negative() || digits < NFS_MIN_DIGITS {
+                eprintln!(
+                    "nfs-relations accepts positive integers with at least {NFS_MIN_DIGITS} digits"
+                );
+                continue;
+            }
+            let pairs = select_polynomials(&n, &PolySelectConfig::for_digits(digits));
+            let Some(pair) = pairs.first() else {
+                eprintln!("No polynomial was found for {n}");
+                continue;
+            };
+            let mut conf = SieveConfig::for_digits(digits);
+            conf.skew = pair.skew;
+            let fb = FactorBase::new(&pair.f, &pair.m, conf.rational_bound, conf.algebraic_bound);
+            let q_start = conf.algebraic_bound as u32;
+            // Relations are printed in the text format documented in nfs::relation.
+            println!("# f = {:?}", pair.f);
+            println!("# m = {}", pair.m);
+            for special_q in special_qs(&pair.f, q_start..2 * q_start)
+                .into_iter()
+                .take(NFS_SPECIAL_QS)
+            {
+                for rel in lattice_sieve(&pair.f, &pair.m, &fb, &special_q, &conf) {
+                    println!("{}", rel);
+                }
+            }
+            continue;
+        }
         eprintln!("Unrecognized command: {}", to_find);
     }
 }
@@ -0,0 +1,486 @@
+use num::{BigInt, Integer, One, Signed, Zero};
+
+use crate::dlog::{BabyStepGiantStep, UnitGroup};
+use crate::ecm::factorize;
+use crate::inverse::inv;
+use crate::kronecker::jacobi_symbol;
+
+/// Computes a square root of a modulo a prime p, or returns None if a is not a square modulo p.
+///
+/// Tonelli-Shanks takes O(log p + s^2) multiplications and Cipolla O(log p), where p - 1 = 2^s t with t odd,
+/// so Tonelli-Shanks is used for small s and Cipolla for large s.
+pub fn sqrt_mod_prime(a: &BigInt, p: &BigInt) -> Option<BigInt> {
+    let s = (p - 1u32).trailing_zeros().unwrap_or(0);
+    if s * s > 2 * p.bits() {
+        cipolla(a, p)
+    } else {
+        tonelli_shanks(a, p)
+    }
+}
+
+/// Computes a square root of a modulo a prime p with the Tonelli-Shanks algorithm,
+/// or returns None if a is not a square modulo p.
+/// If p is an odd composite number, this still terminates, and returns None or a square root of a modulo p.
+///
+/// Algorithm 1.5.1 in \[Cohen\].
+///
+/// \[Cohen\]: Cohen, Henri. A course in computational algebraic number theory. Vol. 138. Springer Science & Business Media, 2013.
+pub fn tonelli_shanks(a: &BigInt, p: &BigInt) -> Option<BigInt> {
+    let a = a.mod_floor(p);
+    if a.is_zero() || p == &BigInt::from(2) {
+        return Some(a);
+    }
+    if jacobi_symbol(&a, p) != 1 {
+        return None;
+    }
+    let p_minus_1: BigInt = p - 1;
+    let s = p_minus_1.trailing_zeros().unwrap();
+    let t = &p_minus_1 >> s;
+    // z is a non-residue, so y = z^t generates the 2-Sylow subgroup.
+    let z = quadratic_non_residue(p)?;
+    let mut y = z.modpow(&t, p);
+    let mut r = s;
+    let mut x = a.modpow(&((&t - 1) >> 1), p);
+    let mut b = &a * &x % p * &x % p;
+    x = &a * &x % p;
+    // Invariant: a b = x^2, y^{2^{r - 1}} = -1, b^{2^{r - 1}} = 1
+    while !b.is_one() {
+        let mut m = 0;
+        let mut b2m = b.clone();
+        while !b2m.is_one() {
+            b2m = &b2m * &b2m % p;
+            m += 1;
+            if m == r {
+                // b^{2^{r - 1}} != 1, which never happens if p is a prime.
+                return None;
+            }
+        }
+        let mut w = y.clone();
+        for _ in 0..r - m - 1 {
+            w = &w * &w % p;
+        }
+        y = &w * &w % p;
+        r = m;
+        x = &x * &w % p;
+        b = &b * &y % p;
+    }
+    Some(x)
+}
+
+/// Returns the least z >= 2 with (z/p) = -1, or None if p is a square, for which (z/p) is never -1.
+/// p must be odd.
+pub(crate) fn quadratic_non_residue(p: &BigInt) -> Option<BigInt> {
+    if is_square(p) {
+        return None;
+    }
+    // (z/p) is a nontrivial character modulo p, so z < p is found.
+    let mut z = BigInt::from(2);
+    while jacobi_symbol(&z, p) != -1 {
+        z += 1;
+    }
+    Some(z)
+}
+
+fn is_square(n: &BigInt) -> bool {
+    let root = n.sqrt();
+    &(&root * &root) == n
+}
+
+/// Computes a square root of a modulo an odd prime p with Cipolla's algorithm,
+/// or returns None if a is not a square modulo p.
+/// If p is an odd composite number, this still terminates, and returns None or a square root of a modulo p.
+///
+/// With t such that t^2 - a is a non-residue, (t + w)^{(p + 1) / 2} is a square root of a in F_p(w), where w^2 = t^2 - a.
+pub fn cipolla(a: &BigInt, p: &BigInt) -> Option<BigInt> {
+    let a = a.mod_floor(p);
+    if a.is_zero() || p == &BigInt::from(2) {
+        return Some(a);
+    }
+    if jacobi_symbol(&a, p) != 1 {
+        return None;
+    }
+    if is_square(p) {
+        return None;
+    }
+    let mut t = BigInt::one();
+    let w2 = loop {
+        // t runs over all residues modulo p, and some of them work for a prime p.
+        if &t > p {
+            return None;
+        }
+        let w2 = (&t * &t - &a).mod_floor(p);
+        if jacobi_symbol(&w2, p) == -1 {
+            break w2;
+        }
+        t += 1;
+    };
+    // (x, y) stands for x + y w.
+    let mul = |(x1, y1): &(BigInt, BigInt), (x2, y2): &(BigInt, BigInt)| {
+        ((x1 * x2 + y1 * y2 % p * &w2) % p, (x1 * y2 + y1 * x2) % p)
+    };
+    let e: BigInt = (p + 1) >> 1;
+    let mut result = (BigInt::one(), BigInt::zero());
+    let base = (t, BigInt::one());
+    for i in (0..e.bits()).rev() {
+        result = mul(&result, &result);
+        if e.bit(i) {
+            result = mul(&result, &base);
+        }
+    }
+    // Only fails if p is not a prime.
+    let is_root = result.1.is_zero() && (&result.0 * &result.0 - &a).mod_floor(p).is_zero();
+    is_root.then_some(result.0)
+}
+
+/// Returns all square roots of a modulo p^e in the increasing order, where p is a prime and e >= 1.
+///
+/// Roots modulo p are lifted by Hensel's lemma. a need not be coprime to p.
+pub fn sqrt_mod_prime_power(a: &BigInt, p: &BigInt, e: u64) -> Vec<BigInt> {
+    assert!(e >= 1);
+    let pe = num::pow(p.clone(), e as usize);
+    let a = a.mod_floor(&pe);
+    if a.is_zero() {
+        // x = 0 (mod p^{ceil(e / 2)})
+        let step = num::pow(p.clone(), e.div_ceil(2) as usize);
+        let count = num::pow(p.clone(), (e / 2) as usize);
+        return num::range(BigInt::zero(), count)
+            .map(|i| i * &step)
+            .collect();
+    }
+    let mut v = 0;
+    let mut b = a;
+    while (&b % p).is_zero() {
+        b /= p;
+        v += 1;
+    }
+    if v % 2 == 1 {
+        return vec![];
+    }
+    // x = p^{v/2} y with y^2 = b (mod p^{e - v}), and y is determined modulo p^{e - v/2}.
+    let k = e - v;
+    let pk = num::pow(p.clone(), k as usize);
+    let ph = num::pow(p.clone(), (v / 2) as usize);
+    let mut result = vec![];
+    for y in unit_sqrt_mod_prime_power(&b, p, k) {
+        for j in num::range(BigInt::zero(), ph.clone()) {
+            result.push(&ph * (&y + j * &pk) % &pe);
+        }
+    }
+    result.sort();
+    result
+}
+
+/// Returns all square roots of b modulo p^k, where b is coprime to p.
+fn unit_sqrt_mod_prime_power(b: &BigInt, p: &BigInt, k: u64) -> Vec<BigInt> {
+    let pk = num::pow(p.clone(), k as usize);
+    if p == &BigInt::from(2) {
+        let b = b.mod_floor(&pk);
+        if k == 1 {
+            return vec![BigInt::one()];
+        }
+        let modulus = if k == 2 { 4 } else { 8 };
+        if b.mod_floor(&BigInt::from(modulus)) != BigInt::one() {
+            return vec![];
+        }
+        if k == 2 {
+            return vec![1.into(), 3.into()];
+        }
+        // r^2 = b (mod 2^j) for j = 3, ..., k
+        let mut r = BigInt::one();
+        for j in 3..k {
+            if !((&r * &r - &b) >> j).is_even() {
+                r += BigInt::one() << (j - 1);
+            }
+        }
+        let half = BigInt::one() << (k - 1);
+        let mut result = vec![
+            r.clone(),
+            &pk - &r,
+            (&r + &half) % &pk,
+            (&pk - &r + &half) % &pk,
+        ];
+        result.sort();
+        return result;
+    }
+    let Some(mut r) = sqrt_mod_prime(b, p) else {
+        return vec![];
+    };
+    // Newton's iteration, which doubles the precision each time.
+    let mut m = p.clone();
+    while m < pk {
+        m = std::cmp::min(&m * &m, pk.clone());
+        let two_r_inv = inv(&(2 * &r), &m).unwrap();
+        r = (&r - (&r * &r - b) * two_r_inv).mod_floor(&m);
+    }
+    vec![r.clone(), &pk - r]
+}
+
+/// Returns all square roots of a modulo n > 0 in the increasing order. n is factorized with `ecm::factorize`.
+pub fn sqrt_mod(a: &BigInt, n: &BigInt) -> Vec<BigInt> {
+    assert!(n.is_positive());
+    sqrt_mod_factored(a, &factorize(n))
+}
+
+/// Returns all square roots of a modulo n in the increasing order, where n = prod p^e for (p, e) in factors.
+///
+/// Roots modulo each p^e are combined by the Chinese remainder theorem.
+pub fn sqrt_mod_factored(a: &BigInt, factors: &[(BigInt, u64)]) -> Vec<BigInt> {
+    let mut result = vec![BigInt::zero()];
+    let mut modulus = BigInt::one();
+    for (p, e) in factors {
+        let pe = num::pow(p.clone(), *e as usize);
+        let roots = sqrt_mod_prime_power(a, p, *e);
+        let mut next = Vec::with_capacity(result.len() * roots.len());
+        // x = x1 + modulus ((x2 - x1) modulus^{-1} mod p^e)
+        let modulus_inv = inv(&modulus, &pe).unwrap();
+        for x1 in &result {
+            for x2 in &roots {
+                let t = ((x2 - x1) * &modulus_inv).mod_floor(&pe);
+                next.push(x1 + &modulus * t);
+            }
+        }
+        result = next;
+        modulus *= pe;
+    }
+    result.sort();
+    result
+}
+
+/// Computes a k-th root of a modulo a prime p, or returns None if a is not a k-th power modulo p. k must be >= 1.
+///
+/// With d = gcd(k, p - 1), a d-th root y of a is found by taking r-th roots for the prime factors r of d
+/// with the Adleman-Manders-Miller algorithm. Then y^{(k/d)^{-1} mod (p - 1)/d} is a k-th root of a.
+pub fn kth_root_mod_prime(a: &BigInt, k: &BigInt, p: &BigInt) -> Option<BigInt> {
+    kth_root_mod_prime_impl(a, k, p).map(|(x, _)| x)
+}
+
+/// Returns all k-th roots of a modulo a prime p in the increasing order. k must be >= 1.
+///
+/// If a is nonzero and a k-th power, there are gcd(k, p - 1) roots,
+/// which are a root times the gcd(k, p - 1)-th roots of unity.
+pub fn kth_roots_mod_prime(a: &BigInt, k: &BigInt, p: &BigInt) -> Vec<BigInt> {
+    let Some((x, d_factors)) = kth_root_mod_prime_impl(a, k, p) else {
+        return vec![];
+    };
+    if x.is_zero() {
+        return vec![x];
+    }
+    // A primitive d-th root of unity, the product of primitive r^e-th roots of unity.
+    let p_minus_1: BigInt = p - 1;
+    let mut zeta = BigInt::one();
+    let mut d = BigInt::one();
+    for (r, e) in &d_factors {
+        let re = num::pow(r.clone(), *e as usize);
+        let rho = non_residue(r, p);
+        zeta = zeta * rho.modpow(&(&p_minus_1 / &re), p) % p;
+        d *= re;
+    }
+    let mut result = Vec::new();
+    let mut y = x;
+    for _ in num::range(BigInt::zero(), d) {
+        result.push(y.clone());
+        y = y * &zeta % p;
+    }
+    result.sort();
+    result
+}
+
+/// Returns a k-th root of a modulo p and the factorization of gcd(k, p - 1).
+fn kth_root_mod_prime_impl(
+    a: &BigInt,
+    k: &BigInt,
+    p: &BigInt,
+) -> Option<(BigInt, Vec<(BigInt, u64)>)> {
+    assert!(k.is_positive());
+    let a = a.mod_floor(p);
+    if a.is_zero() {
+        return Some((a, vec![]));
+    }
+    let p_minus_1: BigInt = p - 1;
+    let d = k.gcd(&p_minus_1);
+    if !a.modpow(&(&p_minus_1 / &d), p).is_one() {
+        return None;
+    }
+    let d_factors = factorize(&d);
+    let mut y = a;
+    for (r, e) in &d_factors {
+        for _ in 0..*e {
+            y = amm_root(&y, r, p);
+        }
+    }
+    let exp = inv(&(k / &d), &(&p_minus_1 / &d)).unwrap();
+    Some((y.modpow(&exp, p), d_factors))
+}
+
+/// Returns the least c >= 2 that is not an r-th power modulo p, where r is a prime dividing p - 1.
+fn non_residue(r: &BigInt, p: &BigInt) -> BigInt {
+    let exp: BigInt = (p - 1) / r;
+    let mut c = BigInt::from(2);
+    while c.modpow(&exp, p).is_one() {
+        c += 1;
+    }
+    c
+}
+
+/// Computes an r-th root of an r-th power delta modulo p with the Adleman-Manders-Miller algorithm,
+/// where r is a prime dividing p - 1.
+///
+/// Cao, Zhengjun, Qian Sha, and Xiao Fan. "Adleman-Manders-Miller root extraction method revisited." International Conference on Information Security and Cryptology. Springer, 2011.
+fn amm_root(delta: &BigInt, r: &BigInt, p: &BigInt) -> BigInt {
+    // p - 1 = r^t s with gcd(r, s) = 1
+    let mut s: BigInt = p - 1;
+    let mut t = 0;
+    while (&s % r).is_zero() {
+        s /= r;
+        t += 1;
+    }
+    // alpha = r^{-1} mod s, so that s | r alpha - 1
+    let alpha = if s.is_one() {
+        BigInt::zero()
+    } else {
+        inv(r, &s).unwrap()
+    };
+    let root = delta.modpow(&alpha, p);
+    if t == 1 {
+        return root;
+    }
+    let rho = non_residue(r, p);
+    let pow_r = |x: &BigInt, times: u64| (0..times).fold(x.clone(), |x, _| x.modpow(r, p));
+    // a has order r.
+    let a = pow_r(&rho.modpow(&s, p), t - 1);
+    let mut b = delta.modpow(&(r * &alpha - 1), p);
+    let mut c = rho.modpow(&s, p);
+    let mut h = BigInt::one();
+    let group = UnitGroup { n: p.clone() };
+    let log_table = BabyStepGiantStep::new(&group, &a, r);
+    for i in 1..t {
+        let d = pow_r(&b, t - 1 - i);
+        let j = if d.is_one() {
+            BigInt::zero()
+        } else {
+            let log = log_table.log(&group, &d).expect("d is a power of a");
+            (-log).mod_floor(r)
+        };
+        let cr = c.modpow(r, p);
+        b = b * cr.modpow(&j, p) % p;
+        h = h * c.modpow(&j, p) % p;
+        c = cr;
+    }
+    root * h % p
+}
+
+#[cfg(test)]
+mod tests {
+    use num::ToPrimitive;
+
+    use super::*;
+
+    fn brute_force_roots(a: i64, k: u32, n: i64) -> Vec<BigInt> {
+        (0..n)
+            .filter(|&x| (BigInt::from(x).pow(k) - a).mod_floor(&n.into()).is_zero())
+            .map(BigInt::from)
+            .collect()
+    }
+
+    #[test]
+    fn sqrt_mod_prime_works() {
+        // q = 2^64 - 2^32 + 1 is a prime with q - 1 = 2^32 * 3 * 5 * 17 * 257 * 65537.
+        let p = BigInt::from(1_000_000_007u64);
+        let q = BigInt::from(18_446_744_069_414_584_321u128);
+        for a in [0u64, 1, 2, 3, 5, 10, 123_456_789] {
+            let a = BigInt::from(a);
+            for p in [&p, &q] {
+                let x = tonelli_shanks(&a, p);
+                let y = cipolla(&a, p);
+                assert_eq!(x.is_some(), jacobi_symbol(&a, p) != -1);
+                assert_eq!(y.is_some(), x.is_some());
+                if let (Some(x), Some(y)) = (x, y) {
+                    assert_eq!((&x * &x) % p, a);
+                    assert!(y == x || y == p - x);
+                    assert_eq!(sqrt_mod_prime(&a, p).unwrap().modpow(&2.into(), p), a);
+                }
+            }
+        }
+        for p in [2, 3, 5, 13, 17, 97] {
+            for a in 0..p {
+                let roots = brute_force_roots(a, 2, p);
+                let x = tonelli_shanks(&a.into(), &p.into());
+                assert_eq!(x.is_some(), !roots.is_empty());
+                assert_eq!(cipolla(&a.into(), &p.into()).is_some(), !roots.is_empty());
+            }
+        }
+        // Both terminate even if p is not a prime, including squares, and any root they return is correct.
+        for p in [9, 21, 25, 33, 49, 65, 105, 561, 1105] {
+            for a in 0..p {
+                let roots = brute_force_roots(a, 2, p);
+                if let Some(x) = tonelli_shanks(&a.into(), &p.into()) {
+                    assert!(roots.contains(&x));
+                }
+                if let Some(x) = cipolla(&a.into(), &p.into()) {
+                    assert!(roots.contains(&x));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sqrt_mod_prime_power_works() {
+        for (p, e) in [(2, 1), (2, 2), (2, 3), (2, 6), (3, 4), (5, 3), (7, 2)] {
+            let pe = num::pow(p, e as usize);
+            for a in 0..pe {
+                assert_eq!(
+                    sqrt_mod_prime_power(&a.into(), &p.into(), e),
+                    brute_force_roots(a, 2, pe),
+                    "a = {}, p^e = {}^{}",
+                    a,
+                    p,
+                    e
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn sqrt_mod_works() {
+        for n in [1, 12, 60, 105, 360, 1001] {
+            for a in 0..n {
+                assert_eq!(sqrt_mod(&a.into(), &n.into()), brute_force_roots(a, 2, n));
+            }
+        }
+        // (10^9 + 7) (998244353): 4 roots of 4
+        let n = BigInt::from(1_000_000_007u64 * 998_244_353);
+        let roots = sqrt_mod(&4.into(), &n);
+        assert_eq!(roots.len(), 4);
+        assert_eq!(roots[0], BigInt::from(2));
+        assert_eq!(roots[3], &n - 2);
+    }
+
+    #[test]
+    fn kth_roots_mod_prime_works() {
+        for p in [2, 3, 7, 13, 31, 37, 73, 97] {
+            for k in 1..=12 {
+                for a in 0..p {
+                    let roots = kth_roots_mod_prime(&a.into(), &k.into(), &p.into());
+                    assert_eq!(
+                        roots,
+                        brute_force_roots(a, k, p),
+                        "a = {a}, k = {k}, p = {p}"
+                    );
+                    let x = kth_root_mod_prime(&a.into(), &k.into(), &p.into());
+                    assert_eq!(x.is_some(), !roots.is_empty());
+                }
+            }
+        }
+        // p - 1 = 2^23 * 7 * 17
+        let p = BigInt::from(998_244_353u64);
+        for k in [2u64, 7, 17, 119, 1 << 10, 3 << 12] {
+            let k = BigInt::from(k);
+            let a = BigInt::from(3).modpow(&k, &p);
+            let roots = kth_roots_mod_prime(&a, &k, &p);
+            assert_eq!(roots.len(), k.gcd(&(&p - 1)).to_usize().unwrap());
+            for x in &roots {
+                assert_eq!(x.modpow(&k, &p), a);
+            }
+        }
+    }
+}
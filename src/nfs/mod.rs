@@ -0,0 +1,4 @@
+pub mod polyselect;
+pub mod relation;
+pub mod sieve;
+pub mod sqrt;
@@ -0,0 +1,338 @@
+use num::{BigInt, Integer, One, Signed, ToPrimitive, Zero};
+use number_theory_elementary::primes;
+use std::f64::consts::PI;
+
+use crate::discriminant::discriminant;
+use crate::poly_mod::find_linear_factors;
+use crate::polynomial::Polynomial;
+use crate::resultant::resultant;
+
+/// A polynomial pair for the number field sieve.
+///
+/// f is the algebraic polynomial and g = X - m is the rational one. They have the common root m modulo N.
+#[derive(Clone, Debug)]
+pub struct PolyPair {
+    pub f: Polynomial<BigInt>,
+    pub g: Polynomial<BigInt>,
+    pub m: BigInt,
+    /// The skewness used to compute murphy_e.
+    pub skew: f64,
+    /// The root property of f.
+    pub alpha: f64,
+    /// Murphy's E score. Larger is better.
+    pub murphy_e: f64,
+}
+
+/// Parameters of Murphy's E score: the smoothness bounds on both sides and the sieve area.
+#[derive(Clone, Debug)]
+pub struct MurphyParams {
+    pub bf: f64,
+    pub bg: f64,
+    pub area: f64,
+}
+
+impl MurphyParams {
+    /// Parameters suitable for N with the given number of decimal digits.
+    pub fn for_digits(digits: usize) -> Self {
+        let b = 10f64.powf(4.0 + digits as f64 / 30.0);
+        let area = 10f64.powf(8.0 + digits as f64 / 12.0);
+        MurphyParams { bf: b, bg: b, area }
+    }
+}
+
+/// Configuration for polynomial selection.
+#[derive(Clone, Debug)]
+pub struct PolySelectConfig {
+    /// The degree of f.
+    pub degree: usize,
+    /// Leading coefficients 1, ..., max_leading are tried.
+    pub max_leading: u64,
+    /// Primes up to this bound are used to compute alpha.
+    pub alpha_bound: usize,
+    pub murphy: MurphyParams,
+}
+
+impl PolySelectConfig {
+    /// A default configuration for N with the given number of decimal digits.
+    pub fn for_digits(digits: usize) -> Self {
+        PolySelectConfig {
+            degree: default_degree(digits),
+            max_leading: 200,
+            alpha_bound: 200,
+            murphy: MurphyParams::for_digits(digits),
+        }
+    }
+}
+
+/// The usual degree of f for N with the given number of decimal digits.
+pub fn default_degree(digits: usize) -> usize {
+    match digits {
+        0..=50 => 3,
+        51..=90 => 4,
+        91..=140 => 5,
+        _ => 6,
+    }
+}
+
+/// Finds polynomial pairs for N with base-m expansion, and ranks them by Murphy's E score.
+///
+/// For each leading coefficient a_d <= max_leading, m is taken to be floor((N / a_d)^{1/d}),
+/// and N is expanded in base m with digits in (-m/2, m/2].
+/// Candidates are returned in decreasing order of the E score.
+pub fn select_polynomials(n: &BigInt, conf: &PolySelectConfig) -> Vec<PolyPair> {
+    let d = conf.degree;
+    assert!(d >= 2);
+    let rho = DickmanRho::new(40.0);
+    let mut result: Vec<PolyPair> = vec![];
+    for ad in 1..=conf.max_leading {
+        let m = (n / ad).nth_root(d as u32);
+        if m <= BigInt::one() {
+            break;
+        }
+        let f = base_m(n, &m);
+        if f.deg() != d || result.iter().any(|pair| pair.f == f) {
+            continue;
+        }
+        // f must be squarefree.
+        if discriminant(&f).is_zero() {
+            continue;
+        }
+        let g = Polynomial::from_raw(vec![-m.clone(), BigInt::one()]);
+        debug_assert_eq!(resultant(&f, &g).abs(), *n);
+        let skew = skewness(&f);
+        let alpha = alpha(&f, conf.alpha_bound);
+        let alpha_g = alpha_linear(conf.alpha_bound);
+        let murphy_e = murphy_e(&f, &g, alpha, alpha_g, skew, &conf.murphy, &rho);
+        result.push(PolyPair {
+            f,
+            g,
+            m,
+            skew,
+            alpha,
+            murphy_e,
+        });
+    }
+    result.sort_by(|a, b| b.murphy_e.total_cmp(&a.murphy_e));
+    result
+}
+
+/// Expands n in base m with digits in (-m/2, m/2].
+pub fn base_m(n: &BigInt, m: &BigInt) -> Polynomial<BigInt> {
+    let mut digits = vec![];
+    let mut rest = n.clone();
+    while !rest.is_zero() {
+        let (q, mut r) = rest.div_mod_floor(m);
+        rest = q;
+        if &r * 2 > *m {
+            r -= m;
+            rest += 1;
+        }
+        digits.push(r);
+    }
+    Polynomial::from_raw(digits)
+}
+
+/// Computes the skewness (|a_0| / |a_d|)^{1/d} of f, which balances the sizes of the coefficients.
+pub fn skewness(f: &Polynomial<BigInt>) -> f64 {
+    let d = f.deg();
+    let a0 = f.coef_at(0).abs().to_f64().unwrap_or(f64::MAX);
+    let ad = f.coef_at(d).abs().to_f64().unwrap_or(f64::MAX);
+    if a0 == 0.0 {
+        return 1.0;
+    }
+    (a0 / ad).powf(1.0 / d as f64).max(1.0)
+}
+
+/// Computes the root property alpha(f) with primes up to bound.
+///
+/// alpha(f) = \sum_p (1/(p-1) - q_p p/(p^2-1)) log p, where q_p is the number of roots of f mod p,
+/// including the root at infinity if p divides the leading coefficient.
+/// This formula is exact only for primes not dividing disc(f).
+/// A negative alpha means that values of f are more likely to be smooth than random integers of the same size.
+pub fn alpha(f: &Polynomial<BigInt>, bound: usize) -> f64 {
+    let lc = f.coef_at(f.deg());
+    let mut sum = 0.0;
+    for p in primes(bound) {
+        let pb = BigInt::from(p);
+        let mut roots = find_linear_factors::<BigInt>(f, pb.clone());
+        roots.sort();
+        roots.dedup();
+        let mut q = roots.len();
+        if lc.is_multiple_of(&pb) {
+            q += 1;
+        }
+        sum += alpha_term(p, q);
+    }
+    sum
+}
+
+/// alpha of a linear polynomial, which has exactly one root modulo every prime.
+fn alpha_linear(bound: usize) -> f64 {
+    primes(bound).into_iter().map(|p| alpha_term(p, 1)).sum()
+}
+
+fn alpha_term(p: usize, q: usize) -> f64 {
+    let pf = p as f64;
+    (1.0 / (pf - 1.0) - q as f64 * pf / (pf * pf - 1.0)) * pf.ln()
+}
+
+/// Computes Murphy's E score of (f, g).
+///
+/// Values of the homogenized polynomials are sampled at K points on an ellipse of the given area and skewness,
+/// and the probability that both of them are smooth is estimated with the Dickman rho function.
+pub fn murphy_e(
+    f: &Polynomial<BigInt>,
+    g: &Polynomial<BigInt>,
+    alpha_f: f64,
+    alpha_g: f64,
+    skew: f64,
+    params: &MurphyParams,
+    rho: &DickmanRho,
+) -> f64 {
+    const K: usize = 1000;
+    let fc = to_f64_coefs(f);
+    let gc = to_f64_coefs(g);
+    let (lbf, lbg) = (params.bf.ln(), params.bg.ln());
+    let mut sum = 0.0;
+    for i in 0..K {
+        let theta = PI / K as f64 * (i as f64 + 0.5);
+        let x = theta.cos() * (params.area * skew).sqrt();
+        let y = theta.sin() * (params.area / skew).sqrt();
+        let uf = (homogeneous_value(&fc, x, y).abs().ln() + alpha_f) / lbf;
+        let ug = (homogeneous_value(&gc, x, y).abs().ln() + alpha_g) / lbg;
+        sum += rho.eval(uf) * rho.eval(ug);
+    }
+    sum / K as f64
+}
+
+pub(crate) fn to_f64_coefs(f: &Polynomial<BigInt>) -> Vec<f64> {
+    f.dat
+        .iter()
+        .map(|c| c.to_f64().unwrap_or(f64::MAX))
+        .collect()
+}
+
+/// Computes F(x, y) = y^d f(x/y).
+pub(crate) fn homogeneous_value(coefs: &[f64], x: f64, y: f64) -> f64 {
+    // Horner's method from the leading coefficient: each step multiplies by x and adds c_i y^{d-i}.
+    let mut value = 0.0;
+    let mut ypow = 1.0;
+    for &c in coefs.iter().rev() {
+        value = value * x + c * ypow;
+        ypow *= y;
+    }
+    value
+}
+
+/// A table of the Dickman rho function.
+///
+/// rho(u) is the probability that a random integer x has no prime factors larger than x^{1/u}.
+pub struct DickmanRho {
+    step: f64,
+    table: Vec<f64>,
+}
+
+impl DickmanRho {
+    /// The number of table entries per unit length.
+    const RESOLUTION: usize = 256;
+
+    /// Tabulates rho on [0, max_u].
+    ///
+    /// rho(u) = 1 - log u on [1, 2]. Beyond that, u rho'(u) = -rho(u - 1) is integrated with the trapezoidal rule.
+    pub fn new(max_u: f64) -> Self {
+        let res = Self::RESOLUTION;
+        let h = 1.0 / res as f64;
+        let len = ((max_u * res as f64).ceil() as usize + 1).max(2 * res + 1);
+        let mut table = vec![1.0; len];
+        for (i, value) in table.iter_mut().enumerate().take(2 * res + 1).skip(res) {
+            *value = 1.0 - (i as f64 * h).ln();
+        }
+        for i in 2 * res + 1..len {
+            let u = i as f64 * h;
+            let f0 = table[i - 1 - res] / (u - h);
+            let f1 = table[i - res] / u;
+            table[i] = table[i - 1] - h * (f0 + f1) / 2.0;
+        }
+        DickmanRho { step: h, table }
+    }
+
+    /// Evaluates rho(u) with linear interpolation. Returns 0 beyond the table.
+    pub fn eval(&self, u: f64) -> f64 {
+        if u <= 1.0 {
+            return 1.0;
+        }
+        let pos = u / self.step;
+        let i = pos.floor() as usize;
+        if i + 1 >= self.table.len() {
+            return 0.0;
+        }
+        let t = pos - i as f64;
+        self.table[i] * (1.0 - t) + self.table[i + 1] * t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn dickman_rho_works() {
+        let rho = DickmanRho::new(10.0);
+        assert!((rho.eval(0.5) - 1.0).abs() < 1e-9);
+        assert!((rho.eval(2.0) - (1.0 - 2.0f64.ln())).abs() < 1e-5);
+        assert!((rho.eval(3.0) - 0.0486083882).abs() < 1e-5);
+        assert!(
+            (rho.eval(4.0) - 0.0049109256).abs() < 1e-6,
+            "{}",
+            rho.eval(4.0)
+        );
+    }
+
+    #[test]
+    fn base_m_works() {
+        let n = BigInt::from(1_000_000_016_000_000_063u64);
+        let m = BigInt::from(1_000_000);
+        let f = base_m(&n, &m);
+        assert_eq!(f.of(&m), n);
+        for c in &f.dat {
+            assert!(c * 2 <= m && c * 2 > -&m);
+        }
+    }
+
+    #[test]
+    fn alpha_works() {
+        // A linear polynomial has alpha = \sum_p log p / (p^2 - 1) ~ 0.57.
+        let g = Polynomial::from_raw(vec![BigInt::from(-12345), BigInt::one()]);
+        let a = alpha(&g, 2000);
+        assert!((a - alpha_linear(2000)).abs() < 1e-9);
+        assert!((a - 0.57).abs() < 0.01, "a = {}", a);
+        // X(X-1)(X-2)(X-3) has 4 roots modulo every p >= 5, so its values are very likely to be smooth.
+        let f = Polynomial::from_raw(vec![
+            0.into(),
+            (-6).into(),
+            11.into(),
+            (-6).into(),
+            1.into(),
+        ]);
+        assert!(alpha(&f, 2000) < -3.0, "alpha = {}", alpha(&f, 2000));
+    }
+
+    #[test]
+    fn select_polynomials_works() {
+        let n = BigInt::from_str("290124695775024043134364775079652767238256990948361607195403")
+            .unwrap();
+        let mut conf = PolySelectConfig::for_digits(60);
+        conf.max_leading = 20;
+        let pairs = select_polynomials(&n, &conf);
+        assert!(!pairs.is_empty());
+        for pair in &pairs {
+            assert_eq!(pair.f.deg(), 4);
+            assert_eq!(pair.f.of(&pair.m), n);
+            assert_eq!(resultant(&pair.f, &pair.g).abs(), n);
+        }
+        for w in pairs.windows(2) {
+            assert!(w[0].murphy_e >= w[1].murphy_e);
+        }
+    }
+}
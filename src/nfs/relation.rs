@@ -0,0 +1,140 @@
+use num::{BigInt, BigRational};
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use crate::algebraic::Algebraic;
+use crate::polynomial::Polynomial;
+
+/// A relation of the number field sieve: a coprime pair (a, b) with b > 0
+/// such that both a - bm and F(a, b) = b^d f(a/b) = lc(f) N(a - b theta) split into small primes.
+///
+/// Relations are written one per line in the following text format:
+///
+/// ```text
+/// a,b:p_1,p_2,...,p_k:q_1,q_2,...,q_l
+/// ```
+///
+/// a and b are decimal integers. p_1, ..., p_k are the prime factors of |a - bm| and
+/// q_1, ..., q_l are the prime factors of |F(a, b)|, both in decimal and listed with multiplicity,
+/// so that p_1 ... p_k = |a - bm| and q_1 ... q_l = |F(a, b)|. A list may be empty if the norm is 1.
+/// For example, `-3,7:2,2,5:3,31` says that |-3 - 7m| = 20 and |F(-3, 7)| = 93.
+/// The prime ideal above q_i is determined by a/b mod q_i, so it is not written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Relation {
+    pub a: i64,
+    pub b: u64,
+    /// Prime factors of the rational norm |a - bm|.
+    pub rational: Vec<u64>,
+    /// Prime factors of the algebraic norm |F(a, b)|.
+    pub algebraic: Vec<u64>,
+}
+
+impl Relation {
+    /// Returns a - b theta.
+    pub fn algebraic_element(&self, theta: &Algebraic) -> Algebraic {
+        let expr = Polynomial::from_raw(vec![
+            BigRational::from_integer(self.a.into()),
+            BigRational::from_integer(-BigInt::from(self.b)),
+        ]);
+        Algebraic::with_expr(theta.min_poly.clone(), expr)
+    }
+}
+
+impl Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}:", self.a, self.b)?;
+        write_list(f, &self.rational)?;
+        write!(f, ":")?;
+        write_list(f, &self.algebraic)
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter, primes: &[u64]) -> fmt::Result {
+    for (i, p) in primes.iter().enumerate() {
+        if i > 0 {
+            write!(f, ",")?;
+        }
+        write!(f, "{}", p)?;
+    }
+    Ok(())
+}
+
+/// The error returned when a line is not in the relation format.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseRelationError;
+
+impl FromStr for Relation {
+    type Err = ParseRelationError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().split(':');
+        let (ab, rational, algebraic) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(ab), Some(rational), Some(algebraic)) => (ab, rational, algebraic),
+            _ => return Err(ParseRelationError),
+        };
+        if parts.next().is_some() {
+            return Err(ParseRelationError);
+        }
+        let (a, b) = ab.split_once(',').ok_or(ParseRelationError)?;
+        Ok(Relation {
+            a: a.parse().map_err(|_| ParseRelationError)?,
+            b: b.parse().map_err(|_| ParseRelationError)?,
+            rational: parse_list(rational)?,
+            algebraic: parse_list(algebraic)?,
+        })
+    }
+}
+
+fn parse_list(s: &str) -> Result<Vec<u64>, ParseRelationError> {
+    if s.is_empty() {
+        return Ok(vec![]);
+    }
+    s.split(',')
+        .map(|p| p.parse().map_err(|_| ParseRelationError))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::Order;
+
+    #[test]
+    fn relation_format_works() {
+        let rel = Relation {
+            a: -3,
+            b: 7,
+            rational: vec![2, 2, 5],
+            algebraic: vec![3, 31],
+        };
+        let s = rel.to_string();
+        assert_eq!(s, "-3,7:2,2,5:3,31");
+        assert_eq!(s.parse::<Relation>(), Ok(rel));
+        let empty: Relation = "5,1::".parse().unwrap();
+        assert!(empty.rational.is_empty() && empty.algebraic.is_empty());
+        assert_eq!(empty.to_string(), "5,1::");
+        assert!("5,1:2".parse::<Relation>().is_err());
+        assert!("5:2:3".parse::<Relation>().is_err());
+        assert!("5,1:x:3".parse::<Relation>().is_err());
+    }
+
+    #[test]
+    fn algebraic_element_works() {
+        // theta^3 - 2: N(a - b theta) = a^3 - 2 b^3
+        let theta = Algebraic::new(Polynomial::from_raw(vec![
+            (-2).into(),
+            0.into(),
+            0.into(),
+            1.into(),
+        ]));
+        let order = Order::singly_gen(&theta);
+        let mult_table = order.get_mult_table(&theta);
+        let rel = Relation {
+            a: 5,
+            b: 3,
+            rational: vec![],
+            algebraic: vec![],
+        };
+        let elem = order.to_z_basis_int(&rel.algebraic_element(&theta));
+        assert_eq!(mult_table.norm(&elem), BigInt::from(125 - 2 * 27));
+    }
+}
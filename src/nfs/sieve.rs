@@ -0,0 +1,541 @@
+use num::{BigInt, Integer, One, Signed, ToPrimitive, Zero};
+use number_theory_elementary::primes;
+use std::collections::HashSet;
+use std::ops::Range;
+
+use crate::algebraic::Algebraic;
+use crate::ideal::Ideal;
+use crate::mult_table::MultTable;
+use crate::nfs::polyselect::{homogeneous_value, to_f64_coefs};
+use crate::nfs::relation::Relation;
+use crate::order::Order;
+use crate::poly_mod::find_linear_factors;
+use crate::polynomial::Polynomial;
+use crate::prime::is_prime;
+
+/// A prime ideal of degree 1, which is generated by p and theta - r.
+///
+/// (a, b) is divisible by this ideal (i.e. the ideal divides a - b theta) if and only if a = br (mod p).
+/// r = p denotes the projective root, which exists if p divides the leading coefficient;
+/// (a, b) is divisible by it if and only if b = 0 (mod p).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DegreeOnePrime {
+    pub p: u32,
+    pub r: u32,
+}
+
+impl DegreeOnePrime {
+    pub fn is_projective(&self) -> bool {
+        self.p == self.r
+    }
+
+    /// Returns true if this ideal divides a - b theta.
+    pub fn divides(&self, a: i64, b: i64) -> bool {
+        let p = self.p as i128;
+        if self.is_projective() {
+            return (b as i128).rem_euclid(p) == 0;
+        }
+        (a as i128 - b as i128 * self.r as i128).rem_euclid(p) == 0
+    }
+
+    /// Returns this ideal as an ideal of the given order, namely (p) + (theta - r).
+    ///
+    /// theta - r must be in the order, which is the case if theta is an algebraic integer.
+    pub fn to_ideal<'mul>(
+        &self,
+        order: &Order,
+        theta: &Algebraic,
+        mult_table: &'mul MultTable,
+    ) -> Ideal<'mul> {
+        assert!(!self.is_projective());
+        let deg = theta.deg();
+        let p = Algebraic::from_int(theta.min_poly.clone(), self.p);
+        let gen = theta - &Algebraic::from_int(theta.min_poly.clone(), self.r);
+        let p = Ideal::principal(&order.to_z_basis_int(&p), mult_table);
+        let gen = Ideal::principal(&order.to_z_basis_int(&gen), mult_table);
+        debug_assert_eq!(p.deg(), deg);
+        &p + &gen
+    }
+}
+
+/// Factor bases on both sides.
+///
+/// The rational side consists of (p, m mod p) for g = X - m, and the algebraic side consists of
+/// all degree-1 prime ideals (p, r) of f. Both are sorted by p.
+#[derive(Clone, Debug)]
+pub struct FactorBase {
+    pub rational: Vec<DegreeOnePrime>,
+    pub algebraic: Vec<DegreeOnePrime>,
+}
+
+impl FactorBase {
+    /// Collects primes up to rational_bound on the rational side and up to algebraic_bound on the algebraic side.
+    pub fn new(
+        f: &Polynomial<BigInt>,
+        m: &BigInt,
+        rational_bound: usize,
+        algebraic_bound: usize,
+    ) -> Self {
+        let rational = primes(rational_bound + 1)
+            .into_iter()
+            .map(|p| DegreeOnePrime {
+                p: p as u32,
+                r: (m % p).to_u32().unwrap(),
+            })
+            .collect();
+        let mut algebraic = vec![];
+        for p in primes(algebraic_bound + 1) {
+            for r in roots_mod_p(f, p as u32) {
+                algebraic.push(DegreeOnePrime { p: p as u32, r });
+            }
+        }
+        FactorBase {
+            rational,
+            algebraic,
+        }
+    }
+}
+
+/// Finds the roots of f modulo p, including the projective root p if p divides the leading coefficient.
+pub fn roots_mod_p(f: &Polynomial<BigInt>, p: u32) -> Vec<u32> {
+    let pb = BigInt::from(p);
+    let reduced: Vec<i64> = f
+        .dat
+        .iter()
+        .map(|c| c.mod_floor(&pb).to_i64().unwrap())
+        .collect();
+    if reduced.iter().all(|c| *c == 0) {
+        // Every (a, b) would be divisible by p. Such primes are left to the large prime check.
+        return vec![];
+    }
+    let mut roots: Vec<u32> = find_linear_factors::<i64>(&Polynomial::from_raw(reduced), p as i64)
+        .into_iter()
+        .map(|r| r as u32)
+        .collect();
+    roots.sort_unstable();
+    roots.dedup();
+    if f.coef_at(f.deg()).is_multiple_of(&pb) {
+        roots.push(p);
+    }
+    roots
+}
+
+/// Finds the special-q's in the given range: all affine degree-1 prime ideals (q, r) of f with q in range.
+pub fn special_qs(f: &Polynomial<BigInt>, range: Range<u32>) -> Vec<DegreeOnePrime> {
+    let mut result = vec![];
+    for q in primes(range.end as usize) {
+        let q = q as u32;
+        if q < range.start {
+            continue;
+        }
+        for r in roots_mod_p(f, q) {
+            if r != q {
+                result.push(DegreeOnePrime { p: q, r });
+            }
+        }
+    }
+    result
+}
+
+/// Configuration of the lattice siever.
+#[derive(Clone, Debug)]
+pub struct SieveConfig {
+    /// The sieve region is -I/2 <= i < I/2 and 0 < j < J, where I = 2^log_i.
+    pub log_i: u32,
+    /// J in the above.
+    pub j_max: u32,
+    /// Bounds of the factor bases.
+    pub rational_bound: usize,
+    pub algebraic_bound: usize,
+    /// Large prime bounds. A norm may have one prime factor above the factor base bound and below this bound.
+    pub rational_lpb: u64,
+    pub algebraic_lpb: u64,
+    /// Bits by which the unfactored part of a norm may exceed the large prime bound after sieving.
+    /// This accounts for prime powers, which are not sieved.
+    pub slack: f64,
+    /// The skewness of f; the lattice is reduced with respect to the norm a^2 + (skew b)^2.
+    pub skew: f64,
+}
+
+impl SieveConfig {
+    /// A default configuration for N with the given number of decimal digits.
+    pub fn for_digits(digits: usize) -> Self {
+        let (log_i, bound) = match digits {
+            0..=60 => (11, 100_000),
+            61..=70 => (11, 200_000),
+            71..=80 => (12, 400_000),
+            _ => (12, 800_000),
+        };
+        SieveConfig {
+            log_i,
+            j_max: 1 << (log_i - 1),
+            rational_bound: bound,
+            algebraic_bound: bound,
+            rational_lpb: bound as u64 * 64,
+            algebraic_lpb: bound as u64 * 64,
+            slack: 4.0,
+            skew: 1.0,
+        }
+    }
+}
+
+/// Computes F(a, b) = b^d f(a/b).
+pub fn algebraic_norm(f: &Polynomial<BigInt>, a: i64, b: i64) -> BigInt {
+    let (a, b) = (BigInt::from(a), BigInt::from(b));
+    let mut value = BigInt::zero();
+    let mut bpow = BigInt::one();
+    for c in f.dat.iter().rev() {
+        value = value * &a + c * &bpow;
+        bpow *= &b;
+    }
+    value
+}
+
+/// Finds a reduced basis of the lattice {(a, b) | a = br (mod q)} with the Lagrange-Gauss algorithm.
+///
+/// Lengths are measured with a^2 + (skew b)^2.
+pub fn reduce_lattice(q: u32, r: u32, skew: f64) -> [(i64, i64); 2] {
+    let s2 = skew * skew;
+    let norm = |v: (i64, i64)| (v.0 as f64).powi(2) + s2 * (v.1 as f64).powi(2);
+    let dot = |u: (i64, i64), v: (i64, i64)| u.0 as f64 * v.0 as f64 + s2 * u.1 as f64 * v.1 as f64;
+    let mut u = (q as i64, 0);
+    let mut v = (r as i64, 1);
+    if norm(u) > norm(v) {
+        std::mem::swap(&mut u, &mut v);
+    }
+    loop {
+        let k = (dot(u, v) / norm(u)).round() as i64;
+        v = (v.0 - k * u.0, v.1 - k * u.1);
+        if norm(v) >= norm(u) {
+            break;
+        }
+        std::mem::swap(&mut u, &mut v);
+    }
+    [u, v]
+}
+
+/// Computes x^{-1} mod p for a prime p.
+fn mod_inv(x: i64, p: i64) -> i64 {
+    let (mut a, mut b) = (x.rem_euclid(p), p);
+    let (mut s, mut t) = (1i64, 0i64);
+    while b != 0 {
+        let k = a / b;
+        (a, b) = (b, a - k * b);
+        (s, t) = (t, s - k * t);
+    }
+    debug_assert_eq!(a, 1);
+    s.rem_euclid(p)
+}
+
+/// Adds log p to every cell (i, j) such that i u + j v is divisible by an ideal in primes.
+fn sieve_side(
+    sieve: &mut [u8],
+    primes: &[DegreeOnePrime],
+    [u, v]: [(i64, i64); 2],
+    conf: &SieveConfig,
+    special_q: Option<&DegreeOnePrime>,
+) {
+    let width = 1i64 << conf.log_i;
+    let half = width / 2;
+    let height = conf.j_max as i64;
+    for ideal in primes {
+        if special_q == Some(ideal) {
+            continue;
+        }
+        let p = ideal.p as i64;
+        // (a, b) = i u + j v is divisible by the ideal if and only if i alpha + j beta = 0 (mod p).
+        let (alpha, beta) = if ideal.is_projective() {
+            (u.1.rem_euclid(p), v.1.rem_euclid(p))
+        } else {
+            let r = ideal.r as i64;
+            (
+                (u.0 - r * u.1.rem_euclid(p) % p).rem_euclid(p),
+                (v.0 - r * v.1.rem_euclid(p) % p).rem_euclid(p),
+            )
+        };
+        let logp = (p as f64).log2().round() as u8;
+        if alpha == 0 {
+            if beta == 0 {
+                continue;
+            }
+            // Only the rows j = 0 (mod p) are hit, entirely.
+            for j in (p..height).step_by(p as usize) {
+                let row = &mut sieve[(j * width) as usize..((j + 1) * width) as usize];
+                for cell in row {
+                    *cell = cell.saturating_add(logp);
+                }
+            }
+            continue;
+        }
+        // i = j step (mod p)
+        let step = (p - beta) % p * mod_inv(alpha, p) % p;
+        let mut start = half % p;
+        for j in 1..height {
+            start = (start + step) % p;
+            let row = &mut sieve[(j * width) as usize..((j + 1) * width) as usize];
+            let mut x = start as usize;
+            while x < row.len() {
+                row[x] = row[x].saturating_add(logp);
+                x += p as usize;
+            }
+        }
+    }
+}
+
+/// Divides norm by the primes of the ideals dividing a - b theta, and checks that the remaining part is 1
+/// or a prime below lpb. Returns the prime factors of norm with multiplicity.
+fn factor_norm(
+    mut norm: BigInt,
+    primes: &[DegreeOnePrime],
+    a: i64,
+    b: i64,
+    lpb: u64,
+    factors: &mut Vec<u64>,
+) -> Option<()> {
+    let mut last = 0;
+    for ideal in primes {
+        if ideal.p == last || !ideal.divides(a, b) {
+            continue;
+        }
+        last = ideal.p;
+        let p = BigInt::from(ideal.p);
+        loop {
+            let (q, r) = norm.div_rem(&p);
+            if !r.is_zero() {
+                break;
+            }
+            norm = q;
+            factors.push(ideal.p as u64);
+        }
+    }
+    if norm.is_one() {
+        return Some(());
+    }
+    let rest = norm.to_u64().filter(|&rest| rest <= lpb)?;
+    let pmax = primes.last().map_or(1, |ideal| ideal.p as u64);
+    if rest > pmax * pmax && !is_prime(&norm) {
+        return None;
+    }
+    factors.push(rest);
+    Some(())
+}
+
+/// Collects relations in the lattice of (a, b) divisible by special_q.
+///
+/// The lattice is spanned by a reduced basis u, v, and the points i u + j v in the sieve region are sieved
+/// with the factor bases on both sides, line by line.
+/// Candidates whose sieved parts are large enough are factored exactly.
+/// special_q divides the algebraic norm of every relation found.
+pub fn lattice_sieve(
+    f: &Polynomial<BigInt>,
+    m: &BigInt,
+    fb: &FactorBase,
+    special_q: &DegreeOnePrime,
+    conf: &SieveConfig,
+) -> Vec<Relation> {
+    assert!(!special_q.is_projective());
+    let basis = reduce_lattice(special_q.p, special_q.r, conf.skew);
+    let width = 1usize << conf.log_i;
+    let half = (width / 2) as i64;
+    let height = conf.j_max as usize;
+    let mut rational_sieve = vec![0u8; width * height];
+    let mut algebraic_sieve = vec![0u8; width * height];
+    sieve_side(&mut rational_sieve, &fb.rational, basis, conf, None);
+    sieve_side(
+        &mut algebraic_sieve,
+        &fb.algebraic,
+        basis,
+        conf,
+        Some(special_q),
+    );
+
+    let fc = to_f64_coefs(f);
+    let mf = m.to_f64().unwrap();
+    let log_q = (special_q.p as f64).log2();
+    let rational_threshold = (conf.rational_lpb as f64).log2() + conf.slack;
+    let algebraic_threshold = (conf.algebraic_lpb as f64).log2() + conf.slack;
+    let [u, v] = basis;
+    let mut relations = vec![];
+    for j in 1..height {
+        for x in 0..width {
+            let idx = j * width + x;
+            let i = x as i64 - half;
+            let (mut a, mut b) = (i * u.0 + j as i64 * v.0, i * u.1 + j as i64 * v.1);
+            if b == 0 {
+                continue;
+            }
+            let log_r = (a as f64 - b as f64 * mf).abs().log2();
+            if log_r - rational_sieve[idx] as f64 > rational_threshold {
+                continue;
+            }
+            let log_a = homogeneous_value(&fc, a as f64, b as f64).abs().log2() - log_q;
+            if log_a - algebraic_sieve[idx] as f64 > algebraic_threshold {
+                continue;
+            }
+            if b < 0 {
+                a = -a;
+                b = -b;
+            }
+            if a.gcd(&b) != 1 {
+                continue;
+            }
+            if let Some(rel) = check_relation(f, m, fb, special_q, conf, a, b) {
+                relations.push(rel);
+            }
+        }
+    }
+    relations
+}
+
+fn check_relation(
+    f: &Polynomial<BigInt>,
+    m: &BigInt,
+    fb: &FactorBase,
+    special_q: &DegreeOnePrime,
+    conf: &SieveConfig,
+    a: i64,
+    b: i64,
+) -> Option<Relation> {
+    let mut rational = vec![];
+    let rational_norm = (BigInt::from(a) - m * b).abs();
+    factor_norm(
+        rational_norm,
+        &fb.rational,
+        a,
+        b,
+        conf.rational_lpb,
+        &mut rational,
+    )?;
+    let mut algebraic = vec![special_q.p as u64];
+    let norm = algebraic_norm(f, a, b).abs();
+    debug_assert!((&norm % special_q.p).is_zero());
+    let norm = norm / special_q.p;
+    factor_norm(
+        norm,
+        &fb.algebraic,
+        a,
+        b,
+        conf.algebraic_lpb,
+        &mut algebraic,
+    )?;
+    rational.sort_unstable();
+    algebraic.sort_unstable();
+    Some(Relation {
+        a,
+        b: b as u64,
+        rational,
+        algebraic,
+    })
+}
+
+/// Sieves the special-q's in q_range in increasing order until at least wanted relations are found.
+///
+/// Relations found with more than one special-q are reported only once.
+pub fn collect_relations(
+    f: &Polynomial<BigInt>,
+    m: &BigInt,
+    q_range: Range<u32>,
+    wanted: usize,
+    conf: &SieveConfig,
+    verbose: bool,
+) -> Vec<Relation> {
+    let fb = FactorBase::new(f, m, conf.rational_bound, conf.algebraic_bound);
+    let mut seen = HashSet::new();
+    let mut relations = vec![];
+    for special_q in special_qs(f, q_range) {
+        for rel in lattice_sieve(f, m, &fb, &special_q, conf) {
+            if seen.insert((rel.a, rel.b)) {
+                relations.push(rel);
+            }
+        }
+        if verbose {
+            eprintln!(
+                "special-q = ({}, {}): {} relations",
+                special_q.p,
+                special_q.r,
+                relations.len()
+            );
+        }
+        if relations.len() >= wanted {
+            break;
+        }
+    }
+    relations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nfs::polyselect::{select_polynomials, PolySelectConfig};
+    use std::str::FromStr;
+
+    fn small_config() -> SieveConfig {
+        SieveConfig {
+            log_i: 9,
+            j_max: 256,
+            rational_bound: 5000,
+            algebraic_bound: 5000,
+            rational_lpb: 1 << 18,
+            algebraic_lpb: 1 << 18,
+            slack: 4.0,
+            skew: 1.0,
+        }
+    }
+
+    #[test]
+    fn reduce_lattice_works() {
+        let (q, r) = (1_000_003u32, 123_456u32);
+        let [u, v] = reduce_lattice(q, r, 1.0);
+        for w in [u, v] {
+            assert_eq!((w.0 - w.1 * r as i64).rem_euclid(q as i64), 0);
+        }
+        // A reduced basis is almost orthogonal: |u| |v| <= 2 q / sqrt(3).
+        let norm = |w: (i64, i64)| (w.0 as f64).hypot(w.1 as f64);
+        assert!(
+            norm(u) * norm(v) <= 2.0 * q as f64 / 3f64.sqrt(),
+            "{:?} {:?}",
+            u,
+            v
+        );
+        assert_eq!((u.0 * v.1 - u.1 * v.0).abs(), q as i64);
+    }
+
+    #[test]
+    fn degree_one_prime_to_ideal_works() {
+        // theta^3 - 2 has the root 3 modulo 5.
+        let f = Polynomial::from_raw(vec![(-2).into(), 0.into(), 0.into(), 1.into()]);
+        assert_eq!(roots_mod_p(&f, 5), vec![3]);
+        let theta = Algebraic::new(f);
+        let order = Order::singly_gen(&theta);
+        let mult_table = order.get_mult_table(&theta);
+        let ideal = DegreeOnePrime { p: 5, r: 3 }.to_ideal(&order, &theta, &mult_table);
+        assert_eq!(ideal.norm(), BigInt::from(5));
+        let elem = &theta - &Algebraic::from_int(theta.min_poly.clone(), 8);
+        assert!(ideal.contains(&order.to_z_basis_int(&elem)));
+    }
+
+    #[test]
+    fn lattice_sieve_works() {
+        let n = BigInt::from_str("100000000000003700000000000000111").unwrap();
+        let mut poly_conf = PolySelectConfig::for_digits(33);
+        poly_conf.max_leading = 5;
+        let pair = &select_polynomials(&n, &poly_conf)[0];
+        let conf = small_config();
+        let fb = FactorBase::new(&pair.f, &pair.m, conf.rational_bound, conf.algebraic_bound);
+        let special_q = special_qs(&pair.f, 6000..7000)[0];
+        let relations = lattice_sieve(&pair.f, &pair.m, &fb, &special_q, &conf);
+        assert!(!relations.is_empty());
+        for rel in &relations {
+            let (a, b) = (rel.a, rel.b as i64);
+            assert_eq!(a.gcd(&b), 1);
+            assert!(special_q.divides(a, b));
+            let rational: BigInt = rel.rational.iter().map(|&p| BigInt::from(p)).product();
+            let algebraic: BigInt = rel.algebraic.iter().map(|&p| BigInt::from(p)).product();
+            assert_eq!(rational, (BigInt::from(a) - &pair.m * b).abs());
+            assert_eq!(algebraic, algebraic_norm(&pair.f, a, b).abs());
+            for &p in rel.rational.iter().chain(&rel.algebraic) {
+                assert!(is_prime(&p.into()));
+            }
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use num::{BigInt, BigRational, Integer, One, Signed, Zero};
+use number_theory_elementary::primes;
+
+use crate::algebraic::Algebraic;
+use crate::mult_table::MultTable;
+use crate::nfs::relation::Relation;
+use crate::order::Order;
+use crate::poly_mod::factorize_mod_p;
+use crate::polynomial::Polynomial;
+
+/// Primes below this bound are tried to find an inert prime.
+const INERT_PRIME_BOUND: usize = 10_000;
+
+/// Finds gcd(x - y, n) from relations whose products are squares on both sides.
+///
+/// Let c be the leading coefficient of f and omega = c theta, which is a root of the monic polynomial
+/// F(X) = c^{d-1} f(X / c). Then c^{k + (k mod 2)} F'(omega)^2 \prod (ca - b omega) is a square in Z\[omega\]
+/// for the k relations, whose square root maps to x under omega -> cm, and y is obtained from the rational side.
+/// Returns None if the algebraic side is not a square or gcd(x - y, n) is trivial.
+pub fn find_factor(
+    n: &BigInt,
+    f: &Polynomial<BigInt>,
+    m: &BigInt,
+    relations: &[&Relation],
+) -> Option<BigInt> {
+    let d = f.deg();
+    let c = f.coef_at(d);
+    let k = relations.len();
+    let monic = monic_polynomial(f);
+    let omega = Algebraic::new(monic.clone());
+    let order = Order::singly_gen(&omega);
+    let mult_table = order.get_mult_table(&omega);
+
+    let factors: Vec<Vec<BigInt>> = relations
+        .iter()
+        .map(|rel| {
+            let expr = Polynomial::from_raw(vec![
+                BigRational::from_integer(&c * rel.a),
+                BigRational::from_integer(-BigInt::from(rel.b)),
+            ]);
+            order.to_z_basis_int(&Algebraic::with_expr(monic.clone(), expr))
+        })
+        .collect();
+    let mut delta = product(&factors, &mult_table);
+    let mut derivative = monic.differential().dat;
+    derivative.resize(d, BigInt::zero());
+    delta = mult_table.mul(&delta, &mult_table.mul(&derivative, &derivative));
+    if k % 2 == 1 {
+        delta.iter_mut().for_each(|x| *x *= &c);
+    }
+    let sqrt = algebraic_sqrt(&delta, &monic, &mult_table)?;
+
+    let image = (&c * m).mod_floor(n);
+    let mut x = BigInt::zero();
+    for coef in sqrt.iter().rev() {
+        x = (x * &image + coef).mod_floor(n);
+    }
+    let mut y = rational_sqrt(relations, n)?;
+    y = y * c.modpow(&BigInt::from(k.div_ceil(2)), n) % n;
+    y = y * monic.differential().of(&image) % n;
+    let g = (x - y).gcd(n);
+    if g.is_one() || &g == n {
+        return None;
+    }
+    Some(g)
+}
+
+/// Computes c^{d-1} f(X / c), where c is the leading coefficient of f.
+fn monic_polynomial(f: &Polynomial<BigInt>) -> Polynomial<BigInt> {
+    let d = f.deg();
+    let c = f.coef_at(d);
+    let mut dat = f.dat.clone();
+    let mut cpow = BigInt::one();
+    for i in (0..d).rev() {
+        dat[i] *= &cpow;
+        cpow *= &c;
+    }
+    dat[d] = BigInt::one();
+    Polynomial::from_raw(dat)
+}
+
+/// Computes \prod |a - bm| modulo n, provided that every prime appears an even number of times.
+fn rational_sqrt(relations: &[&Relation], n: &BigInt) -> Option<BigInt> {
+    let mut primes: Vec<u64> = relations
+        .iter()
+        .flat_map(|rel| rel.rational.iter().copied())
+        .collect();
+    primes.sort_unstable();
+    if primes.len() % 2 != 0 {
+        return None;
+    }
+    let mut y = BigInt::one();
+    for pair in primes.chunks(2) {
+        if pair[0] != pair[1] {
+            return None;
+        }
+        y = y * pair[0] % n;
+    }
+    Some(y)
+}
+
+/// Multiplies elements with a product tree.
+fn product(elems: &[Vec<BigInt>], mult_table: &MultTable) -> Vec<BigInt> {
+    match elems.len() {
+        0 => {
+            let mut one = vec![BigInt::zero(); mult_table.deg()];
+            one[0] = BigInt::one();
+            one
+        }
+        1 => elems[0].clone(),
+        len => {
+            let (left, right) = elems.split_at(len / 2);
+            mult_table.mul(&product(left, mult_table), &product(right, mult_table))
+        }
+    }
+}
+
+/// Finds a square root of delta in Z\[theta\], where theta is a root of the monic polynomial min_poly.
+///
+/// delta and the result are represented in the power basis 1, theta, ..., theta^{d-1},
+/// and mult_table must be that of Z\[theta\] with this basis.
+/// An inert prime p is chosen, so that Z\[theta\] / (p) is the finite field with p^d elements.
+/// After an inverse square root r of delta is found there, the root of X^2 delta - 1 is lifted p-adically
+/// with Newton's iteration r <- r (3 - delta r^2) / 2, and delta r is tested as the square root
+/// each time the precision doubles.
+/// `poly_mod::lift_factorization` does not apply here: it lifts factorizations of polynomials over Z,
+/// while X^2 delta - 1 has its coefficients in Z\[theta\].
+/// Returns None if delta is not a square, or if no inert prime is found (e.g. the Galois group of min_poly
+/// has no d-cycle).
+pub fn algebraic_sqrt(
+    delta: &[BigInt],
+    min_poly: &Polynomial<BigInt>,
+    mult_table: &MultTable,
+) -> Option<Vec<BigInt>> {
+    let d = min_poly.deg();
+    let p = find_inert_prime(delta, min_poly)?;
+    let q = p.pow(d as u32);
+    let delta_p = reduce(delta, &p);
+    if pow_mod(&delta_p, &((&q - 1u32) / 2u32), &p, mult_table) != one(d) {
+        return None;
+    }
+    let s = sqrt_finite_field(&delta_p, &q, &p, mult_table);
+    let mut r = pow_mod(&s, &(&q - 2u32), &p, mult_table);
+
+    let max_bits = delta.iter().map(|x| x.bits()).max().unwrap_or(0);
+    let height = min_poly.dat.iter().map(|x| x.bits()).max().unwrap_or(0);
+    let cap = max_bits + 2 * d as u64 * height + 64;
+    let mut pk = p;
+    while pk.bits() <= cap {
+        pk = &pk * &pk;
+        // r <- r (3 - delta r^2) / 2
+        let dr2 = mul_mod(delta, &mul_mod(&r, &r, &pk, mult_table), &pk, mult_table);
+        let mut t: Vec<BigInt> = dr2.into_iter().map(|x| -x).collect();
+        t[0] += 3;
+        let half = (&pk + 1u32) / 2u32;
+        r = mul_mod(&r, &t, &pk, mult_table)
+            .into_iter()
+            .map(|x| x * &half % &pk)
+            .collect();
+        let x: Vec<BigInt> = mul_mod(delta, &r, &pk, mult_table)
+            .into_iter()
+            .map(|x| if &x * 2 > pk { x - &pk } else { x })
+            .collect();
+        if mult_table.mul(&x, &x) == delta {
+            return Some(x);
+        }
+    }
+    None
+}
+
+/// Finds an odd prime p such that min_poly is irreducible modulo p and delta is not divisible by p.
+fn find_inert_prime(delta: &[BigInt], min_poly: &Polynomial<BigInt>) -> Option<BigInt> {
+    let d = min_poly.deg();
+    for p in primes(INERT_PRIME_BOUND).into_iter().skip(1) {
+        let pb = BigInt::from(p);
+        if delta.iter().all(|x| x.is_multiple_of(&pb)) {
+            continue;
+        }
+        let factors = factorize_mod_p::<BigInt>(min_poly, &pb, p);
+        if factors.len() == 1 && factors[0].0.deg() == d && factors[0].1 == 1 {
+            return Some(pb);
+        }
+    }
+    None
+}
+
+/// Finds a square root of a in the finite field Z\[theta\] / (p) with q elements, with the Tonelli-Shanks algorithm.
+/// a must be a nonzero square.
+fn sqrt_finite_field(a: &[BigInt], q: &BigInt, p: &BigInt, mult_table: &MultTable) -> Vec<BigInt> {
+    let d = a.len();
+    let mut t: BigInt = q - 1u32;
+    let mut s = 0;
+    while t.is_even() {
+        t >>= 1;
+        s += 1;
+    }
+    // Finds a non-square z by enumerating the elements, whose coefficients are the base-p digits of i.
+    let half = (q - 1u32) / 2u32;
+    let z = (1u64..)
+        .map(|i| {
+            let mut rest = BigInt::from(i);
+            (0..d)
+                .map(|_| {
+                    let (quo, rem) = rest.div_rem(p);
+                    rest = quo;
+                    rem
+                })
+                .collect::<Vec<_>>()
+        })
+        .find(|z| pow_mod(z, &half, p, mult_table) != one(d))
+        .unwrap();
+    let mut c = pow_mod(&z, &t, p, mult_table);
+    let mut x = pow_mod(a, &((&t + 1u32) / 2u32), p, mult_table);
+    let mut b = pow_mod(a, &t, p, mult_table);
+    let mut m = s;
+    while b != one(d) {
+        let mut i = 0;
+        let mut b2 = b.clone();
+        while b2 != one(d) {
+            b2 = mul_mod(&b2, &b2, p, mult_table);
+            i += 1;
+        }
+        let mut g = c.clone();
+        for _ in 0..m - i - 1 {
+            g = mul_mod(&g, &g, p, mult_table);
+        }
+        x = mul_mod(&x, &g, p, mult_table);
+        c = mul_mod(&g, &g, p, mult_table);
+        b = mul_mod(&b, &c, p, mult_table);
+        m = i;
+    }
+    x
+}
+
+fn one(d: usize) -> Vec<BigInt> {
+    let mut one = vec![BigInt::zero(); d];
+    one[0] = BigInt::one();
+    one
+}
+
+fn reduce(a: &[BigInt], modulus: &BigInt) -> Vec<BigInt> {
+    a.iter().map(|x| x.mod_floor(modulus)).collect()
+}
+
+fn mul_mod(a: &[BigInt], b: &[BigInt], modulus: &BigInt, mult_table: &MultTable) -> Vec<BigInt> {
+    reduce(&mult_table.mul(a, b), modulus)
+}
+
+fn pow_mod(a: &[BigInt], e: &BigInt, modulus: &BigInt, mult_table: &MultTable) -> Vec<BigInt> {
+    let mut result = one(a.len());
+    let mut cur = reduce(a, modulus);
+    let mut e = e.clone();
+    while e.is_positive() {
+        if e.is_odd() {
+            result = mul_mod(&result, &cur, modulus, mult_table);
+        }
+        cur = mul_mod(&cur, &cur, modulus, mult_table);
+        e >>= 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nfs::polyselect::{select_polynomials, PolySelectConfig};
+    use crate::nfs::sieve::{collect_relations, roots_mod_p, SieveConfig};
+    use crate::qs::find_dependencies;
+    use num::ToPrimitive;
+    use std::collections::HashMap;
+
+    #[test]
+    fn algebraic_sqrt_works() {
+        // theta^3 - theta - 1
+        let f = Polynomial::from_raw(vec![(-1).into(), (-1).into(), 0.into(), 1.into()]);
+        let theta = Algebraic::new(f.clone());
+        let mult_table = Order::singly_gen(&theta).get_mult_table(&theta);
+        let beta: Vec<BigInt> = vec![123456789.into(), (-987654321).into(), 55555.into()];
+        let delta = mult_table.mul(&beta, &beta);
+        let sqrt = algebraic_sqrt(&delta, &f, &mult_table).unwrap();
+        let neg: Vec<BigInt> = beta.iter().map(|x| -x).collect();
+        assert!(sqrt == beta || sqrt == neg);
+        // 2 is not a square in a cubic field.
+        let two = vec![2.into(), 0.into(), 0.into()];
+        assert_eq!(algebraic_sqrt(&two, &f, &mult_table), None);
+    }
+
+    /// Builds the exponent matrix modulo 2: the sign of a - bm, rational primes, degree-1 prime ideals
+    /// and quadratic characters.
+    fn build_matrix(
+        relations: &[Relation],
+        f: &Polynomial<BigInt>,
+        m: &BigInt,
+    ) -> (Vec<Vec<usize>>, usize) {
+        let mut columns = HashMap::new();
+        let mut rows = vec![];
+        let characters: Vec<(u64, u64)> = (1_000_000u32..1_001_000)
+            .filter(|&s| crate::prime::is_prime(&s.into()))
+            .flat_map(|s| {
+                roots_mod_p(f, s)
+                    .into_iter()
+                    .filter(move |&t| t != s)
+                    .map(move |t| (s as u64, t as u64))
+            })
+            .take(32)
+            .collect();
+        for rel in relations {
+            let mut row = vec![];
+            let (a, b) = (rel.a as i128, rel.b as i128);
+            if BigInt::from(rel.a) - m * rel.b < BigInt::zero() {
+                row.push(0);
+            }
+            for &p in &rel.rational {
+                let len = columns.len() + 1;
+                row.push(*columns.entry((0, p, 0)).or_insert(len));
+            }
+            for &p in &rel.algebraic {
+                let pi = p as i128;
+                let r = if b % pi == 0 {
+                    p
+                } else {
+                    let binv = BigInt::from(b).modpow(&BigInt::from(p - 2), &BigInt::from(p));
+                    (BigInt::from(a) * binv)
+                        .mod_floor(&BigInt::from(p))
+                        .to_u64()
+                        .unwrap()
+                };
+                let len = columns.len() + 1;
+                row.push(*columns.entry((1, p, r)).or_insert(len));
+            }
+            rows.push(row);
+        }
+        let base = columns.len() + 1;
+        for (rel, row) in relations.iter().zip(rows.iter_mut()) {
+            for (i, &(s, t)) in characters.iter().enumerate() {
+                let x = (rel.a as i128 - rel.b as i128 * t as i128).rem_euclid(s as i128);
+                let x = BigInt::from(x);
+                if x.modpow(&BigInt::from((s - 1) / 2), &BigInt::from(s)) != BigInt::one() {
+                    row.push(base + i);
+                }
+            }
+        }
+        (rows, base + characters.len())
+    }
+
+    #[test]
+    fn find_factor_works() {
+        let n = BigInt::from(1_000_000_007u64) * BigInt::from(998_244_353u64);
+        let mut poly_conf = PolySelectConfig::for_digits(18);
+        poly_conf.max_leading = 5;
+        let pair = &select_polynomials(&n, &poly_conf)[0];
+        let conf = SieveConfig {
+            log_i: 9,
+            j_max: 256,
+            rational_bound: 2000,
+            algebraic_bound: 2000,
+            rational_lpb: 2000,
+            algebraic_lpb: 2000,
+            slack: 4.0,
+            skew: pair.skew,
+        };
+        let relations = collect_relations(&pair.f, &pair.m, 2000..20000, 800, &conf, false);
+        let (rows, ncols) = build_matrix(&relations, &pair.f, &pair.m);
+        let deps = find_dependencies(&rows, ncols);
+        let mut found = false;
+        for dep in deps.iter().take(10) {
+            let rels: Vec<&Relation> = dep.iter().map(|&i| &relations[i]).collect();
+            if let Some(g) = find_factor(&n, &pair.f, &pair.m, &rels) {
+                assert!((&n % &g).is_zero());
+                found = true;
+                break;
+            }
+        }
+        assert!(found);
+    }
+}
@@ -0,0 +1,288 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use num::{BigInt, Integer, One, Signed, Zero};
+
+use crate::arith::divisors;
+use crate::continued_fraction::{convergents, expand_quadratic};
+use crate::ecm::factorize;
+use crate::modular_root::sqrt_mod_factored;
+
+/// Returns the fundamental solution (x, y) of x^2 - d y^2 = 1, the least one with x, y > 0,
+/// where d > 0 is not a square.
+pub fn pell(d: &BigInt) -> (BigInt, BigInt) {
+    let (x, y, norm) = fundamental_unit(d);
+    if norm == 1 {
+        (x, y)
+    } else {
+        // (x + y sqrt(d))^2
+        (&x * &x + d * &y * &y, 2 * x * y)
+    }
+}
+
+/// Returns the fundamental solution (x, y) of x^2 - d y^2 = -1, or None if it has no solutions,
+/// where d > 0 is not a square.
+pub fn negative_pell(d: &BigInt) -> Option<(BigInt, BigInt)> {
+    let (x, y, norm) = fundamental_unit(d);
+    if norm == -1 {
+        Some((x, y))
+    } else {
+        None
+    }
+}
+
+/// Returns (x, y, x^2 - d y^2), where x + y sqrt(d) is the least unit > 1 of Z[sqrt(d)].
+///
+/// If the period of sqrt(d) = [a_0; a_1, ..., a_l] has length l, it is p_{l-1} + q_{l-1} sqrt(d) of norm (-1)^l.
+fn fundamental_unit(d: &BigInt) -> (BigInt, BigInt, i32) {
+    let expansion = expand_quadratic(&BigInt::zero(), &BigInt::one(), d, &BigInt::one());
+    let l = expansion.period.len();
+    let c = convergents(expansion.terms()).nth(l - 1).unwrap();
+    let norm = if l % 2 == 0 { 1 } else { -1 };
+    (c.numer().clone(), c.denom().clone(), norm)
+}
+
+/// Returns the fundamental solutions of x^2 - d y^2 = n != 0 in the increasing order of y,
+/// where d > 0 is not a square.
+///
+/// Every solution is ±(x + y sqrt(d)) (t + u sqrt(d))^k for exactly one (x, y) in the result and k in Z,
+/// where (t, u) = `pell(d)`. The returned (x, y) is the least one with x, y >= 0 in its class.
+///
+/// This uses the LMM (Lagrange-Matthews-Mollin) method:
+/// for each f > 0 with f^2 | n and m = n / f^2, the primitive solutions of X^2 - d Y^2 = m are grouped by z = X / Y mod |m|,
+/// which satisfies z^2 = d (mod |m|). A class exists iff for some convergent A / B of (z + sqrt(d)) / |m|,
+/// G = |m| A - z B satisfies G^2 - d B^2 = m, or = -m and x^2 - d y^2 = -1 is solvable.
+/// Such a convergent appears in the pre-period or the first two periods.
+pub fn generalized_pell(d: &BigInt, n: &BigInt) -> Vec<(BigInt, BigInt)> {
+    assert!(!n.is_zero());
+    let unit = pell(d);
+    let negative_unit = negative_pell(d);
+    let factors = factorize(&n.abs());
+    let square_part: Vec<(BigInt, u64)> = factors
+        .iter()
+        .filter(|&&(_, e)| e >= 2)
+        .map(|(p, e)| (p.clone(), e / 2))
+        .collect();
+    let mut result = vec![];
+    for f in divisors(&square_part) {
+        let m = n / (&f * &f);
+        let m_abs = m.abs();
+        let mut m_factors = vec![];
+        for (p, _) in &factors {
+            let mut e = 0;
+            let mut rest = m_abs.clone();
+            while rest.is_multiple_of(p) {
+                rest /= p;
+                e += 1;
+            }
+            if e > 0 {
+                m_factors.push((p.clone(), e));
+            }
+        }
+        for z in sqrt_mod_factored(d, &m_factors) {
+            // -|m| / 2 < z <= |m| / 2
+            let z = if &z * 2 > m_abs { z - &m_abs } else { z };
+            let solution = match primitive_solution(d, &m, &z) {
+                (Some(solution), _) => solution,
+                (None, Some(solution)) => match &negative_unit {
+                    Some(negative_unit) => multiply(&solution, negative_unit, d),
+                    None => continue,
+                },
+                (None, None) => continue,
+            };
+            let (x, y) = least_nonnegative(solution, &unit, d);
+            result.push((x * &f, y * &f));
+        }
+    }
+    result.sort_by(|a, b| (&a.1, &a.0).cmp(&(&b.1, &b.0)));
+    result
+}
+
+/// Returns an iterator of the solutions (x, y) of x^2 - d y^2 = n != 0 with x, y >= 0 in the increasing order of y.
+///
+/// The iterator is infinite unless it has no solutions.
+pub fn pell_solutions(d: &BigInt, n: &BigInt) -> PellSolutions {
+    let heap = generalized_pell(d, n)
+        .into_iter()
+        .map(|(x, y)| Reverse((y, x)))
+        .collect();
+    PellSolutions {
+        d: d.clone(),
+        unit: pell(d),
+        heap,
+    }
+}
+
+/// An iterator of the solutions of x^2 - d y^2 = n with x, y >= 0.
+///
+/// The solutions with x, y >= 0 in a class are (x_0 + y_0 sqrt(d)) (t + u sqrt(d))^k for k >= 0,
+/// so the next solution of each class is kept in a heap.
+pub struct PellSolutions {
+    d: BigInt,
+    /// The fundamental solution of x^2 - d y^2 = 1
+    unit: (BigInt, BigInt),
+    /// (y, x)
+    heap: BinaryHeap<Reverse<(BigInt, BigInt)>>,
+}
+
+impl Iterator for PellSolutions {
+    type Item = (BigInt, BigInt);
+
+    fn next(&mut self) -> Option<(BigInt, BigInt)> {
+        let Reverse((y, x)) = self.heap.pop()?;
+        let (next_x, next_y) = multiply(&(x.clone(), y.clone()), &self.unit, &self.d);
+        self.heap.push(Reverse((next_y, next_x)));
+        Some((x, y))
+    }
+}
+
+/// Searches the convergents A / B of (z + sqrt(d)) / |m| for G = |m| A - z B with G^2 - d B^2 = ±m.
+/// Returns the solutions (G, B) for m and -m found first.
+#[allow(clippy::type_complexity)]
+fn primitive_solution(
+    d: &BigInt,
+    m: &BigInt,
+    z: &BigInt,
+) -> (Option<(BigInt, BigInt)>, Option<(BigInt, BigInt)>) {
+    let m_abs = m.abs();
+    let expansion = expand_quadratic(z, &BigInt::one(), d, &m_abs);
+    let len = expansion.pre_period.len() + 2 * expansion.period.len();
+    let mut negative = None;
+    for c in convergents(expansion.terms()).take(len) {
+        let (a, b) = (c.numer(), c.denom());
+        let g = &m_abs * a - z * b;
+        let norm = &g * &g - d * b * b;
+        if &norm == m {
+            return (Some((g, b.clone())), None);
+        }
+        if norm == -m && negative.is_none() {
+            negative = Some((g, b.clone()));
+        }
+    }
+    (None, negative)
+}
+
+/// Returns the least solution with x, y >= 0 among ±(x + y sqrt(d)) (t + u sqrt(d))^k for k in Z.
+///
+/// They are the elements alpha > 0 of the class with alpha >= |conj(alpha)|,
+/// which make up an interval of k since alpha (t + u sqrt(d))^k increases and its conjugate decreases in absolute value.
+fn least_nonnegative(
+    (x, y): (BigInt, BigInt),
+    unit: &(BigInt, BigInt),
+    d: &BigInt,
+) -> (BigInt, BigInt) {
+    let is_nonnegative = |(x, y): &(BigInt, BigInt)| !x.is_negative() && !y.is_negative();
+    // Make x + y sqrt(d) > 0.
+    let positive = match (x.is_negative(), y.is_negative()) {
+        (false, false) => true,
+        (true, true) => false,
+        (false, true) => &x * &x > d * &y * &y,
+        (true, false) => &x * &x < d * &y * &y,
+    };
+    let mut current = if positive { (x, y) } else { (-x, -y) };
+    while !is_nonnegative(&current) {
+        current = multiply(&current, unit, d);
+    }
+    let unit_inv = (unit.0.clone(), -&unit.1);
+    loop {
+        let prev = multiply(&current, &unit_inv, d);
+        if !is_nonnegative(&prev) {
+            return current;
+        }
+        current = prev;
+    }
+}
+
+/// (x + y sqrt(d)) (z + w sqrt(d))
+fn multiply(a: &(BigInt, BigInt), b: &(BigInt, BigInt), d: &BigInt) -> (BigInt, BigInt) {
+    (&a.0 * &b.0 + d * &a.1 * &b.1, &a.0 * &b.1 + &a.1 * &b.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(x: i64, y: i64) -> (BigInt, BigInt) {
+        (x.into(), y.into())
+    }
+
+    #[test]
+    fn pell_works() {
+        assert_eq!(pell(&2.into()), pair(3, 2));
+        assert_eq!(negative_pell(&2.into()), Some(pair(1, 1)));
+        assert_eq!(pell(&3.into()), pair(2, 1));
+        assert_eq!(negative_pell(&3.into()), None);
+        assert_eq!(pell(&61.into()), pair(1766319049, 226153980));
+        assert_eq!(negative_pell(&61.into()), Some(pair(29718, 3805)));
+        let (x, y) = pell(&991.into());
+        assert_eq!(
+            x,
+            "379516400906811930638014896080".parse::<BigInt>().unwrap()
+        );
+        assert_eq!(
+            y,
+            "12055735790331359447442538767".parse::<BigInt>().unwrap()
+        );
+        // x^2 - 1000099 y^2 = 1 for a large fundamental solution
+        let d = BigInt::from(1000099);
+        let (x, y) = pell(&d);
+        assert_eq!(&x * &x - &d * &y * &y, BigInt::one());
+        assert!(y.bits() > 1000);
+    }
+
+    #[test]
+    fn generalized_pell_works() {
+        // x^2 - 2 y^2 = 7: (3, 1) and (5, 3)
+        assert_eq!(
+            generalized_pell(&2.into(), &7.into()),
+            vec![pair(3, 1), pair(5, 3)]
+        );
+        // x^2 - 3 y^2 = -1 has no solutions.
+        assert_eq!(generalized_pell(&3.into(), &(-1).into()), vec![]);
+        // x^2 - 5 y^2 = 44: (7, 1), (8, 2), (13, 5), (17, 7), (32, 14), ...
+        let solutions = generalized_pell(&5.into(), &44.into());
+        for (x, y) in &solutions {
+            assert_eq!(x * x - 5 * y * y, BigInt::from(44));
+        }
+        assert_eq!(solutions.len(), 6);
+        // x^2 - 13 y^2 = 27 has the primitive solutions (40, 11), ... and 3 (4, 1), ... from 3^2 | 27.
+        let solutions: Vec<(BigInt, BigInt)> =
+            pell_solutions(&13.into(), &27.into()).take(4).collect();
+        assert_eq!(
+            solutions,
+            vec![pair(12, 3), pair(40, 11), pair(220, 61), pair(768, 213)]
+        );
+    }
+
+    #[test]
+    fn pell_solutions_agree_with_brute_force() {
+        const BOUND: i64 = 300;
+        for d in 2..30i64 {
+            let s = (d as f64).sqrt() as i64;
+            if s * s == d {
+                continue;
+            }
+            for n in -60..=60i64 {
+                if n == 0 {
+                    continue;
+                }
+                let mut expected = vec![];
+                for y in 0..=BOUND {
+                    let xx = n + d * y * y;
+                    if xx < 0 {
+                        continue;
+                    }
+                    let x = (xx as f64).sqrt() as i64;
+                    let x = (x - 1..=x + 1).find(|&x| x >= 0 && x * x == xx);
+                    if let Some(x) = x {
+                        expected.push(pair(x, y));
+                    }
+                }
+                let actual: Vec<(BigInt, BigInt)> = pell_solutions(&d.into(), &n.into())
+                    .take_while(|(_, y)| y <= &BigInt::from(BOUND))
+                    .collect();
+                assert_eq!(actual, expected, "d = {}, n = {}", d, n);
+            }
+        }
+    }
+}
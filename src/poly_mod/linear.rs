@@ -125,7 +125,7 @@ mod tests {
         let poly: Polynomial<i32> = Polynomial::from_raw(vec![2, 0, 1]);
         let p = 5;
         let factors = find_linear_factors::<i32>(&poly, p);
-        assert_eq!(factors, vec![]);
+        assert_eq!(factors, Vec::<i32>::new());
     }
 
     #[test]
@@ -1,54 +1,234 @@
 use num::bigint::RandBigInt;
-use num::{BigInt, One};
+use num::{BigInt, Integer, One, Signed, ToPrimitive, Zero};
+use number_theory_elementary::{
+    deterministic_mr_bases as deterministic_mr_bases_u128, is_prime_u128, primes,
+};
+use rand::Rng;
 
-#[allow(clippy::many_single_char_names)]
+use crate::kronecker::jacobi_symbol;
+
+/// Small primes tried by trial division before the probable prime tests.
+const TRIAL_DIVISION_PRIMES: [u32; 15] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47];
+
+/// Primes below this bound cross off candidates in a window before `is_prime` is called on them.
+const WINDOW_SIEVE_BOUND: usize = 1 << 12;
+
+/// Tests if n is a prime.
+///
+/// n < 2^128 is tested on machine words with `is_prime_u128`, which is exact below
+/// `number_theory_elementary::DETERMINISTIC_MR_BOUND`.
+/// Larger n are tested with the Baillie-PSW test, for which no counterexample is known.
+/// The result never depends on randomness.
 pub fn is_prime(n: &BigInt) -> bool {
     if n <= &BigInt::one() {
         return false;
     }
-    let two = BigInt::from(2u8);
-    if n == &two {
-        return true;
+    if let Some(n) = n.to_u128() {
+        return is_prime_u128(n);
     }
-    // if n % 2 == 0
-    if !n.bit(0) {
-        return false;
+    for p in TRIAL_DIVISION_PRIMES {
+        if (n % p).is_zero() {
+            return false;
+        }
     }
+    is_bpsw_probable_prime(n)
+}
 
-    let mut c = 0u64;
-    let mut d: BigInt = n - 1;
+/// Returns the first primes that make the Miller-Rabin test deterministic for n,
+/// or None if n >= `number_theory_elementary::DETERMINISTIC_MR_BOUND` (about 3.3 * 10^24).
+pub fn deterministic_mr_bases(n: &BigInt) -> Option<&'static [u64]> {
+    n.to_u128().and_then(deterministic_mr_bases_u128)
+}
 
-    while !d.bit(0) {
-        d >>= 1;
-        c += 1;
+/// Returns the smallest prime > n.
+pub fn next_prime(n: &BigInt) -> BigInt {
+    let mut start = if n < &BigInt::from(2) {
+        BigInt::from(2)
+    } else {
+        n + 1
+    };
+    let len = window_len(&start);
+    let small_primes = primes(WINDOW_SIEVE_BOUND);
+    loop {
+        let window = sieve_window(&start, len, &small_primes);
+        if let Some(i) = (0..len).find(|&i| window[i] && is_prime(&(&start + i))) {
+            return start + i;
+        }
+        start += len;
     }
+}
 
-    let mut rng = rand::thread_rng();
+/// Returns the largest prime < n, or None if n <= 2.
+pub fn prev_prime(n: &BigInt) -> Option<BigInt> {
+    if n <= &BigInt::from(2) {
+        return None;
+    }
+    let len = window_len(n);
+    let small_primes = primes(WINDOW_SIEVE_BOUND);
+    let mut end = n.clone();
+    loop {
+        // end > 2 holds here, so the window contains 2 if it starts at 0.
+        let start = std::cmp::max(&end - len, BigInt::zero());
+        let window = sieve_window(&start, (&end - &start).to_usize().unwrap(), &small_primes);
+        if let Some(i) = (0..window.len())
+            .rev()
+            .find(|&i| window[i] && is_prime(&(&start + i)))
+        {
+            return Some(start + i);
+        }
+        end = start;
+    }
+}
 
-    let k = 20;
-    for _ in 0..k {
-        let r = rng.gen_bigint_range(&1.into(), n);
-        let mut tmp = r.modpow(&d, n);
-        if tmp == BigInt::one() {
-            continue;
+/// Returns the first pair of consecutive primes (p, q) with n < p and q - p >= gap.
+pub fn find_prime_gap(n: &BigInt, gap: u64) -> (BigInt, BigInt) {
+    let mut p = next_prime(n);
+    loop {
+        let q = next_prime(&p);
+        if &q - &p >= BigInt::from(gap) {
+            return (p, q);
         }
-        let mut aborted = false;
-        for _ in 0..c {
-            if tmp == n - 1 {
-                aborted = true;
-                break;
-            }
-            tmp = tmp.modpow(&2.into(), n);
-            if tmp == BigInt::one() {
-                // witness found
-                return false;
-            }
+        p = q;
+    }
+}
+
+/// Returns a uniformly random prime with exactly `bits` bits, that is, in [2^{bits - 1}, 2^bits).
+/// bits must be >= 2.
+pub fn random_prime(bits: u64, rng: &mut impl Rng) -> BigInt {
+    assert!(bits >= 2, "no primes with {} bits", bits);
+    let top = BigInt::one() << (bits - 1);
+    loop {
+        let candidate = &top | rng.gen_bigint_range(&BigInt::zero(), &top);
+        if is_prime(&candidate) {
+            return candidate;
         }
-        if !aborted && tmp != BigInt::one() {
-            return false;
+    }
+}
+
+/// The length of windows searched for primes near n. Primes near n are about ln n apart,
+/// so a window usually contains a few of them.
+fn window_len(n: &BigInt) -> usize {
+    (n.bits() as usize * 2).max(64)
+}
+
+/// Returns is_candidate, where is_candidate[i] is false if start + i is < 2 or a multiple of
+/// one of small_primes other than itself. start must be >= 0.
+///
+/// The callers call `is_prime` only on the candidates they need, and stop at the first prime.
+fn sieve_window(start: &BigInt, len: usize, small_primes: &[usize]) -> Vec<bool> {
+    let mut is_candidate = vec![true; len];
+    if let Some(start) = start.to_usize() {
+        // 0 and 1 are not primes.
+        for b in is_candidate.iter_mut().take(2usize.saturating_sub(start)) {
+            *b = false;
+        }
+    }
+    for &p in small_primes {
+        // The first multiple of p >= max(start, p^2). Smaller multiples of p are either p itself
+        // or have a smaller prime factor.
+        let first = match start.to_usize() {
+            Some(s) if s <= p * p => p * p - s,
+            _ => (p - (start % p).to_usize().unwrap()) % p,
+        };
+        for i in (first..len).step_by(p) {
+            is_candidate[i] = false;
+        }
+    }
+    is_candidate
+}
+
+/// The Baillie-PSW test: a strong probable prime test to base 2 followed by
+/// a strong Lucas test with Selfridge's parameters. n must be odd and > 1.
+///
+/// Baillie, Robert, and Samuel S. Wagstaff. "Lucas pseudoprimes." Mathematics of Computation 35.152 (1980): 1391-1417.
+pub fn is_bpsw_probable_prime(n: &BigInt) -> bool {
+    is_strong_probable_prime(n, &2.into()) && is_strong_lucas_probable_prime(n)
+}
+
+/// Tests if n is a strong probable prime to base a, that is,
+/// a^d = 1 or a^{d 2^r} = -1 (mod n) for some 0 <= r < s, where n - 1 = d 2^s with d odd.
+/// n must be odd and > 1.
+pub fn is_strong_probable_prime(n: &BigInt, a: &BigInt) -> bool {
+    let n_minus_1: BigInt = n - 1;
+    let s = n_minus_1.trailing_zeros().unwrap();
+    let d = &n_minus_1 >> s;
+    let a = a.mod_floor(n);
+    if a.is_zero() {
+        return true;
+    }
+    let mut x = a.modpow(&d, n);
+    if x.is_one() || x == n_minus_1 {
+        return true;
+    }
+    for _ in 1..s {
+        x = &x * &x % n;
+        if x == n_minus_1 {
+            return true;
+        }
+    }
+    false
+}
+
+/// The strong Lucas probable prime test with Selfridge's parameters:
+/// D is the first of 5, -7, 9, -11, ... with (D/n) = -1, P = 1 and Q = (1 - D) / 4.
+/// Then n is a strong Lucas probable prime if U_d = 0 or V_{d 2^r} = 0 (mod n) for some 0 <= r < s,
+/// where n + 1 = d 2^s with d odd.
+/// n must be odd and > 1.
+pub fn is_strong_lucas_probable_prime(n: &BigInt) -> bool {
+    // (D/n) = -1 never happens for a square n.
+    let root = n.sqrt();
+    if &(&root * &root) == n {
+        return false;
+    }
+    let mut d = BigInt::from(5);
+    loop {
+        match jacobi_symbol(&d, n) {
+            -1 => break,
+            0 if &d.abs() != n => return false,
+            _ => {}
+        }
+        d = if d.is_positive() { -d - 2 } else { -d + 2 };
+    }
+    let q: BigInt = (1 - &d) / 4;
+    let half = |x: BigInt| {
+        if x.is_odd() {
+            (x + n) >> 1
+        } else {
+            x >> 1
+        }
+    };
+
+    let n_plus_1: BigInt = n + 1;
+    let s = n_plus_1.trailing_zeros().unwrap();
+    let k = &n_plus_1 >> s;
+    // (U_j, V_j, Q^j) for j = 1, with P = 1
+    let mut u = BigInt::one();
+    let mut v = BigInt::one();
+    let mut qk = q.mod_floor(n);
+    for i in (0..k.bits() - 1).rev() {
+        // j -> 2j
+        u = &u * &v % n;
+        v = (&v * &v - BigInt::from(2) * &qk).mod_floor(n);
+        qk = &qk * &qk % n;
+        if k.bit(i) {
+            // j -> j + 1
+            let new_u = half((&u + &v) % n);
+            v = half((&d * &u + &v).mod_floor(n));
+            u = new_u;
+            qk = (&qk * &q).mod_floor(n);
+        }
+    }
+    if u.is_zero() || v.is_zero() {
+        return true;
+    }
+    for _ in 1..s {
+        v = (&v * &v - BigInt::from(2) * &qk).mod_floor(n);
+        qk = &qk * &qk % n;
+        if v.is_zero() {
+            return true;
         }
     }
-    true
+    false
 }
 
 #[cfg(test)]
@@ -75,4 +255,118 @@ mod tests {
         assert!(is_prime(&large2));
         assert!(!is_prime(&(large1 * large2)));
     }
+
+    #[test]
+    fn is_prime_works_2() {
+        // 2^521 - 1 is a Mersenne prime, and 2^523 - 1 is not.
+        let m521 = (BigInt::one() << 521u32) - 1;
+        let m523 = (BigInt::one() << 523u32) - 1;
+        assert!(is_prime(&m521));
+        assert!(!is_prime(&m523));
+        let large = BigInt::from(1_000_000_000_000_000_000_000_000_103u128);
+        assert!(!is_prime(&(&large * &large)));
+        assert!(!is_prime(&(&m521 * &large)));
+    }
+
+    #[test]
+    fn is_bpsw_probable_prime_works() {
+        // Strong pseudoprimes to base 2
+        for n in [
+            2047u64,
+            3277,
+            4033,
+            3_215_031_751,
+            3_825_123_056_546_413_051,
+        ] {
+            let n = BigInt::from(n);
+            assert!(is_strong_probable_prime(&n, &2.into()));
+            assert!(!is_bpsw_probable_prime(&n), "n = {}", n);
+        }
+        // Strong Lucas pseudoprimes
+        for n in [5459u64, 5777, 10877, 16109, 18971] {
+            let n = BigInt::from(n);
+            assert!(is_strong_lucas_probable_prime(&n), "n = {}", n);
+            assert!(!is_bpsw_probable_prime(&n), "n = {}", n);
+        }
+        for p in [3u64, 5, 7, 1_000_000_007, 18_446_744_073_709_551_557] {
+            assert!(is_bpsw_probable_prime(&p.into()), "p = {}", p);
+        }
+    }
+
+    #[test]
+    fn next_prime_works() {
+        let expected = [2, 2, 3, 5, 5, 7, 7, 11, 11, 11, 11, 13];
+        for (n, &p) in expected.iter().enumerate() {
+            assert_eq!(next_prime(&BigInt::from(n)), BigInt::from(p), "n = {}", n);
+        }
+        assert_eq!(next_prime(&BigInt::from(-5)), BigInt::from(2));
+        // 2^64 + 13 is the smallest prime > 2^64.
+        let two64 = BigInt::one() << 64u32;
+        assert_eq!(next_prime(&two64), &two64 + 13);
+        // 2^127 - 1 is a Mersenne prime.
+        let m127 = (BigInt::one() << 127u32) - 1;
+        assert_eq!(next_prime(&(&m127 - 1)), m127);
+    }
+
+    #[test]
+    fn prev_prime_works() {
+        for n in -1..=2 {
+            assert_eq!(prev_prime(&BigInt::from(n)), None);
+        }
+        let expected = [2, 3, 3, 5, 5, 7, 7, 7, 7, 11];
+        for (i, &p) in expected.iter().enumerate() {
+            let n = BigInt::from(i + 3);
+            assert_eq!(prev_prime(&n), Some(BigInt::from(p)), "n = {}", n);
+        }
+        // 2^64 - 59 is the largest prime < 2^64.
+        let two64 = BigInt::one() << 64u32;
+        assert_eq!(prev_prime(&two64), Some(&two64 - 59));
+        for n in [1_000_000_007u64, 998_244_353] {
+            let n = BigInt::from(n);
+            assert_eq!(next_prime(&prev_prime(&n).unwrap()), n);
+            assert_eq!(prev_prime(&next_prime(&n)).unwrap(), n);
+        }
+    }
+
+    #[test]
+    fn find_prime_gap_works() {
+        // 113 and 127 are the first consecutive primes whose difference is >= 14.
+        assert_eq!(
+            find_prime_gap(&BigInt::zero(), 14),
+            (BigInt::from(113), BigInt::from(127))
+        );
+        assert_eq!(
+            find_prime_gap(&BigInt::from(2), 1),
+            (BigInt::from(3), BigInt::from(5))
+        );
+        // The maximal gap of 72 after 31397.
+        assert_eq!(
+            find_prime_gap(&BigInt::zero(), 72),
+            (BigInt::from(31397), BigInt::from(31469))
+        );
+    }
+
+    #[test]
+    fn random_prime_works() {
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        for bits in [2, 3, 10, 64, 65, 200] {
+            let p = random_prime(bits, &mut rng);
+            assert_eq!(p.bits(), bits);
+            assert!(is_prime(&p));
+        }
+    }
+
+    #[test]
+    fn deterministic_mr_bases_works() {
+        let psi = BigInt::from(318_665_857_834_031_151_167_461u128);
+        let bases = deterministic_mr_bases(&psi).unwrap();
+        assert_eq!(bases.len(), 13);
+        // psi is a strong pseudoprime to the first 12 primes, but not to 41.
+        for &b in &bases[..12] {
+            assert!(is_strong_probable_prime(&psi, &b.into()));
+        }
+        assert!(!is_strong_probable_prime(&psi, &41.into()));
+        assert_eq!(deterministic_mr_bases(&(BigInt::one() << 100u32)), None);
+    }
 }
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+
+use num::{BigInt, Integer, One, Signed, Zero};
+
+use crate::dlog::{element_order, unit_group_order, Group, UnitGroup};
+use crate::ecm::factorize;
+use crate::inverse::{crt, inv};
+
+/// Computes the multiplicative order of a modulo n > 0, or returns None if gcd(a, n) > 1.
+///
+/// The order is found among the divisors of phi(n), whose factorization is computed with `ecm::factorize`.
+pub fn multiplicative_order(a: &BigInt, n: &BigInt) -> Option<BigInt> {
+    assert!(n.is_positive());
+    inv(a, n).ok()?;
+    let group = UnitGroup { n: n.clone() };
+    let order = element_order(&group, &a.mod_floor(n), &unit_group_order(n))
+        .into_iter()
+        .map(|(p, e)| num::pow(p, e as usize))
+        .product();
+    Some(order)
+}
+
+/// Returns the least primitive root modulo a prime p.
+pub fn primitive_root(p: &BigInt) -> BigInt {
+    primitive_root_mod(p).expect("p is not a prime")
+}
+
+/// Returns the least primitive root modulo n > 0, or None if (Z/nZ)^* is not cyclic,
+/// that is, n is not 1, 2, 4, p^k or 2 p^k for an odd prime p.
+pub fn primitive_root_mod(n: &BigInt) -> Option<BigInt> {
+    assert!(n.is_positive());
+    if n.is_one() {
+        return Some(BigInt::zero());
+    }
+    let factors = factorize(n);
+    let odd: Vec<&(BigInt, u64)> = factors.iter().filter(|(p, _)| p.is_odd()).collect();
+    let cyclic = matches!(
+        (n.trailing_zeros().unwrap(), odd.len()),
+        (0 | 1, 0 | 1) | (2, 0)
+    );
+    if !cyclic {
+        return None;
+    }
+    let group = UnitGroup { n: n.clone() };
+    let phi_factors = unit_group_order(n);
+    let phi: BigInt = phi_factors
+        .iter()
+        .map(|(p, e)| num::pow(p.clone(), *e as usize))
+        .product();
+    // g is a primitive root iff g^{phi / q} != 1 for all primes q | phi.
+    let mut g = BigInt::one();
+    loop {
+        if inv(&g, n).is_ok()
+            && phi_factors
+                .iter()
+                .all(|(q, _)| !group.pow(&g, &(&phi / q)).is_one())
+        {
+            return Some(g);
+        }
+        g += 1;
+    }
+}
+
+/// Returns the structure of (Z/nZ)^* for n > 0 as a list of (g_i, d_i) with d_1 | d_2 | ... | d_r and d_1 > 1,
+/// such that (Z/nZ)^* is the direct product of the cyclic groups generated by g_i of order d_i.
+///
+/// (Z/p^kZ)^* is cyclic for odd p, and (Z/2^kZ)^* is generated by -1 and 5 for k >= 3.
+/// Their generators are lifted to n by the CRT, split into components of prime power order,
+/// and combined into the invariant factors d_i.
+pub fn unit_group_structure(n: &BigInt) -> Vec<(BigInt, BigInt)> {
+    assert!(n.is_positive());
+    let group = UnitGroup { n: n.clone() };
+    // q -> [(generator, e)] with generators of order q^e
+    let mut primary: BTreeMap<BigInt, Vec<(BigInt, u64)>> = BTreeMap::new();
+    for (p, k) in factorize(n) {
+        let pk = num::pow(p.clone(), k as usize);
+        // (generator modulo p^k, factorization of its order)
+        let mut cyclic: Vec<(BigInt, Vec<(BigInt, u64)>)> = vec![];
+        if p == BigInt::from(2) {
+            if k >= 2 {
+                cyclic.push((&pk - 1, vec![(p.clone(), 1)]));
+            }
+            if k >= 3 {
+                cyclic.push((5.into(), vec![(p.clone(), k - 2)]));
+            }
+        } else {
+            // A primitive root g modulo p is one modulo p^k unless g^{p - 1} = 1 (mod p^2).
+            let mut g = primitive_root(&p);
+            let p2 = &p * &p;
+            if k >= 2 && g.modpow(&(&p - 1), &p2).is_one() {
+                g += &p;
+            }
+            let mut order = factorize(&(&p - 1));
+            if k >= 2 {
+                order.push((p.clone(), k - 1));
+            }
+            cyclic.push((g, order));
+        }
+        let rest = n / &pk;
+        for (g, order) in cyclic {
+            // g modulo p^k and 1 modulo n / p^k
+            let (g, _) = crt(&[g, BigInt::one()], &[pk.clone(), rest.clone()]).unwrap();
+            let m: BigInt = order
+                .iter()
+                .map(|(q, e)| num::pow(q.clone(), *e as usize))
+                .product();
+            for (q, e) in order {
+                let qe = num::pow(q.clone(), e as usize);
+                let g_q = group.pow(&g, &(&m / &qe));
+                primary.entry(q).or_default().push((g_q, e));
+            }
+        }
+    }
+    // The i-th largest components for each q make up the i-th largest invariant factor.
+    let mut result: Vec<(BigInt, BigInt)> = vec![];
+    for (q, mut components) in primary {
+        components.sort_by_key(|&(_, e)| std::cmp::Reverse(e));
+        for (i, (g_q, e)) in components.into_iter().enumerate() {
+            if i == result.len() {
+                result.push((group.identity(), BigInt::one()));
+            }
+            let (g, d) = &mut result[i];
+            *g = group.op(g, &g_q);
+            *d *= num::pow(q.clone(), e as usize);
+        }
+    }
+    result.reverse();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multiplicative_order_works() {
+        assert_eq!(multiplicative_order(&2.into(), &7.into()), Some(3.into()));
+        assert_eq!(multiplicative_order(&10.into(), &7.into()), Some(6.into()));
+        assert_eq!(multiplicative_order(&6.into(), &9.into()), None);
+        assert_eq!(multiplicative_order(&0.into(), &1.into()), Some(1.into()));
+        assert_eq!(
+            multiplicative_order(&2.into(), &10403.into()),
+            Some(5100.into())
+        );
+        // 2 has order 61 modulo 2^61 - 1, so -2 has order 2 * 61.
+        let m61 = (BigInt::one() << 61u32) - 1;
+        assert_eq!(multiplicative_order(&(-2).into(), &m61), Some(122.into()));
+    }
+
+    #[test]
+    fn primitive_root_works() {
+        assert_eq!(primitive_root(&2.into()), BigInt::from(1));
+        assert_eq!(primitive_root(&7.into()), BigInt::from(3));
+        assert_eq!(primitive_root(&1_000_000_007.into()), BigInt::from(5));
+        assert_eq!(primitive_root(&998_244_353.into()), BigInt::from(3));
+        for (n, g) in [(1, Some(0)), (4, Some(3)), (9, Some(2)), (50, Some(3))] {
+            assert_eq!(primitive_root_mod(&n.into()), g.map(BigInt::from));
+        }
+        for n in [8, 12, 15, 21, 100] {
+            assert_eq!(primitive_root_mod(&n.into()), None);
+        }
+        // 5 is the least primitive root modulo 40487, but not a primitive root modulo 40487^2.
+        let p = BigInt::from(40487);
+        let p2 = &p * &p;
+        assert_eq!(primitive_root(&p), BigInt::from(5));
+        assert_eq!(primitive_root_mod(&p2), Some(10.into()));
+        let structure = unit_group_structure(&p2);
+        assert_eq!(structure.len(), 1);
+        assert_eq!(
+            multiplicative_order(&structure[0].0, &p2),
+            Some(&p * (&p - 1))
+        );
+    }
+
+    #[test]
+    fn unit_group_structure_works() {
+        for n in 1..=300 {
+            let n = BigInt::from(n);
+            let structure = unit_group_structure(&n);
+            let phi = crate::arith::euler_phi(&factorize(&n));
+            let product: BigInt = structure.iter().map(|(_, d)| d).product();
+            assert_eq!(product, phi, "n = {}", n);
+            for (i, (g, d)) in structure.iter().enumerate() {
+                assert!(d > &BigInt::one());
+                assert_eq!(multiplicative_order(g, &n).as_ref(), Some(d), "n = {}", n);
+                if i > 0 {
+                    assert!(d.is_multiple_of(&structure[i - 1].1), "n = {}", n);
+                }
+            }
+            // The generators are independent iff their products generate phi(n) distinct elements.
+            let mut elements = std::collections::HashSet::new();
+            elements.insert(BigInt::one() % &n);
+            for (g, d) in &structure {
+                let mut next = std::collections::HashSet::new();
+                for x in &elements {
+                    let mut y = x.clone();
+                    for _ in num::range(BigInt::zero(), d.clone()) {
+                        next.insert(y.clone());
+                        y = y * g % &n;
+                    }
+                }
+                elements = next;
+            }
+            assert_eq!(BigInt::from(elements.len()), phi, "n = {}", n);
+        }
+        let structure = unit_group_structure(&720.into());
+        let orders: Vec<BigInt> = structure.into_iter().map(|(_, d)| d).collect();
+        assert_eq!(orders, vec![2.into(), 2.into(), 4.into(), 12.into()]);
+    }
+}
@@ -0,0 +1,83 @@
+/// Finds sets of rows whose sum is zero over GF(2).
+///
+/// Each row is given as the list of columns in which it has a 1; a column may appear more than once,
+/// in which case only the parity counts.
+/// This performs Gaussian elimination on bit-packed rows, keeping track of which original rows
+/// were added to each row. Rows that vanish give the dependencies.
+pub fn find_dependencies(rows: &[Vec<usize>], ncols: usize) -> Vec<Vec<usize>> {
+    let nrows = rows.len();
+    let width = (ncols + nrows).div_ceil(64);
+    // Bits [0, ncols) hold the matrix; bits [ncols, ncols + nrows) hold the history.
+    let mut mat = vec![vec![0u64; width]; nrows];
+    for (i, row) in rows.iter().enumerate() {
+        for &c in row {
+            debug_assert!(c < ncols);
+            mat[i][c / 64] ^= 1 << (c % 64);
+        }
+        let h = ncols + i;
+        mat[i][h / 64] |= 1 << (h % 64);
+    }
+    let mut is_pivot = vec![false; nrows];
+    for c in 0..ncols {
+        let (word, bit) = (c / 64, 1u64 << (c % 64));
+        let pivot = match (0..nrows).find(|&i| !is_pivot[i] && mat[i][word] & bit != 0) {
+            Some(pivot) => pivot,
+            None => continue,
+        };
+        is_pivot[pivot] = true;
+        let pivot_row = std::mem::take(&mut mat[pivot]);
+        for row in mat.iter_mut() {
+            if !row.is_empty() && row[word] & bit != 0 {
+                // Columns < c are already zero in pivot_row.
+                for (x, y) in row[word..].iter_mut().zip(&pivot_row[word..]) {
+                    *x ^= y;
+                }
+            }
+        }
+        mat[pivot] = pivot_row;
+    }
+    let mut deps = vec![];
+    for i in 0..nrows {
+        if is_pivot[i] {
+            continue;
+        }
+        let dep: Vec<usize> = (0..nrows)
+            .filter(|&j| {
+                let h = ncols + j;
+                mat[i][h / 64] & (1 << (h % 64)) != 0
+            })
+            .collect();
+        deps.push(dep);
+    }
+    deps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_dependencies_works_0() {
+        // r0 + r1 + r3 = 0, r2 + r4 = 0
+        let rows = vec![vec![0, 1], vec![1, 2], vec![3], vec![0, 2], vec![3, 4, 4]];
+        let mut deps = find_dependencies(&rows, 5);
+        for dep in &deps {
+            let mut parity = [0; 5];
+            for &r in dep {
+                for &c in &rows[r] {
+                    parity[c] ^= 1;
+                }
+            }
+            assert_eq!(parity, [0; 5]);
+        }
+        deps.sort();
+        assert_eq!(deps, [vec![0, 1, 3], vec![2, 4]]);
+    }
+
+    #[test]
+    fn find_dependencies_works_1() {
+        // Rows are linearly independent.
+        let rows = vec![vec![0], vec![0, 1], vec![1, 2]];
+        assert!(find_dependencies(&rows, 3).is_empty());
+    }
+}
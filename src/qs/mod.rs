@@ -0,0 +1,425 @@
+use num::{BigInt, Integer, One, Signed, ToPrimitive, Zero};
+use number_theory_elementary::{kronecker_symbol_i64, primes};
+use std::collections::{HashMap, HashSet};
+
+mod gf2;
+mod poly;
+
+pub use crate::qs::gf2::find_dependencies;
+use crate::qs::poly::{choose_a, sqrt_mod, FbPrime, Poly};
+
+/// Primes smaller than this are not sieved. Their contribution is accounted for by the threshold.
+const SIEVE_START: u32 = 30;
+
+/// Bits subtracted from the sieve threshold, because logarithms are rounded and prime powers are not sieved.
+/// Candidates are cheap to check, so it is better to err on the side of too many.
+const SIEVE_SLACK: f64 = 6.0;
+
+/// The number of relations collected in addition to the size of the factor base.
+const EXTRA_RELATIONS: usize = 32;
+
+/// Large primes up to (the largest prime in the factor base) * LARGE_PRIME_MULTIPLIER are accepted.
+const LARGE_PRIME_MULTIPLIER: u64 = 64;
+
+/// Parameters of the sieve.
+#[derive(Clone, Copy, Debug)]
+struct Params {
+    /// The number of primes in the factor base.
+    fb_size: usize,
+    /// The sieve interval is [-M, M).
+    m: usize,
+}
+
+impl Params {
+    /// Parameters for n with the given number of decimal digits.
+    fn for_digits(digits: usize) -> Self {
+        // (digits, fb_size, M)
+        const TABLE: [(usize, usize, usize); 12] = [
+            (20, 100, 16384),
+            (30, 200, 32768),
+            (35, 350, 32768),
+            (40, 600, 65536),
+            (45, 1000, 65536),
+            (50, 1600, 65536),
+            (55, 2500, 98304),
+            (60, 4000, 98304),
+            (70, 7000, 131072),
+            (80, 12000, 196608),
+            (90, 18000, 262144),
+            (100, 25000, 327680),
+        ];
+        let &(_, fb_size, m) = TABLE
+            .iter()
+            .find(|&&(d, _, _)| digits <= d)
+            .unwrap_or(&TABLE[TABLE.len() - 1]);
+        Params { fb_size, m }
+    }
+}
+
+/// A relation Y^2 = (-1)^{e_0} \prod p_i^{e_i} * L^2 (mod N).
+#[derive(Clone, Debug)]
+struct Relation {
+    y: BigInt,
+    /// Pairs of (column, exponent). Column 0 stands for -1, and column i + 1 for the i-th prime in the factor base.
+    factors: Vec<(usize, u32)>,
+    /// The product of large primes. Each of them appears squared in the relation.
+    large: BigInt,
+}
+
+/// Finds a nontrivial factor of n with the self-initializing quadratic sieve (SIQS).
+///
+/// This follows \[Contini\], with the single large prime variation.
+///
+/// n should be an odd composite number that is not a perfect power and has no small factors.
+/// Returns None if the sieve fails to find a factor.
+///
+/// \[Contini\]: Contini, Scott Patrick. Factoring integers with the self-initializing quadratic sieve. 1997.
+pub fn qs(n: &BigInt, verbose: bool) -> Option<BigInt> {
+    qs_cancellable(n, verbose, &|| false)
+}
+
+/// `qs` that polls `cancelled` after each polynomial and returns None as soon as it returns true.
+pub fn qs_cancellable(n: &BigInt, verbose: bool, cancelled: &dyn Fn() -> bool) -> Option<BigInt> {
+    let digits = n.to_string().len();
+    let params = Params::for_digits(digits);
+    let k = choose_multiplier(n);
+    let kn = n * k;
+    let fb = match factor_base(n, &kn, params.fb_size) {
+        Ok(fb) => fb,
+        Err(fac) => return Some(fac),
+    };
+    let pmax = fb[fb.len() - 1].p as u64;
+    let large_bound = pmax * LARGE_PRIME_MULTIPLIER;
+    if verbose {
+        eprintln!(
+            "SIQS: {} digits, k = {}, factor base size = {}, largest prime = {}, M = {}",
+            digits,
+            k,
+            fb.len(),
+            pmax,
+            params.m
+        );
+    }
+
+    let m = params.m;
+    let log2_kn = kn.bits() as f64;
+    // log_2 of the largest value of |Q(x)|, which is about M sqrt(kN / 2)
+    let log_qmax = (m as f64).log2() + log2_kn / 2.0 - 0.5;
+    let threshold = log_qmax - (large_bound as f64).log2() - small_prime_slack(&fb) - SIEVE_SLACK;
+    let threshold = threshold.max(0.0) as u8;
+    // A should be close to sqrt(2kN) / M
+    let log_target = (log2_kn + 1.0) / 2.0 * 2.0f64.ln() - (m as f64).ln();
+
+    let ncols = fb.len() + 1;
+    let needed = ncols + EXTRA_RELATIONS;
+    let mut rng = rand::thread_rng();
+    let mut used = HashSet::new();
+    let mut relations: Vec<Relation> = vec![];
+    let mut partials: HashMap<u64, Relation> = HashMap::new();
+    let mut sieve = vec![0u8; 2 * m];
+    let mut poly_count = 0u64;
+    let mut reported = 0u64;
+    while relations.len() < needed {
+        let a_factors = choose_a(&fb, log_target, &mut used, &mut rng)?;
+        let mut poly = Poly::new(&kn, &fb, a_factors);
+        loop {
+            if cancelled() {
+                return None;
+            }
+            poly_count += 1;
+            sieve_interval(&poly, &fb, m, &mut sieve);
+            for (i, &v) in sieve.iter().enumerate() {
+                if v < threshold {
+                    continue;
+                }
+                let x = i as i64 - m as i64;
+                let (rel, rest) = match check_candidate(&poly, &fb, n, x) {
+                    Some(found) => found,
+                    None => continue,
+                };
+                if rest == 1 {
+                    relations.push(rel);
+                } else if rest < large_bound {
+                    if let Some(other) = partials.get(&rest) {
+                        relations.push(combine(&rel, other, rest, n));
+                    } else {
+                        partials.insert(rest, rel);
+                    }
+                }
+            }
+            if relations.len() >= needed || !poly.next(&kn, &fb) {
+                break;
+            }
+        }
+        if verbose && poly_count >= reported + 256 {
+            reported = poly_count;
+            eprintln!(
+                "SIQS: {} polynomials, {}/{} relations, {} partials",
+                poly_count,
+                relations.len(),
+                needed,
+                partials.len()
+            );
+        }
+    }
+    if verbose {
+        eprintln!(
+            "SIQS: {} relations collected with {} polynomials",
+            relations.len(),
+            poly_count
+        );
+    }
+
+    let rows: Vec<Vec<usize>> = relations
+        .iter()
+        .map(|rel| {
+            rel.factors
+                .iter()
+                .filter(|&&(_, e)| e % 2 == 1)
+                .map(|&(c, _)| c)
+                .collect()
+        })
+        .collect();
+    for dep in find_dependencies(&rows, ncols) {
+        if let Some(fac) = square_root(&dep, &relations, &fb, n) {
+            return Some(fac);
+        }
+    }
+    None
+}
+
+/// Chooses a multiplier k with the Knuth-Schroeppel function, so that kN has many small primes as quadratic residues.
+fn choose_multiplier(n: &BigInt) -> u32 {
+    // Squarefree odd numbers. Even multipliers make kN even, which breaks the sieve.
+    const MULTIPLIERS: [u32; 20] = [
+        1, 3, 5, 7, 11, 13, 15, 17, 19, 21, 23, 29, 31, 33, 35, 37, 39, 41, 43, 47,
+    ];
+    let small_primes = primes(300);
+    let mut best = (f64::MIN, 1);
+    for k in MULTIPLIERS {
+        let kn = n * k;
+        let mut score = -0.5 * (k as f64).ln();
+        let kn8 = (&kn % 8u32).to_u32().unwrap();
+        score += match kn8 {
+            1 => 2.0 * 2.0f64.ln(),
+            5 => 2.0f64.ln(),
+            _ => 0.5 * 2.0f64.ln(),
+        };
+        for &p in &small_primes[1..] {
+            let p = p as u32;
+            let lnp = (p as f64).ln();
+            if k % p == 0 {
+                score += lnp / p as f64;
+            } else if kronecker_symbol_i64((&kn % p).to_i64().unwrap(), p as i64) == 1 {
+                score += 2.0 * lnp / (p - 1) as f64;
+            }
+        }
+        if score > best.0 {
+            best = (score, k);
+        }
+    }
+    best.1
+}
+
+/// Collects primes p such that kN is a square mod p.
+///
+/// If some prime in the factor base divides n, returns it as Err.
+fn factor_base(n: &BigInt, kn: &BigInt, size: usize) -> Result<Vec<FbPrime>, BigInt> {
+    let mut fb = vec![FbPrime {
+        p: 2,
+        sqrt: 1,
+        logp: 1,
+    }];
+    let mut bound = size * 16 + 100;
+    loop {
+        for p in primes(bound).into_iter().skip(1) {
+            if fb.len() >= size {
+                return Ok(fb);
+            }
+            let p = p as u32;
+            if p <= fb[fb.len() - 1].p {
+                continue;
+            }
+            if (n % p).is_zero() {
+                return Err(p.into());
+            }
+            let r = (kn % p).to_u64().unwrap();
+            if r != 0 && kronecker_symbol_i64(r as i64, p as i64) != 1 {
+                continue;
+            }
+            fb.push(FbPrime {
+                p,
+                sqrt: sqrt_mod(r, p as u64) as u32,
+                logp: (p as f64).log2().round() as u8,
+            });
+        }
+        bound *= 2;
+    }
+}
+
+/// The expected contribution (in bits) of the primes that are not sieved.
+fn small_prime_slack(fb: &[FbPrime]) -> f64 {
+    let mut slack = 0.0;
+    for fp in fb {
+        if fp.p >= SIEVE_START {
+            break;
+        }
+        // Each of the two roots hits with probability 1/p.
+        slack += 2.0 * (fp.p as f64).log2() / fp.p as f64;
+    }
+    slack
+}
+
+/// Adds log_2 p to the positions x in [-M, M) such that p | Q(x).
+fn sieve_interval(poly: &Poly, fb: &[FbPrime], m: usize, sieve: &mut [u8]) {
+    sieve.fill(0);
+    let len = sieve.len();
+    for (j, fp) in fb.iter().enumerate() {
+        if fp.p < SIEVE_START || poly.a_factors.contains(&j) {
+            continue;
+        }
+        let p = fp.p as usize;
+        let (r1, r2) = poly.roots[j];
+        let offset = m % p;
+        let starts = [(r1 as usize + offset) % p, (r2 as usize + offset) % p];
+        let count = if r1 == r2 { 1 } else { 2 };
+        for &start in &starts[..count] {
+            let mut i = start;
+            while i < len {
+                sieve[i] = sieve[i].saturating_add(fp.logp);
+                i += p;
+            }
+        }
+    }
+}
+
+/// Factors A Q(x) over the factor base.
+///
+/// Returns the relation and the remaining cofactor if it fits in u64.
+fn check_candidate(poly: &Poly, fb: &[FbPrime], n: &BigInt, x: i64) -> Option<(Relation, u64)> {
+    let xb = BigInt::from(x);
+    let q: BigInt = &poly.a * &xb * &xb + &poly.b * &xb * 2 + &poly.c;
+    if q.is_zero() {
+        return None;
+    }
+    let mut factors = vec![];
+    if q.is_negative() {
+        factors.push((0, 1));
+    }
+    let mut rest = q.abs();
+    for (j, fp) in fb.iter().enumerate() {
+        let p = fp.p;
+        let in_a = poly.a_factors.contains(&j);
+        let hit = p == 2 || in_a || {
+            let xm = x.rem_euclid(p as i64) as u32;
+            let (r1, r2) = poly.roots[j];
+            xm == r1 || xm == r2
+        };
+        let mut e = u32::from(in_a);
+        if hit {
+            while (&rest % p).is_zero() {
+                rest /= p;
+                e += 1;
+            }
+        }
+        if e > 0 {
+            factors.push((j + 1, e));
+        }
+    }
+    let rest = rest.to_u64()?;
+    let y = (&poly.a * &xb + &poly.b).mod_floor(n);
+    Some((
+        Relation {
+            y,
+            factors,
+            large: BigInt::one(),
+        },
+        rest,
+    ))
+}
+
+/// Combines two partial relations with the same large prime.
+fn combine(a: &Relation, b: &Relation, large: u64, n: &BigInt) -> Relation {
+    let mut factors = a.factors.clone();
+    for &(c, e) in &b.factors {
+        match factors.iter_mut().find(|(c2, _)| *c2 == c) {
+            Some((_, e2)) => *e2 += e,
+            None => factors.push((c, e)),
+        }
+    }
+    Relation {
+        y: (&a.y * &b.y) % n,
+        factors,
+        large: BigInt::from(large),
+    }
+}
+
+/// Computes gcd(X - Y, n) from a dependency, where X^2 = Y^2 (mod n).
+fn square_root(
+    dep: &[usize],
+    relations: &[Relation],
+    fb: &[FbPrime],
+    n: &BigInt,
+) -> Option<BigInt> {
+    let mut x = BigInt::one();
+    let mut y = BigInt::one();
+    let mut exps = vec![0u32; fb.len() + 1];
+    for &r in dep {
+        let rel = &relations[r];
+        x = x * &rel.y % n;
+        y = y * &rel.large % n;
+        for &(c, e) in &rel.factors {
+            exps[c] += e;
+        }
+    }
+    for (j, fp) in fb.iter().enumerate() {
+        let e = exps[j + 1];
+        debug_assert_eq!(e % 2, 0);
+        if e > 0 {
+            y = y * BigInt::from(fp.p).modpow(&(e / 2).into(), n) % n;
+        }
+    }
+    let g = (x - y).gcd(n);
+    if g.is_one() || &g == n {
+        None
+    } else {
+        Some(g)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qs_works_0() {
+        // 1000000007 * 1000000009
+        let n = BigInt::from(1_000_000_016_000_000_063u64);
+        let fac = qs(&n, false).unwrap();
+        assert!(fac == 1_000_000_007.into() || fac == 1_000_000_009.into());
+    }
+
+    #[test]
+    fn qs_works_1() {
+        // A 30-digit semiprime: 1000000000000037 * 100000000000000003
+        let p = BigInt::from(1_000_000_000_000_037u64);
+        let q = BigInt::from(100_000_000_000_000_003u64);
+        let n = &p * &q;
+        let fac = qs(&n, false).unwrap();
+        assert!(fac == p || fac == q);
+    }
+
+    #[test]
+    fn qs_cancellable_works() {
+        let p = BigInt::from(1_000_000_000_000_037u64);
+        let q = BigInt::from(100_000_000_000_000_003u64);
+        assert_eq!(qs_cancellable(&(&p * &q), false, &|| true), None);
+    }
+
+    #[test]
+    fn choose_multiplier_works() {
+        let n = BigInt::from(1_000_000_016_000_000_063u64);
+        let k = choose_multiplier(&n);
+        assert!((1..=33).contains(&k));
+    }
+}
@@ -0,0 +1,290 @@
+use num::{BigInt, Integer, ToPrimitive, Zero};
+use rand::Rng;
+use std::collections::HashSet;
+
+/// A prime in the factor base, together with a square root of kN modulo it.
+#[derive(Clone, Debug)]
+pub struct FbPrime {
+    pub p: u32,
+    pub sqrt: u32,
+    pub logp: u8,
+}
+
+/// A polynomial Q(x) = Ax^2 + 2Bx + C with B^2 - AC = kN,
+/// so that (Ax + B)^2 - kN = A Q(x).
+///
+/// A is a product of s primes in the factor base. The 2^{s-1} values of B for the same A
+/// are visited in Gray code order, so that the roots modulo each prime are updated by one addition.
+pub struct Poly {
+    pub a: BigInt,
+    pub b: BigInt,
+    pub c: BigInt,
+    /// Indices (in the factor base) of the primes dividing A.
+    pub a_factors: Vec<usize>,
+    /// Roots of Q modulo each prime in the factor base. Meaningless for primes dividing 2A.
+    pub roots: Vec<(u32, u32)>,
+    /// B_l in Contini's thesis; B is the sum of +-B_l.
+    b_terms: Vec<BigInt>,
+    /// 2 B_l A^{-1} mod p for each l and each prime p.
+    ainv2b: Vec<Vec<u32>>,
+    /// The index of the current B in Gray code order.
+    index: u32,
+}
+
+impl Poly {
+    /// Creates the first polynomial for A = \prod_{i \in a_factors} fb\[i\].
+    pub fn new(kn: &BigInt, fb: &[FbPrime], a_factors: Vec<usize>) -> Self {
+        let mut a = BigInt::from(1);
+        for &i in &a_factors {
+            a *= fb[i].p;
+        }
+        let mut b_terms = Vec::with_capacity(a_factors.len());
+        for &i in &a_factors {
+            let q = fb[i].p as u64;
+            let a_q = &a / q;
+            let a_q_mod = (&a_q % q).to_u64().unwrap();
+            let mut gamma = fb[i].sqrt as u64 * mod_inv(a_q_mod, q) % q;
+            if gamma > q / 2 {
+                gamma = q - gamma;
+            }
+            b_terms.push(a_q * gamma);
+        }
+        let b: BigInt = b_terms.iter().sum();
+        debug_assert!(((&b * &b - kn) % &a).is_zero());
+        let c = (&b * &b - kn) / &a;
+        let mut roots = vec![(0, 0); fb.len()];
+        let mut ainv2b = vec![vec![0; fb.len()]; b_terms.len()];
+        for (j, fp) in fb.iter().enumerate() {
+            let p = fp.p as u64;
+            let a_mod = (&a % p).to_u64().unwrap();
+            if p == 2 || a_mod == 0 {
+                continue;
+            }
+            let ainv = mod_inv(a_mod, p);
+            let b_mod = b.mod_floor(&p.into()).to_u64().unwrap();
+            let t = fp.sqrt as u64;
+            let r1 = ainv * ((t + p - b_mod) % p) % p;
+            let r2 = ainv * ((2 * p - t - b_mod) % p) % p;
+            roots[j] = (r1 as u32, r2 as u32);
+            for (l, b_l) in b_terms.iter().enumerate() {
+                let b_l_mod = (b_l % p).to_u64().unwrap();
+                ainv2b[l][j] = (2 * b_l_mod % p * ainv % p) as u32;
+            }
+        }
+        Poly {
+            a,
+            b,
+            c,
+            a_factors,
+            roots,
+            b_terms,
+            ainv2b,
+            index: 0,
+        }
+    }
+
+    /// Switches to the next B for the same A. Returns false if all B's are exhausted.
+    pub fn next(&mut self, kn: &BigInt, fb: &[FbPrime]) -> bool {
+        let s = self.b_terms.len() as u32;
+        if s == 0 || self.index + 1 >= 1 << (s - 1) {
+            return false;
+        }
+        self.index += 1;
+        let i = self.index;
+        let v = i.trailing_zeros() as usize;
+        // Bit v of the Gray code flips. If it becomes 1, B_v changes its sign from + to -.
+        let to_minus = (i >> (v + 1)) & 1 == 0;
+        let delta: BigInt = &self.b_terms[v] * 2;
+        if to_minus {
+            self.b -= delta;
+        } else {
+            self.b += delta;
+        }
+        self.c = (&self.b * &self.b - kn) / &self.a;
+        for (j, fp) in fb.iter().enumerate() {
+            let p = fp.p;
+            let d = self.ainv2b[v][j];
+            let (r1, r2) = self.roots[j];
+            self.roots[j] = if to_minus {
+                ((r1 + d) % p, (r2 + d) % p)
+            } else {
+                ((r1 + p - d) % p, (r2 + p - d) % p)
+            };
+        }
+        true
+    }
+}
+
+/// Chooses the primes whose product A is close to target = sqrt(2kN) / M.
+///
+/// Primes are picked at random from the middle of the factor base, except the last one,
+/// which is chosen so that A gets as close to the target as possible.
+/// Sets of primes in `used` are avoided, and the chosen set is added to it.
+pub fn choose_a(
+    fb: &[FbPrime],
+    log_target: f64,
+    used: &mut HashSet<Vec<usize>>,
+    rng: &mut impl Rng,
+) -> Option<Vec<usize>> {
+    // Primes that can divide A: odd, not dividing k, and not too small.
+    let eligible: Vec<usize> = (0..fb.len())
+        .filter(|&j| fb[j].p >= 11 && fb[j].sqrt != 0)
+        .collect();
+    if eligible.is_empty() {
+        return None;
+    }
+    let largest = (fb[eligible[eligible.len() * 3 / 4]].p as f64).ln();
+    let s = ((log_target / largest).ceil() as usize).max(1);
+    let ideal = log_target / s as f64;
+    let mut pool: Vec<usize> = eligible
+        .iter()
+        .copied()
+        .filter(|&j| ((fb[j].p as f64).ln() - ideal).abs() <= 0.7)
+        .collect();
+    if pool.len() < s + 2 {
+        pool = eligible;
+    }
+    if pool.len() < s {
+        return None;
+    }
+    for _ in 0..100 {
+        let mut chosen: Vec<usize> = vec![];
+        let mut log_a = 0.0;
+        while chosen.len() + 1 < s {
+            let j = pool[rng.gen_range(0..pool.len())];
+            if !chosen.contains(&j) {
+                chosen.push(j);
+                log_a += (fb[j].p as f64).ln();
+            }
+        }
+        let rest = log_target - log_a;
+        let last = pool
+            .iter()
+            .copied()
+            .filter(|j| !chosen.contains(j))
+            .min_by(|&x, &y| {
+                let dx = ((fb[x].p as f64).ln() - rest).abs();
+                let dy = ((fb[y].p as f64).ln() - rest).abs();
+                dx.total_cmp(&dy)
+            })?;
+        chosen.push(last);
+        chosen.sort_unstable();
+        if used.insert(chosen.clone()) {
+            return Some(chosen);
+        }
+    }
+    None
+}
+
+/// Computes a^{-1} mod p for a prime p < 2^32.
+pub fn mod_inv(a: u64, p: u64) -> u64 {
+    mod_pow(a, p - 2, p)
+}
+
+/// Computes a^e mod p for p < 2^32.
+pub fn mod_pow(mut a: u64, mut e: u64, p: u64) -> u64 {
+    let mut prod = 1 % p;
+    a %= p;
+    while e > 0 {
+        if e % 2 == 1 {
+            prod = prod * a % p;
+        }
+        a = a * a % p;
+        e /= 2;
+    }
+    prod
+}
+
+/// Finds a square root of a modulo an odd prime p < 2^32 with the Tonelli-Shanks algorithm.
+/// a must be a quadratic residue modulo p.
+pub fn sqrt_mod(a: u64, p: u64) -> u64 {
+    let a = a % p;
+    if a == 0 {
+        return 0;
+    }
+    let mut q = p - 1;
+    let mut s = 0;
+    while q % 2 == 0 {
+        q /= 2;
+        s += 1;
+    }
+    let mut z = 2;
+    while mod_pow(z, (p - 1) / 2, p) != p - 1 {
+        z += 1;
+    }
+    let mut m = s;
+    let mut c = mod_pow(z, q, p);
+    let mut t = mod_pow(a, q, p);
+    let mut r = mod_pow(a, q.div_ceil(2), p);
+    while t != 1 {
+        let mut i = 0;
+        let mut t2 = t;
+        while t2 != 1 {
+            t2 = t2 * t2 % p;
+            i += 1;
+        }
+        let b = mod_pow(c, 1 << (m - i - 1), p);
+        m = i;
+        c = b * b % p;
+        t = t * c % p;
+        r = r * b % p;
+    }
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sqrt_mod_works() {
+        for p in [3, 5, 7, 13, 17, 41, 65537, 1_000_000_007] {
+            for a in 1..50 {
+                if mod_pow(a, (p - 1) / 2, p) != 1 {
+                    continue;
+                }
+                let r = sqrt_mod(a, p);
+                assert_eq!(r * r % p, a % p, "a = {}, p = {}", a, p);
+            }
+        }
+    }
+
+    #[test]
+    fn poly_roots_are_correct() {
+        let n = BigInt::from(1_000_000_016_000_000_063u64);
+        let primes = [2u32, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47];
+        let mut fb = vec![];
+        for p in primes {
+            let r = (&n % p).to_u64().unwrap();
+            if p != 2 && mod_pow(r, (p as u64 - 1) / 2, p as u64) != 1 {
+                continue;
+            }
+            let sqrt = if p == 2 { 1 } else { sqrt_mod(r, p as u64) };
+            fb.push(FbPrime {
+                p,
+                sqrt: sqrt as u32,
+                logp: 0,
+            });
+        }
+        let a_factors: Vec<usize> = (fb.len() - 3..fb.len()).collect();
+        let mut poly = Poly::new(&n, &fb, a_factors);
+        let mut count = 0;
+        loop {
+            count += 1;
+            for (j, fp) in fb.iter().enumerate() {
+                if fp.p == 2 || poly.a_factors.contains(&j) {
+                    continue;
+                }
+                for r in [poly.roots[j].0, poly.roots[j].1] {
+                    let x = BigInt::from(r);
+                    let q: BigInt = &poly.a * &x * &x + &poly.b * &x * 2 + &poly.c;
+                    assert!((q % fp.p).is_zero());
+                }
+            }
+            if !poly.next(&n, &fb) {
+                break;
+            }
+        }
+        assert_eq!(count, 4);
+    }
+}
@@ -0,0 +1,108 @@
+use num::{BigInt, Integer, One, Signed};
+
+/// The number of iterations after which gcds are taken in Brent's variant.
+const BATCH: u64 = 128;
+
+/// Finds a nontrivial factor of n with Pollard's rho method.
+///
+/// This function uses Brent's cycle detection and computes gcds in batches,
+/// which is Algorithm 8.5.2 in \[Cohen\].
+/// It gives up and returns None after max_iter evaluations of x -> x^2 + c in total.
+/// n should be an odd composite number.
+///
+/// \[Cohen\]: Cohen, Henri. A course in computational algebraic number theory. Vol. 138. Springer Science & Business Media, 2013.
+pub fn pollard_brent(n: &BigInt, max_iter: u64) -> Option<BigInt> {
+    if !n.bit(0) {
+        return Some(2.into());
+    }
+    let mut remaining = max_iter;
+    let mut c = BigInt::one();
+    while remaining > 0 {
+        if let Some(fac) = pollard_brent_oneshot(n, &c, &mut remaining) {
+            return Some(fac);
+        }
+        c += 1;
+    }
+    None
+}
+
+fn pollard_brent_oneshot(n: &BigInt, c: &BigInt, remaining: &mut u64) -> Option<BigInt> {
+    let f = |x: &BigInt| (x * x + c) % n;
+    let mut y = BigInt::from(2);
+    let mut ys = y.clone();
+    let mut q = BigInt::one();
+    let mut g = BigInt::one();
+    let mut r = 1u64;
+    let x = loop {
+        let x = y.clone();
+        for _ in 0..r {
+            y = f(&y);
+        }
+        let mut k = 0;
+        while k < r && g.is_one() {
+            ys = y.clone();
+            let steps = BATCH.min(r - k);
+            for _ in 0..steps {
+                y = f(&y);
+                q = (q * (&x - &y).abs()) % n;
+            }
+            g = q.gcd(n);
+            k += steps;
+        }
+        let used = 2 * r;
+        if !g.is_one() {
+            *remaining = remaining.saturating_sub(used);
+            break x;
+        }
+        if *remaining <= used {
+            *remaining = 0;
+            return None;
+        }
+        *remaining -= used;
+        r *= 2;
+    };
+    if &g == n {
+        // The batched product hit 0 mod n. Retry one step at a time.
+        loop {
+            ys = f(&ys);
+            g = (&x - &ys).abs().gcd(n);
+            if !g.is_one() {
+                break;
+            }
+        }
+    }
+    if &g == n {
+        None
+    } else {
+        Some(g)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::Zero;
+
+    #[test]
+    fn pollard_brent_works_0() {
+        let n = BigInt::from(8051);
+        let fac = pollard_brent(&n, 1000).unwrap();
+        assert_eq!(&n % &fac, BigInt::zero());
+        assert!(fac > BigInt::one() && fac < n);
+    }
+
+    #[test]
+    fn pollard_brent_works_1() {
+        // 1000003 * 998244353
+        let n = BigInt::from(1_000_003u64 * 998_244_353u64);
+        let fac = pollard_brent(&n, 1 << 20).unwrap();
+        assert!(fac == 1_000_003.into() || fac == 998_244_353.into());
+    }
+
+    #[test]
+    fn pollard_brent_gives_up() {
+        // 1000000007 * 1000000009: the smaller factor is out of reach with 100 iterations.
+        let n = BigInt::from(1_000_000_007u64 * 1_000_000_009u64);
+        assert_eq!(pollard_brent(&n, 100), None);
+    }
+}